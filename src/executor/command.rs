@@ -1,61 +1,408 @@
-use std::io::{Error, Read};
+use std::io::{Error, ErrorKind, Read};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use bytes::{Buf, BufMut, BytesMut};
 
+use crate::executor::hot_keys::HotKey;
+use crate::executor::slow_log::SlowLogEntry;
+use crate::executor::stats::StatsReport;
+use crate::memory::counter::CounterError;
 use crate::memory::key_value::KeyValue;
+use crate::memory::log::{CompareAndSwapResult, ConcatenateError};
+use crate::memory::scan::{ScanPage, ScanRequest};
+
+// key_length (u16) | value_length (u16) | command_type (u8)
+pub(crate) const COMMAND_HEADER_SIZE: usize = 5;
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub(crate) enum CommandType {
     Get = 1,
     Put = 2,
     Update = 3,
+    Snapshot = 4,
+    GetWithVersion = 5,
+    CompareAndSwap = 6,
+    Increment = 7,
+    Decrement = 8,
+    Append = 9,
+    Prepend = 10,
+    Delete = 11,
+    MultiGet = 12,
+    MultiPut = 13,
+    MultiDelete = 14,
+    Transaction = 15,
+    ShardOf = 16,
+    Scan = 17,
+    ScanKeyspace = 18,
+    Stats = 19,
+    SlowLog = 20,
+    HotKeys = 21,
+    Touch = 22,
+    GetAndTouch = 23,
 }
+#[derive(Clone, Debug)]
 pub(crate) struct Command {
     pub(crate) key: Vec<u8>,
     pub(crate) value: Option<Vec<u8>>,
+    pub(crate) flags: u32,
+    pub(crate) expires_at: Option<u64>,
     pub(crate) command_type: CommandType,
+    pub(crate) version: Option<u64>,
+    pub(crate) delta: Option<u64>,
+    pub(crate) batch: Vec<Command>,
+    pub(crate) scan: Option<Box<ScanRequest>>,
+    pub(crate) cursor: Option<u64>,
+    pub(crate) count: Option<u64>,
 }
 pub(crate) enum CommandResponse {
     Put(bool),
     Update(bool),
     Get(Option<Result<KeyValue, Error>>),
+    Snapshot(bool),
+    GetWithVersion(Option<Result<(KeyValue, u64), Error>>),
+    CompareAndSwap(Option<CompareAndSwapResult>),
+    Increment(Option<Result<i64, CounterError>>),
+    Decrement(Option<Result<i64, CounterError>>),
+    Append(Option<Result<(), ConcatenateError>>),
+    Prepend(Option<Result<(), ConcatenateError>>),
+    Delete(bool),
+    Multi(Vec<CommandResponse>),
+    Transaction(Result<Vec<CommandResponse>, TransactionError>),
+    ShardOf(usize),
+    Scan(Option<ScanPage>),
+    ScanKeyspace(Vec<Vec<u8>>, u64),
+    Stats(Box<StatsReport>),
+    SlowLog(Vec<SlowLogEntry>),
+    HotKeys(Vec<HotKey>),
+    Touch(bool),
+    GetAndTouch(Option<Result<KeyValue, Error>>),
+    OutOfMemory,
 }
 
-impl From<u8> for CommandType {
-    fn from(value: u8) -> Self {
-        match value {
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum TransactionError {
+    CrossShard,
+    Migrating,
+    UnsupportedCommand(usize),
+    Aborted(usize),
+}
+
+impl TryFrom<u8> for CommandType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        let command_type = match value {
             1 => CommandType::Get,
             2 => CommandType::Put,
             3 => CommandType::Update,
-            _ => panic!("Unknown command type")
+            4 => CommandType::Snapshot,
+            5 => CommandType::GetWithVersion,
+            6 => CommandType::CompareAndSwap,
+            7 => CommandType::Increment,
+            8 => CommandType::Decrement,
+            9 => CommandType::Append,
+            10 => CommandType::Prepend,
+            11 => CommandType::Delete,
+            12 => CommandType::MultiGet,
+            13 => CommandType::MultiPut,
+            14 => CommandType::MultiDelete,
+            15 => CommandType::Transaction,
+            16 => CommandType::ShardOf,
+            17 => CommandType::Scan,
+            18 => CommandType::ScanKeyspace,
+            19 => CommandType::Stats,
+            20 => CommandType::SlowLog,
+            21 => CommandType::HotKeys,
+            22 => CommandType::Touch,
+            23 => CommandType::GetAndTouch,
+            _ => return Err(Error::new(ErrorKind::InvalidData, format!("unknown command type {}", value))),
+        };
+        Ok(command_type)
+    }
+}
+
+impl CommandType {
+    pub(crate) const ALL: [CommandType; 23] = [
+        CommandType::Get,
+        CommandType::Put,
+        CommandType::Update,
+        CommandType::Snapshot,
+        CommandType::GetWithVersion,
+        CommandType::CompareAndSwap,
+        CommandType::Increment,
+        CommandType::Decrement,
+        CommandType::Append,
+        CommandType::Prepend,
+        CommandType::Delete,
+        CommandType::MultiGet,
+        CommandType::MultiPut,
+        CommandType::MultiDelete,
+        CommandType::Transaction,
+        CommandType::ShardOf,
+        CommandType::Scan,
+        CommandType::ScanKeyspace,
+        CommandType::Stats,
+        CommandType::SlowLog,
+        CommandType::HotKeys,
+        CommandType::Touch,
+        CommandType::GetAndTouch,
+    ];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            CommandType::Get => "get",
+            CommandType::Put => "put",
+            CommandType::Update => "update",
+            CommandType::Snapshot => "snapshot",
+            CommandType::GetWithVersion => "get_with_version",
+            CommandType::CompareAndSwap => "compare_and_swap",
+            CommandType::Increment => "increment",
+            CommandType::Decrement => "decrement",
+            CommandType::Append => "append",
+            CommandType::Prepend => "prepend",
+            CommandType::Delete => "delete",
+            CommandType::MultiGet => "multi_get",
+            CommandType::MultiPut => "multi_put",
+            CommandType::MultiDelete => "multi_delete",
+            CommandType::Transaction => "transaction",
+            CommandType::ShardOf => "shard_of",
+            CommandType::Scan => "scan",
+            CommandType::ScanKeyspace => "scan_keyspace",
+            CommandType::Stats => "stats",
+            CommandType::SlowLog => "slow_log",
+            CommandType::HotKeys => "hot_keys",
+            CommandType::Touch => "touch",
+            CommandType::GetAndTouch => "get_and_touch",
         }
     }
+
+    pub(crate) fn is_mutating(&self) -> bool {
+        match self {
+            CommandType::Get | CommandType::Snapshot | CommandType::GetWithVersion => false,
+            CommandType::Put | CommandType::Update | CommandType::CompareAndSwap => true,
+            CommandType::Increment | CommandType::Decrement => true,
+            CommandType::Append | CommandType::Prepend => true,
+            CommandType::Delete => true,
+            CommandType::MultiGet => false,
+            CommandType::MultiPut | CommandType::MultiDelete => true,
+            CommandType::Transaction => true,
+            CommandType::ShardOf | CommandType::Scan | CommandType::ScanKeyspace => false,
+            CommandType::Stats | CommandType::SlowLog | CommandType::HotKeys => false,
+            CommandType::Touch | CommandType::GetAndTouch => true,
+        }
+    }
+
+    pub(crate) fn is_multi_key(&self) -> bool {
+        matches!(self, CommandType::MultiGet | CommandType::MultiPut | CommandType::MultiDelete)
+    }
+
+    // Writes that may need more memory, deletes only ever free it and touches rewrite their record in place.
+    pub(crate) fn allocates(&self) -> bool {
+        self.is_mutating() &&
+            !matches!(self, CommandType::Delete | CommandType::MultiDelete | CommandType::Touch | CommandType::GetAndTouch)
+    }
+
+    pub(crate) fn accesses_key(&self) -> bool {
+        matches!(
+            self,
+            CommandType::Get | CommandType::Put | CommandType::Update | CommandType::GetWithVersion |
+                CommandType::CompareAndSwap | CommandType::Increment | CommandType::Decrement |
+                CommandType::Append | CommandType::Prepend | CommandType::Delete | CommandType::Touch |
+                CommandType::GetAndTouch
+        )
+    }
+
+    // Commands writing a new value store the client flags with it, the others keep the flags of the item.
+    pub(crate) fn carries_flags(&self) -> bool {
+        matches!(self, CommandType::Put | CommandType::Update | CommandType::CompareAndSwap)
+    }
+
+    pub(crate) fn carries_expiry(&self) -> bool {
+        self.carries_flags() || matches!(self, CommandType::Touch | CommandType::GetAndTouch)
+    }
+
+    pub(crate) fn has_batch(&self) -> bool {
+        self.is_multi_key() || *self == CommandType::Transaction
+    }
+
+    pub(crate) fn is_transactional(&self) -> bool {
+        !self.has_batch() &&
+            !matches!(
+                self,
+                CommandType::Snapshot | CommandType::Scan | CommandType::ScanKeyspace | CommandType::Stats |
+                    CommandType::SlowLog | CommandType::HotKeys
+            )
+    }
 }
 
 impl Command {
     pub(crate) fn get(key: Vec<u8>) -> Self {
+        Command::new(CommandType::Get, key, None)
+    }
+
+    pub(crate) fn put(key: Vec<u8>, value: Vec<u8>) -> Self {
+        Command::new(CommandType::Put, key, Some(value))
+    }
+    pub(crate) fn update(key: Vec<u8>, value: Vec<u8>) -> Self {
+        Command::new(CommandType::Update, key, Some(value))
+    }
+
+    pub(crate) fn delete(key: Vec<u8>) -> Self {
+        Command::new(CommandType::Delete, key, None)
+    }
+
+    // The file name is resolved against the snapshot directory of the executor, a shard adds its id to it.
+    pub(crate) fn snapshot(file_name: Vec<u8>) -> Self {
+        Command::new(CommandType::Snapshot, file_name, None)
+    }
+
+    pub(crate) fn get_with_version(key: Vec<u8>) -> Self {
+        Command::new(CommandType::GetWithVersion, key, None)
+    }
+
+    pub(crate) fn compare_and_swap(key: Vec<u8>, value: Vec<u8>, version: u64) -> Self {
         Command {
-            key,
-            value: None,
-            command_type: CommandType::Get,
+            version: Some(version),
+            ..Command::new(CommandType::CompareAndSwap, key, Some(value))
         }
     }
 
-    pub(crate) fn put(key: Vec<u8>, value: Vec<u8>) -> Self {
+    pub(crate) fn increment(key: Vec<u8>, delta: u64) -> Self {
         Command {
-            key,
-            value: Some(value),
-            command_type: CommandType::Put,
+            delta: Some(delta),
+            ..Command::new(CommandType::Increment, key, None)
         }
     }
-    pub(crate) fn update(key: Vec<u8>, value: Vec<u8>) -> Self {
+
+    pub(crate) fn decrement(key: Vec<u8>, delta: u64) -> Self {
+        Command {
+            delta: Some(delta),
+            ..Command::new(CommandType::Decrement, key, None)
+        }
+    }
+
+    pub(crate) fn append(key: Vec<u8>, value: Vec<u8>) -> Self {
+        Command::new(CommandType::Append, key, Some(value))
+    }
+
+    pub(crate) fn prepend(key: Vec<u8>, value: Vec<u8>) -> Self {
+        Command::new(CommandType::Prepend, key, Some(value))
+    }
+
+    pub(crate) fn multi_get(keys: Vec<Vec<u8>>) -> Self {
+        Command {
+            batch: keys.into_iter().map(Command::get).collect(),
+            ..Command::new(CommandType::MultiGet, Vec::new(), None)
+        }
+    }
+
+    pub(crate) fn multi_put(key_values: Vec<(Vec<u8>, Vec<u8>)>) -> Self {
+        Command {
+            batch: key_values.into_iter().map(|(key, value)| Command::put(key, value)).collect(),
+            ..Command::new(CommandType::MultiPut, Vec::new(), None)
+        }
+    }
+
+    pub(crate) fn multi_delete(keys: Vec<Vec<u8>>) -> Self {
+        Command {
+            batch: keys.into_iter().map(Command::delete).collect(),
+            ..Command::new(CommandType::MultiDelete, Vec::new(), None)
+        }
+    }
+
+    pub(crate) fn shard_of(key: Vec<u8>) -> Self {
+        Command::new(CommandType::ShardOf, key, None)
+    }
+
+    pub(crate) fn scan(request: ScanRequest) -> Self {
+        Command {
+            scan: Some(Box::new(request)),
+            ..Command::new(CommandType::Scan, Vec::new(), None)
+        }
+    }
+
+    pub(crate) fn scan_keyspace(cursor: u64, count: u64) -> Self {
+        Command {
+            cursor: Some(cursor),
+            count: Some(count),
+            ..Command::new(CommandType::ScanKeyspace, Vec::new(), None)
+        }
+    }
+
+    pub(crate) fn stats() -> Self {
+        Command::new(CommandType::Stats, Vec::new(), None)
+    }
+
+    pub(crate) fn slow_log(count: u64) -> Self {
+        Command {
+            count: Some(count),
+            ..Command::new(CommandType::SlowLog, Vec::new(), None)
+        }
+    }
+
+    pub(crate) fn hot_keys(count: u64) -> Self {
+        Command {
+            count: Some(count),
+            ..Command::new(CommandType::HotKeys, Vec::new(), None)
+        }
+    }
+
+    pub(crate) fn with_flags(mut self, flags: u32) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    // A Put of a key migrated from another shard keeps the version it had there. The write-ahead log only records
+    // the version of a CompareAndSwap, a replayed Put is given a new one.
+    pub(crate) fn with_version(mut self, version: u64) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    // The deadline is fixed when the command is built, replaying it from the write-ahead log does not extend it.
+    pub(crate) fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.expires_at = Some(unix_millis() + ttl.as_millis() as u64);
+        self
+    }
+
+    pub(crate) fn touch(key: Vec<u8>, ttl: Duration) -> Self {
+        Command::new(CommandType::Touch, key, None).with_ttl(ttl)
+    }
+
+    pub(crate) fn get_and_touch(key: Vec<u8>, ttl: Duration) -> Self {
+        Command::new(CommandType::GetAndTouch, key, None).with_ttl(ttl)
+    }
+
+    pub(crate) fn transaction(commands: Vec<Command>) -> Self {
+        Command {
+            batch: commands,
+            ..Command::new(CommandType::Transaction, Vec::new(), None)
+        }
+    }
+
+    fn new(command_type: CommandType, key: Vec<u8>, value: Option<Vec<u8>>) -> Self {
         Command {
             key,
-            value: Some(value),
-            command_type: CommandType::Update,
+            value,
+            flags: 0,
+            expires_at: None,
+            command_type,
+            version: None,
+            delta: None,
+            batch: Vec::new(),
+            scan: None,
+            cursor: None,
+            count: None,
         }
     }
 
+    // The key and value bytes a command carries, including those of its batch.
+    pub(crate) fn payload_bytes(&self) -> usize {
+        self.key.len() +
+            self.value.as_ref().map_or(0, |value| value.len()) +
+            self.batch.iter().map(Command::payload_bytes).sum::<usize>()
+    }
+
     pub(crate) fn encode(&self) -> BytesMut {
         let mut buffer = BytesMut::new();
         buffer.put_u16_le(self.key.len() as u16);
@@ -63,10 +410,42 @@ impl Command {
         buffer.put_u8(self.command_type as u8);
         buffer.put_slice(&self.key);
         buffer.put_slice(self.value.as_ref().map_or(&Vec::new(), |value| value));
+        if self.command_type == CommandType::CompareAndSwap {
+            buffer.put_u64_le(self.version.unwrap_or(0));
+        }
+        if self.command_type.carries_flags() {
+            buffer.put_u32_le(self.flags);
+        }
+        if self.command_type.carries_expiry() {
+            buffer.put_u64_le(self.expires_at.unwrap_or(0));
+        }
+        if let Some(delta) = self.delta {
+            buffer.put_u64_le(delta);
+        }
+        if let Some(cursor) = self.cursor {
+            buffer.put_u64_le(cursor);
+        }
+        if let Some(count) = self.count {
+            buffer.put_u64_le(count);
+        }
+        if let Some(scan) = &self.scan {
+            scan.encode_to(&mut buffer);
+        }
+        if self.command_type.has_batch() {
+            buffer.put_u16_le(self.batch.len() as u16);
+            for command in &self.batch {
+                let encoded = command.encode();
+                buffer.put_u32_le(encoded.len() as u32);
+                buffer.put_slice(&encoded);
+            }
+        }
         buffer
     }
 
     pub(crate) fn decode_from(mut buffer: BytesMut) -> Result<Self, Error> {
+        if buffer.len() < COMMAND_HEADER_SIZE {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "command shorter than its header"));
+        }
         let key_length = buffer.get_u16_le();
         let value_length = buffer.get_u16_le();
         let command_type = buffer.get_u8();
@@ -81,17 +460,131 @@ impl Command {
         value.resize(value_length as usize, 0);
         buffer_reader.read_exact(&mut value)?;
 
+        let command_type = CommandType::try_from(command_type)?;
+        let version = if command_type == CommandType::CompareAndSwap {
+            Some(read_u64(&mut buffer_reader)?)
+        } else {
+            None
+        };
+        let flags = if command_type.carries_flags() {
+            let mut flags = [0; 4];
+            buffer_reader.read_exact(&mut flags)?;
+            u32::from_le_bytes(flags)
+        } else {
+            0
+        };
+        let expires_at = if command_type.carries_expiry() {
+            Some(read_u64(&mut buffer_reader)?).filter(|expires_at| *expires_at != 0)
+        } else {
+            None
+        };
+        let delta = if matches!(command_type, CommandType::Increment | CommandType::Decrement) {
+            Some(read_u64(&mut buffer_reader)?)
+        } else {
+            None
+        };
+        let cursor = if command_type == CommandType::ScanKeyspace {
+            Some(read_u64(&mut buffer_reader)?)
+        } else {
+            None
+        };
+        let count = if matches!(command_type, CommandType::ScanKeyspace | CommandType::SlowLog | CommandType::HotKeys) {
+            Some(read_u64(&mut buffer_reader)?)
+        } else {
+            None
+        };
+        let scan = if command_type == CommandType::Scan {
+            Some(Box::new(ScanRequest::decode_from(&mut buffer_reader)?))
+        } else {
+            None
+        };
+        let mut batch = Vec::new();
+        if command_type.has_batch() {
+            let mut count = [0; 2];
+            buffer_reader.read_exact(&mut count)?;
+            for _ in 0..u16::from_le_bytes(count) {
+                let mut length = [0; 4];
+                buffer_reader.read_exact(&mut length)?;
+
+                // The length is not trusted before it is checked against the bytes that are left.
+                let length = u32::from_le_bytes(length) as usize;
+                if length > buffer_reader.get_ref().remaining() {
+                    return Err(Error::new(ErrorKind::UnexpectedEof, "batch entry longer than the command"));
+                }
+                batch.push(Command::decode_from(buffer_reader.get_mut().split_to(length))?);
+            }
+        }
         Ok(
             Command {
                 key,
                 value: if value.is_empty() { None } else { Some(value) },
-                command_type: CommandType::from(command_type),
+                flags,
+                expires_at,
+                command_type,
+                version,
+                delta,
+                batch,
+                scan,
+                cursor,
+                count,
             }
         )
     }
 }
 
+pub(crate) fn unix_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since_epoch| since_epoch.as_millis() as u64)
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64, Error> {
+    let mut buffer = [0; 8];
+    reader.read_exact(&mut buffer)?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
 impl CommandResponse {
+    pub(crate) fn rejected(command: &Command) -> Self {
+        match command.command_type {
+            CommandType::Get => CommandResponse::Get(None),
+            CommandType::Put => CommandResponse::Put(false),
+            CommandType::Update => CommandResponse::Update(false),
+            CommandType::Snapshot => CommandResponse::Snapshot(false),
+            CommandType::GetWithVersion => CommandResponse::GetWithVersion(None),
+            CommandType::CompareAndSwap => CommandResponse::CompareAndSwap(None),
+            CommandType::Increment => CommandResponse::Increment(None),
+            CommandType::Decrement => CommandResponse::Decrement(None),
+            CommandType::Append => CommandResponse::Append(None),
+            CommandType::Prepend => CommandResponse::Prepend(None),
+            CommandType::Delete => CommandResponse::Delete(false),
+            CommandType::MultiGet | CommandType::MultiPut | CommandType::MultiDelete =>
+                CommandResponse::Multi(command.batch.iter().map(CommandResponse::rejected).collect()),
+            CommandType::Transaction => CommandResponse::Transaction(Err(TransactionError::Aborted(0))),
+            CommandType::ShardOf => CommandResponse::ShardOf(0),
+            CommandType::Scan => CommandResponse::Scan(None),
+            CommandType::ScanKeyspace => CommandResponse::ScanKeyspace(Vec::new(), command.cursor.unwrap_or(0)),
+            CommandType::Stats => CommandResponse::Stats(Box::new(StatsReport::of(Vec::new()))),
+            CommandType::SlowLog => CommandResponse::SlowLog(Vec::new()),
+            CommandType::HotKeys => CommandResponse::HotKeys(Vec::new()),
+            CommandType::Touch => CommandResponse::Touch(false),
+            CommandType::GetAndTouch => CommandResponse::GetAndTouch(None),
+        }
+    }
+
+    // The keys and values a response carries, the reports of Stats, SlowLog and HotKeys are bounded and left out.
+    pub(crate) fn payload_bytes(&self) -> usize {
+        let key_value_bytes = |key_value: &KeyValue| key_value.key().len() + key_value.value().len();
+        match self {
+            CommandResponse::Get(Some(Ok(key_value))) | CommandResponse::GetAndTouch(Some(Ok(key_value))) =>
+                key_value_bytes(key_value),
+            CommandResponse::GetWithVersion(Some(Ok((key_value, _)))) => key_value_bytes(key_value),
+            CommandResponse::Multi(responses) | CommandResponse::Transaction(Ok(responses)) =>
+                responses.iter().map(CommandResponse::payload_bytes).sum(),
+            CommandResponse::Scan(Some(page)) => page.keys.iter().map(Vec::len).sum(),
+            CommandResponse::ScanKeyspace(keys, _) => keys.iter().map(Vec::len).sum(),
+            _ => 0,
+        }
+    }
+
     pub(crate) fn is_put_response(&self) -> bool {
         if let CommandResponse::Put(_) = self {
             return true;
@@ -120,6 +613,147 @@ impl CommandResponse {
         false
     }
 
+    pub(crate) fn is_snapshot_response(&self) -> bool {
+        if let CommandResponse::Snapshot(_) = self {
+            return true;
+        }
+        false
+    }
+
+    pub(crate) fn snapshot_response(&self) -> bool {
+        if let CommandResponse::Snapshot(response) = self {
+            return *response;
+        }
+        false
+    }
+
+    pub(crate) fn get_with_version_response(self) -> Option<Result<(KeyValue, u64), Error>> {
+        if let CommandResponse::GetWithVersion(response) = self {
+            return response;
+        }
+        None
+    }
+
+    pub(crate) fn compare_and_swap_response(&self) -> Option<CompareAndSwapResult> {
+        if let CommandResponse::CompareAndSwap(response) = self {
+            return *response;
+        }
+        None
+    }
+
+    pub(crate) fn counter_response(&self) -> Option<Result<i64, CounterError>> {
+        match self {
+            CommandResponse::Increment(response) | CommandResponse::Decrement(response) => *response,
+            _ => None,
+        }
+    }
+
+    pub(crate) fn concatenate_response(&self) -> Option<Result<(), ConcatenateError>> {
+        match self {
+            CommandResponse::Append(response) | CommandResponse::Prepend(response) => *response,
+            _ => None,
+        }
+    }
+
+    pub(crate) fn delete_response(&self) -> bool {
+        if let CommandResponse::Delete(response) = self {
+            return *response;
+        }
+        false
+    }
+
+    pub(crate) fn multi_response(self) -> Vec<CommandResponse> {
+        if let CommandResponse::Multi(responses) = self {
+            return responses;
+        }
+        Vec::new()
+    }
+
+    pub(crate) fn transaction_response(self) -> Result<Vec<CommandResponse>, TransactionError> {
+        if let CommandResponse::Transaction(response) = self {
+            return response;
+        }
+        Err(TransactionError::Aborted(0))
+    }
+
+    pub(crate) fn shard_of_response(&self) -> Option<usize> {
+        if let CommandResponse::ShardOf(shard) = self {
+            return Some(*shard);
+        }
+        None
+    }
+
+    pub(crate) fn scan_response(self) -> Option<ScanPage> {
+        if let CommandResponse::Scan(response) = self {
+            return response;
+        }
+        None
+    }
+
+    pub(crate) fn scan_keyspace_response(self) -> Option<(Vec<Vec<u8>>, u64)> {
+        if let CommandResponse::ScanKeyspace(keys, cursor) = self {
+            return Some((keys, cursor));
+        }
+        None
+    }
+
+    pub(crate) fn stats_response(self) -> Option<StatsReport> {
+        if let CommandResponse::Stats(report) = self {
+            return Some(*report);
+        }
+        None
+    }
+
+    pub(crate) fn slow_log_response(self) -> Option<Vec<SlowLogEntry>> {
+        if let CommandResponse::SlowLog(entries) = self {
+            return Some(entries);
+        }
+        None
+    }
+
+    pub(crate) fn hot_keys_response(self) -> Option<Vec<HotKey>> {
+        if let CommandResponse::HotKeys(hot_keys) = self {
+            return Some(hot_keys);
+        }
+        None
+    }
+
+    pub(crate) fn touch_response(&self) -> bool {
+        if let CommandResponse::Touch(response) = self {
+            return *response;
+        }
+        false
+    }
+
+    pub(crate) fn get_and_touch_response(self) -> Option<Result<KeyValue, Error>> {
+        if let CommandResponse::GetAndTouch(response) = self {
+            return response;
+        }
+        None
+    }
+
+    pub(crate) fn is_out_of_memory(&self) -> bool {
+        matches!(self, CommandResponse::OutOfMemory)
+    }
+
+    pub(crate) fn succeeded(&self) -> bool {
+        match self {
+            CommandResponse::Put(response) | CommandResponse::Update(response) => *response,
+            CommandResponse::Snapshot(response) | CommandResponse::Delete(response) => *response,
+            CommandResponse::Touch(response) => *response,
+            CommandResponse::Get(_) | CommandResponse::GetWithVersion(_) | CommandResponse::GetAndTouch(_) => true,
+            CommandResponse::CompareAndSwap(response) => matches!(response, Some(CompareAndSwapResult::Swapped(_))),
+            CommandResponse::Increment(response) | CommandResponse::Decrement(response) =>
+                matches!(response, Some(Ok(_))),
+            CommandResponse::Append(response) | CommandResponse::Prepend(response) => matches!(response, Some(Ok(_))),
+            CommandResponse::Multi(responses) => responses.iter().all(CommandResponse::succeeded),
+            CommandResponse::Transaction(response) => response.is_ok(),
+            CommandResponse::ShardOf(_) | CommandResponse::Scan(_) | CommandResponse::ScanKeyspace(..) => true,
+            CommandResponse::Stats(_) | CommandResponse::SlowLog(_) | CommandResponse::HotKeys(_) => true,
+            CommandResponse::OutOfMemory => false,
+        }
+    }
+
     pub(crate) fn is_get_response(&self) -> bool {
         if let CommandResponse::Get(_) = self {
             return true;
@@ -137,7 +771,12 @@ impl CommandResponse {
 
 #[cfg(test)]
 mod tests {
-    use crate::executor::command::{Command, CommandType};
+    use std::time::Duration;
+
+    use bytes::BytesMut;
+
+    use crate::executor::command::{unix_millis, Command, CommandType};
+    use crate::memory::scan::ScanRequest;
 
     #[test]
     fn encodes_and_decodes_a_get_command() {
@@ -167,6 +806,43 @@ mod tests {
         assert_eq!(Vec::from(b"consensus"), decoded.value.unwrap());
     }
 
+    #[test]
+    fn encodes_and_decodes_the_client_flags_of_a_command() {
+        let put = Command::put(Vec::from(b"raft"), Vec::from(b"{}")).with_flags(7);
+        let decoded = Command::decode_from(put.encode()).unwrap();
+        assert_eq!(7, decoded.flags);
+        assert_eq!(Vec::from(b"{}"), decoded.value.unwrap());
+
+        let compare_and_swap = Command::compare_and_swap(Vec::from(b"raft"), Vec::from(b"{}"), 3).with_flags(u32::MAX);
+        let decoded = Command::decode_from(compare_and_swap.encode()).unwrap();
+        assert_eq!(u32::MAX, decoded.flags);
+        assert_eq!(Some(3), decoded.version);
+
+        let multi_put = Command {
+            batch: vec![Command::put(Vec::from(b"raft"), Vec::from(b"{}")).with_flags(9)],
+            ..Command::multi_put(Vec::new())
+        };
+        let decoded = Command::decode_from(multi_put.encode()).unwrap();
+        assert_eq!(9, decoded.batch[0].flags);
+    }
+
+    #[test]
+    fn encodes_and_decodes_the_expiry_of_a_command() {
+        let touch = Command::touch(Vec::from(b"session"), Duration::from_secs(60));
+        let decoded = Command::decode_from(touch.encode()).unwrap();
+        assert_eq!(CommandType::Touch, decoded.command_type);
+        assert_eq!(touch.expires_at, decoded.expires_at);
+        assert_eq!(true, decoded.expires_at.unwrap() >= unix_millis() + 59_000);
+
+        let get_and_touch = Command::get_and_touch(Vec::from(b"session"), Duration::from_secs(60));
+        let decoded = Command::decode_from(get_and_touch.encode()).unwrap();
+        assert_eq!(CommandType::GetAndTouch, decoded.command_type);
+        assert_eq!(get_and_touch.expires_at, decoded.expires_at);
+
+        let decoded = Command::decode_from(Command::put(Vec::from(b"session"), Vec::from(b"alice")).encode()).unwrap();
+        assert_eq!(None, decoded.expires_at);
+    }
+
     #[test]
     fn encodes_and_decodes_an_update_command() {
         let update = Command::update(Vec::from(b"raft"), Vec::from(b"consensus"));
@@ -180,4 +856,173 @@ mod tests {
         assert_eq!(Vec::from(b"raft"), decoded.key);
         assert_eq!(Vec::from(b"consensus"), decoded.value.unwrap());
     }
+
+    #[test]
+    fn encodes_and_decodes_a_snapshot_command() {
+        let snapshot = Command::snapshot(Vec::from(b"shard-0.snap"));
+        let encoded = snapshot.encode();
+
+        let decoded = Command::decode_from(encoded).unwrap();
+        assert_eq!(CommandType::Snapshot, decoded.command_type);
+        assert_eq!(Vec::from(b"shard-0.snap"), decoded.key);
+    }
+
+    #[test]
+    fn encodes_and_decodes_a_compare_and_swap_command() {
+        let compare_and_swap = Command::compare_and_swap(Vec::from(b"raft"), Vec::from(b"consensus"), 42);
+        let encoded = compare_and_swap.encode();
+
+        let decoded = Command::decode_from(encoded).unwrap();
+        assert_eq!(CommandType::CompareAndSwap, decoded.command_type);
+        assert_eq!(Vec::from(b"raft"), decoded.key);
+        assert_eq!(Vec::from(b"consensus"), decoded.value.unwrap());
+        assert_eq!(Some(42), decoded.version);
+    }
+
+    #[test]
+    fn encodes_and_decodes_an_increment_command() {
+        let increment = Command::increment(Vec::from(b"hits"), 5);
+        let encoded = increment.encode();
+
+        let decoded = Command::decode_from(encoded).unwrap();
+        assert_eq!(CommandType::Increment, decoded.command_type);
+        assert_eq!(Vec::from(b"hits"), decoded.key);
+        assert_eq!(Some(5), decoded.delta);
+    }
+
+    #[test]
+    fn encodes_and_decodes_a_decrement_command() {
+        let decrement = Command::decrement(Vec::from(b"hits"), 1);
+        let encoded = decrement.encode();
+
+        let decoded = Command::decode_from(encoded).unwrap();
+        assert_eq!(CommandType::Decrement, decoded.command_type);
+        assert_eq!(Some(1), decoded.delta);
+    }
+
+    #[test]
+    fn encodes_and_decodes_append_and_prepend_commands() {
+        let decoded = Command::decode_from(Command::append(Vec::from(b"events"), Vec::from(b"c")).encode()).unwrap();
+        assert_eq!(CommandType::Append, decoded.command_type);
+        assert_eq!(Vec::from(b"c"), decoded.value.unwrap());
+
+        let decoded = Command::decode_from(Command::prepend(Vec::from(b"events"), Vec::from(b"a")).encode()).unwrap();
+        assert_eq!(CommandType::Prepend, decoded.command_type);
+        assert_eq!(Vec::from(b"a"), decoded.value.unwrap());
+    }
+
+    #[test]
+    fn encodes_and_decodes_a_delete_command() {
+        let decoded = Command::decode_from(Command::delete(Vec::from(b"raft")).encode()).unwrap();
+        assert_eq!(CommandType::Delete, decoded.command_type);
+        assert_eq!(Vec::from(b"raft"), decoded.key);
+    }
+
+    #[test]
+    fn encodes_and_decodes_a_multi_put_command() {
+        let multi_put = Command::multi_put(vec![
+            (Vec::from(b"raft"), Vec::from(b"consensus")),
+            (Vec::from(b"paxos"), Vec::from(b"consensus")),
+        ]);
+        let decoded = Command::decode_from(multi_put.encode()).unwrap();
+
+        assert_eq!(CommandType::MultiPut, decoded.command_type);
+        assert_eq!(2, decoded.batch.len());
+        assert_eq!(CommandType::Put, decoded.batch[1].command_type);
+        assert_eq!(Vec::from(b"paxos"), decoded.batch[1].key);
+        assert_eq!(Vec::from(b"consensus"), decoded.batch[1].value.clone().unwrap());
+    }
+
+    #[test]
+    fn encodes_and_decodes_a_multi_get_command() {
+        let multi_get = Command::multi_get(vec![Vec::from(b"raft"), Vec::from(b"paxos")]);
+        let decoded = Command::decode_from(multi_get.encode()).unwrap();
+
+        assert_eq!(CommandType::MultiGet, decoded.command_type);
+        assert_eq!(CommandType::Get, decoded.batch[0].command_type);
+        assert_eq!(Vec::from(b"raft"), decoded.batch[0].key);
+    }
+
+    #[test]
+    fn encodes_and_decodes_a_transaction_command() {
+        let transaction = Command::transaction(vec![
+            Command::put(Vec::from(b"raft"), Vec::from(b"consensus")),
+            Command::increment(Vec::from(b"term"), 1),
+        ]);
+        let decoded = Command::decode_from(transaction.encode()).unwrap();
+
+        assert_eq!(CommandType::Transaction, decoded.command_type);
+        assert_eq!(CommandType::Increment, decoded.batch[1].command_type);
+        assert_eq!(Some(1), decoded.batch[1].delta);
+    }
+
+    #[test]
+    fn encodes_and_decodes_a_shard_of_command() {
+        let decoded = Command::decode_from(Command::shard_of(Vec::from(b"{user:42}:cart")).encode()).unwrap();
+        assert_eq!(CommandType::ShardOf, decoded.command_type);
+        assert_eq!(Vec::from(b"{user:42}:cart"), decoded.key);
+    }
+
+    #[test]
+    fn encodes_and_decodes_a_scan_command() {
+        let request = ScanRequest::new(10).with_prefix(Vec::from(b"session:")).with_cursor(Some(Vec::from(b"session:3")));
+        let decoded = Command::decode_from(Command::scan(request.clone()).encode()).unwrap();
+
+        assert_eq!(CommandType::Scan, decoded.command_type);
+        assert_eq!(Some(request), decoded.scan.map(|scan| *scan));
+    }
+
+    #[test]
+    fn encodes_and_decodes_a_scan_keyspace_command() {
+        let decoded = Command::decode_from(Command::scan_keyspace(42, 10).encode()).unwrap();
+
+        assert_eq!(CommandType::ScanKeyspace, decoded.command_type);
+        assert_eq!(Some(42), decoded.cursor);
+        assert_eq!(Some(10), decoded.count);
+    }
+
+    #[test]
+    fn encodes_and_decodes_a_slow_log_command() {
+        let decoded = Command::decode_from(Command::slow_log(10).encode()).unwrap();
+
+        assert_eq!(CommandType::SlowLog, decoded.command_type);
+        assert_eq!(None, decoded.cursor);
+        assert_eq!(Some(10), decoded.count);
+    }
+
+    #[test]
+    fn encodes_and_decodes_a_hot_keys_command() {
+        let decoded = Command::decode_from(Command::hot_keys(5).encode()).unwrap();
+
+        assert_eq!(CommandType::HotKeys, decoded.command_type);
+        assert_eq!(Some(5), decoded.count);
+    }
+
+    #[test]
+    fn lists_every_command_type_in_order() {
+        for (index, command_type) in CommandType::ALL.iter().enumerate() {
+            assert_eq!(*command_type, CommandType::try_from(index as u8 + 1).unwrap());
+        }
+    }
+
+    #[test]
+    fn rejects_a_command_shorter_than_its_header() {
+        let decoded = Command::decode_from(BytesMut::from(&[0u8, 0, 0][..]));
+        assert_eq!(true, decoded.is_err());
+    }
+
+    #[test]
+    fn rejects_a_batch_entry_longer_than_the_command() {
+        let mut encoded = Command::multi_get(vec![Vec::from(b"raft")]).encode();
+        let entry_length_at = encoded.len() - Command::get(Vec::from(b"raft")).encode().len() - 4;
+        encoded[entry_length_at..entry_length_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert_eq!(true, Command::decode_from(encoded).is_err());
+    }
+
+    #[test]
+    fn rejects_an_unknown_command_type() {
+        let decoded = Command::decode_from(BytesMut::from(&[0u8, 0, 0, 0, 0][..]));
+        assert_eq!(true, decoded.is_err());
+    }
 }
\ No newline at end of file
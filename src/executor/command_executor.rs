@@ -1,36 +1,468 @@
-use crate::executor::command::{Command, CommandResponse, CommandType};
-use crate::memory::key_value::KeyValue;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::executor::command::{unix_millis, Command, CommandResponse, CommandType, TransactionError};
+use crate::executor::hot_keys::HotKeys;
+use crate::executor::slow_log::{CommandSummary, SlowLog};
+use crate::executor::stats::{Stats, StatsReport};
+use crate::executor::wal::{FsyncPolicy, WriteAheadLog};
+use crate::memory::budget::{EvictionPolicy, MemoryBudget};
+use crate::memory::counter::CounterError;
+use crate::memory::key_value::{KeyValue, KeyValueRef};
 use crate::memory::log::Log;
+use crate::memory::scan::keyspace_cursor_position;
+use crate::memory::snapshot::{Snapshot, SnapshotProgress};
+
+const DEFAULT_SLOW_LOG_THRESHOLD: Duration = Duration::from_millis(10);
+const DEFAULT_SLOW_LOG_CAPACITY: usize = 128;
+const DEFAULT_HOT_KEYS_CAPACITY: usize = 64;
+// Keys sampled per eviction, the segment of the least accessed of them is freed.
+const EVICTION_SAMPLE_SIZE: usize = 8;
+const MAX_EVICTIONS_PER_COMMAND: usize = 16;
 
 pub(crate) struct CommandExecutor {
     log: Log,
+    write_ahead_log: Option<WriteAheadLog>,
+    snapshot: Option<Snapshot>,
+    snapshot_progress: Option<SnapshotProgress>,
+    snapshot_directory: Option<PathBuf>,
+    stats: Stats,
+    slow_log: SlowLog,
+    hot_keys: HotKeys,
+    memory_budget: Option<MemoryBudget>,
+    buffer_bytes: usize,
+    eviction_cursor: u64,
+    evicted_keys: Vec<Vec<u8>>,
 }
 
 impl CommandExecutor {
     pub(crate) fn new(log: Log) -> Self {
         CommandExecutor {
-            log
+            log,
+            write_ahead_log: None,
+            snapshot: None,
+            snapshot_progress: None,
+            snapshot_directory: None,
+            stats: Stats::default(),
+            slow_log: SlowLog::new(DEFAULT_SLOW_LOG_THRESHOLD, DEFAULT_SLOW_LOG_CAPACITY),
+            hot_keys: HotKeys::new(DEFAULT_HOT_KEYS_CAPACITY),
+            memory_budget: None,
+            buffer_bytes: 0,
+            eviction_cursor: 0,
+            evicted_keys: Vec::new(),
         }
     }
 
+    pub(crate) fn with_memory_budget(mut self, memory_budget: MemoryBudget) -> Self {
+        self.memory_budget = Some(memory_budget);
+        self
+    }
+
+    // Memory held outside the log on behalf of this executor, such as queued messages, counts against its budget.
+    pub(crate) fn set_buffer_bytes(&mut self, buffer_bytes: usize) {
+        self.buffer_bytes = buffer_bytes;
+    }
+
+    pub(crate) fn has_memory_budget(&self) -> bool {
+        self.memory_budget.is_some()
+    }
+
+    pub(crate) fn memory_used(&self) -> usize {
+        self.log.memory_usage() + self.buffer_bytes
+    }
+
+    // Keys evicted to admit the last executed command.
+    pub(crate) fn evicted_keys(&self) -> &[Vec<u8>] {
+        &self.evicted_keys
+    }
+
+    // Snapshots are refused until a directory is configured.
+    pub(crate) fn with_snapshot_directory(mut self, snapshot_directory: PathBuf) -> Self {
+        self.snapshot_directory = Some(snapshot_directory);
+        self
+    }
+
+    pub(crate) fn with_slow_log(mut self, threshold: Duration, capacity: usize) -> Self {
+        self.slow_log = SlowLog::new(threshold, capacity);
+        self
+    }
+
+    pub(crate) fn with_hot_keys(mut self, capacity: usize) -> Self {
+        self.hot_keys = HotKeys::new(capacity);
+        self
+    }
+
+    // The write-ahead log is replayed into an empty log from the start, and into a log restored from a snapshot
+    // from the position the snapshot recorded. Any other log holds writes that replaying would apply twice.
+    pub(crate) fn with_write_ahead_log(log: Log, path: &Path, fsync_policy: FsyncPolicy) -> Result<Self, Error> {
+        let position = match log.write_ahead_log_position() {
+            Some(position) => position,
+            None if log.index_size() == 0 => 0,
+            None => return Err(Error::new(ErrorKind::InvalidInput, "log holds writes missing from the write-ahead log")),
+        };
+        let mut write_ahead_log = WriteAheadLog::open(path, fsync_policy)?;
+        let mut executor = CommandExecutor::new(log);
+        for command in write_ahead_log.replay(position)? {
+            executor.apply(command);
+        }
+        // Replayed commands were counted when they were first executed.
+        executor.stats = Stats::default();
+        executor.hot_keys = HotKeys::new(DEFAULT_HOT_KEYS_CAPACITY);
+        executor.log.take_evicted_keys();
+        executor.write_ahead_log = Some(write_ahead_log);
+        Ok(executor)
+    }
+
     pub(crate) fn execute(&mut self, command: Command) -> CommandResponse {
+        let started_at = Instant::now();
+        let summary = CommandSummary::of(&command);
+        let command_response = self.execute_timed(command);
+        self.slow_log.record(summary, started_at.elapsed());
+        command_response
+    }
+
+    fn execute_timed(&mut self, command: Command) -> CommandResponse {
+        let admitted = !command.command_type.allocates() || self.admit();
+        self.evicted_keys = self.log.take_evicted_keys();
+        if !admitted {
+            self.stats.out_of_memory += 1;
+            return CommandResponse::OutOfMemory;
+        }
+        if command.command_type.is_mutating() {
+            if let Some(write_ahead_log) = self.write_ahead_log.as_mut() {
+                if write_ahead_log.append(&command).is_err() {
+                    return CommandResponse::rejected(&command);
+                }
+            }
+        }
+        let command_response = self.apply(command);
+        self.journal_log_evictions();
+        self.advance_snapshot(1);
+        command_response
+    }
+
+    // A segment moving to another size class takes its items with it and expired items are deleted on access,
+    // the write-ahead log must not bring them back.
+    fn journal_log_evictions(&mut self) {
+        for key in self.log.take_evicted_keys() {
+            if self.log.try_get_ref(&key).is_none() {
+                if let Some(write_ahead_log) = self.write_ahead_log.as_mut() {
+                    let _ = write_ahead_log.append(&Command::delete(key.clone()));
+                }
+            }
+            self.evicted_keys.push(key);
+        }
+    }
+
+    pub(crate) fn sync_write_ahead_log(&mut self) -> Result<(), Error> {
+        match self.write_ahead_log.as_mut() {
+            Some(write_ahead_log) => write_ahead_log.sync_if_due(),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn snapshot_progress(&self) -> Option<SnapshotProgress> {
+        self.snapshot_progress
+    }
+
+    pub(crate) fn stats(&self) -> Stats {
+        let segment_bytes = self.log.segment_bytes();
+        let progress = self.snapshot_progress;
+        Stats {
+            rejected_appends: self.log.rejected_appends(),
+            evictions: self.log.evictions(),
+            expirations: self.log.expirations(),
+            compressed_values: self.log.compressed_values(),
+            uncompressed_value_bytes: self.log.uncompressed_value_bytes(),
+            compressed_value_bytes: self.log.compressed_value_bytes(),
+            index_size: self.log.index_size(),
+            bytes_used: segment_bytes.iter().sum(),
+            memory_used: self.memory_used(),
+            segment_bytes,
+            snapshot_segments_written: progress.map_or(0, |progress| progress.segments_written),
+            snapshot_segments_total: progress.map_or(0, |progress| progress.segments_total),
+            snapshot_bytes_written: progress.map_or(0, |progress| progress.bytes_written),
+            snapshot_bytes_total: progress.map_or(0, |progress| progress.bytes_total),
+            ..self.stats.clone()
+        }
+    }
+
+    pub(crate) fn hot_keys(&self) -> &HotKeys {
+        &self.hot_keys
+    }
+
+    fn admit(&mut self) -> bool {
+        let Some(memory_budget) = self.memory_budget else {
+            return true;
+        };
+        if !memory_budget.is_exceeded_by(self.memory_used()) {
+            return true;
+        }
+        if memory_budget.policy() == EvictionPolicy::Reject {
+            return false;
+        }
+        for _ in 0..MAX_EVICTIONS_PER_COMMAND {
+            if !self.evict_least_accessed() || !memory_budget.is_exceeded_by(self.memory_used()) {
+                break;
+            }
+        }
+        !memory_budget.is_exceeded_by(self.memory_used())
+    }
+
+    // Samples keys from a cursor walking the index, so every key is eventually considered, and frees the segment of
+    // the one the hot key tracker has seen the least. Keys the tracker does not follow tie at no accesses and the
+    // oldest write goes first. The keys of that segment accessed more often than the victim are written again, the
+    // others are evicted with it.
+    fn evict_least_accessed(&mut self) -> bool {
+        let (keys, cursor) = self.log.scan_keyspace(self.eviction_cursor, EVICTION_SAMPLE_SIZE);
+        self.eviction_cursor = cursor;
+        let Some(victim) = keys
+            .into_iter()
+            .min_by_key(|key| (self.hot_keys.accesses_of(key), self.log.version_of(key)))
+        else {
+            return false;
+        };
+        let Some(keys) = self.log.keys_evicted_with(&victim) else {
+            return false;
+        };
+        let accesses = self.hot_keys.accesses_of(&victim);
+        let (retained, evicted): (Vec<Vec<u8>>, Vec<Vec<u8>>) =
+            keys.into_iter().partition(|key| self.hot_keys.accesses_of(key) > accesses);
+        if let Some(write_ahead_log) = self.write_ahead_log.as_mut() {
+            for key in evicted {
+                if write_ahead_log.append(&Command::delete(key)).is_err() {
+                    return false;
+                }
+            }
+        }
+        self.log.try_evict(&victim, &retained)
+    }
+
+    fn apply(&mut self, command: Command) -> CommandResponse {
+        if command.command_type.accesses_key() {
+            self.hot_keys.record(&command.key);
+            self.log.try_expire(&command.key, unix_millis());
+        }
         match command.command_type {
-            CommandType::Get =>
-                CommandResponse::Get(self.log.try_get(&command.key)),
-            CommandType::Put =>
-                CommandResponse::Put(self.log.try_append(KeyValue::new(command.key, command.value.unwrap()))),
-            CommandType::Update =>
-                CommandResponse::Update(self.log.try_append(KeyValue::new(command.key, command.value.unwrap()))),
+            CommandType::Get => {
+                let key_value = self.log.try_get(&command.key);
+                self.record_get(key_value.is_some());
+                CommandResponse::Get(key_value)
+            }
+            CommandType::Put => {
+                self.stats.puts += 1;
+                let (expires_at, version) = (command.expires_at, command.version);
+                let key_value = Self::key_value_of(command);
+                CommandResponse::Put(match version {
+                    Some(version) => self.log.try_append_migrated(key_value.with_version(version), expires_at),
+                    None => self.log.try_append_expiring(key_value, expires_at),
+                })
+            }
+            CommandType::Update => {
+                self.stats.updates += 1;
+                let expires_at = command.expires_at;
+                CommandResponse::Update(self.log.try_append_expiring(Self::key_value_of(command), expires_at))
+            }
+            CommandType::Snapshot =>
+                CommandResponse::Snapshot(self.begin_snapshot(&command.key)),
+            CommandType::GetWithVersion => {
+                let key_value = self.get_with_version(&command.key);
+                self.record_get(key_value.is_some());
+                CommandResponse::GetWithVersion(key_value)
+            }
+            CommandType::CompareAndSwap => {
+                let (expected_version, expires_at) = (command.version.unwrap(), command.expires_at);
+                let key_value = Self::key_value_of(command);
+                let swapped = self.log.try_compare_and_swap(key_value, expected_version, expires_at);
+                CommandResponse::CompareAndSwap(Some(swapped))
+            }
+            CommandType::Increment =>
+                CommandResponse::Increment(Some(self.increment(&command.key, command.delta.unwrap(), false))),
+            CommandType::Decrement =>
+                CommandResponse::Decrement(Some(self.increment(&command.key, command.delta.unwrap(), true))),
+            CommandType::Append =>
+                CommandResponse::Append(Some(self.log.try_append_to_value(&command.key, &command.value.unwrap()))),
+            CommandType::Prepend =>
+                CommandResponse::Prepend(Some(self.log.try_prepend_to_value(&command.key, &command.value.unwrap()))),
+            CommandType::Delete =>
+                CommandResponse::Delete(self.log.try_delete(&command.key)),
+            CommandType::MultiGet | CommandType::MultiPut | CommandType::MultiDelete =>
+                CommandResponse::Multi(command.batch.into_iter().map(|command| self.apply(command)).collect()),
+            CommandType::Transaction =>
+                CommandResponse::Transaction(self.transaction(command.batch)),
+            // A standalone executor owns every key, shards answer this before it reaches the executor.
+            CommandType::ShardOf =>
+                CommandResponse::ShardOf(0),
+            CommandType::Scan =>
+                CommandResponse::Scan(command.scan.and_then(|request| self.log.scan(&request))),
+            CommandType::ScanKeyspace => {
+                let cursor = keyspace_cursor_position(command.cursor.unwrap_or(0));
+                let (keys, cursor) = self.log.scan_keyspace(cursor, command.count.unwrap_or(0) as usize);
+                CommandResponse::ScanKeyspace(keys, cursor)
+            }
+            CommandType::Stats =>
+                CommandResponse::Stats(Box::new(StatsReport::of(vec![self.stats()]))),
+            CommandType::SlowLog =>
+                CommandResponse::SlowLog(self.slow_log.latest(command.count.unwrap_or(0) as usize)),
+            CommandType::HotKeys =>
+                CommandResponse::HotKeys(self.hot_keys.top(command.count.unwrap_or(0) as usize)),
+            CommandType::Touch =>
+                CommandResponse::Touch(self.log.try_touch(&command.key, command.expires_at)),
+            CommandType::GetAndTouch => {
+                let touched = self.log.try_touch(&command.key, command.expires_at);
+                let key_value = if touched { self.log.try_get(&command.key) } else { None };
+                self.record_get(key_value.is_some());
+                CommandResponse::GetAndTouch(key_value)
+            }
+        }
+    }
+
+    fn transaction(&mut self, commands: Vec<Command>) -> Result<Vec<CommandResponse>, TransactionError> {
+        if let Some(step) = commands.iter().position(|command| !command.command_type.is_transactional()) {
+            return Err(TransactionError::UnsupportedCommand(step));
+        }
+        let checkpoint = self.log.begin_transaction();
+        let mut responses = Vec::with_capacity(commands.len());
+        for (step, command) in commands.into_iter().enumerate() {
+            let response = self.apply(command);
+            if !response.succeeded() {
+                self.log.rollback_transaction(checkpoint);
+                return Err(TransactionError::Aborted(step));
+            }
+            responses.push(response);
+        }
+        self.log.commit_transaction();
+        Ok(responses)
+    }
+
+    fn record_get(&mut self, hit: bool) {
+        self.stats.gets += 1;
+        if hit {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+        }
+    }
+
+    fn increment(&mut self, key: &[u8], delta: u64, negate: bool) -> Result<i64, CounterError> {
+        let delta = i64::try_from(delta).map_err(|_| CounterError::Overflow)?;
+        self.log.try_increment(key, if negate { -delta } else { delta })
+    }
+
+    fn key_value_of(command: Command) -> KeyValue {
+        KeyValue::new(command.key, command.value.unwrap()).with_flags(command.flags)
+    }
+
+    fn get_with_version(&self, key: &[u8]) -> Option<Result<(KeyValue, u64), Error>> {
+        let version = self.log.version_of(key)?;
+        self.log.try_get(key).map(|key_value| key_value.map(|key_value| (key_value, version)))
+    }
+
+    fn begin_snapshot(&mut self, file_name: &[u8]) -> bool {
+        if self.snapshot.is_some() {
+            return false;
+        }
+        let Some(path) = self.snapshot_path(file_name) else {
+            return false;
+        };
+        let write_ahead_log_position = self.write_ahead_log.as_ref().map(WriteAheadLog::position);
+        match Snapshot::begin(&self.log, &path, write_ahead_log_position) {
+            Ok(snapshot) => {
+                self.snapshot_progress = Some(snapshot.progress());
+                if !snapshot.progress().is_complete() {
+                    self.log.set_snapshot_active(true);
+                    self.snapshot = Some(snapshot);
+                }
+                true
+            }
+            Err(_) => {
+                self.stats.snapshot_failures += 1;
+                false
+            }
+        }
+    }
+
+    // Only a plain file name inside the snapshot directory is accepted, never a path that could escape it.
+    fn snapshot_path(&self, file_name: &[u8]) -> Option<PathBuf> {
+        let snapshot_directory = self.snapshot_directory.as_ref()?;
+        let file_name = std::str::from_utf8(file_name).ok()?;
+        if file_name.contains(['/', '\\']) || Path::new(file_name).file_name()? != file_name {
+            return None;
         }
+        Some(snapshot_directory.join(file_name))
+    }
+
+    // Writes up to the given number of segments of a snapshot in progress, every executed command writes one and
+    // the shard writes more whenever it is polled, so a snapshot also completes on an idle shard.
+    pub(crate) fn advance_snapshot(&mut self, segments: usize) {
+        for _ in 0..segments {
+            let Some(snapshot) = self.snapshot.as_mut() else {
+                return;
+            };
+            match snapshot.step(&self.log) {
+                Ok(progress) => {
+                    self.snapshot_progress = Some(progress);
+                    if progress.is_complete() {
+                        self.log.set_snapshot_active(false);
+                        self.snapshot = None;
+                    }
+                }
+                // The progress stays at the segment that failed to be written.
+                Err(_) => {
+                    self.log.set_snapshot_active(false);
+                    self.snapshot = None;
+                    self.stats.snapshot_failures += 1;
+                }
+            }
+        }
+    }
+
+    // A Get that borrows the value from the segment holding it instead of copying it into a response, it is
+    // accounted for like a Get command.
+    pub(crate) fn execute_get_ref(&mut self, key: &[u8]) -> Option<Result<KeyValueRef<'_>, Error>> {
+        self.evicted_keys.clear();
+        self.hot_keys.record(key);
+        self.log.try_expire(key, unix_millis());
+        self.journal_log_evictions();
+        self.record_get(self.log.contains_key(key));
+        self.log.try_get_ref(key)
+    }
+
+    // Reads a key on behalf of the shard, without counting it as an access.
+    pub(crate) fn get_ref(&self, key: &[u8]) -> Option<Result<KeyValueRef<'_>, Error>> {
+        self.log.try_get_ref(key)
+    }
+
+    // Answers from the index alone, the value is neither read nor decompressed.
+    pub(crate) fn contains_key(&self, key: &[u8]) -> bool {
+        self.log.contains_key(key)
+    }
+
+    pub(crate) fn expires_at(&self, key: &[u8]) -> Option<u64> {
+        self.log.expires_at(key)
+    }
+
+    pub(crate) fn scan_keys(&self, cursor: u64, count: usize) -> (Vec<Vec<u8>>, u64) {
+        self.log.scan_keyspace(cursor, count)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::executor::command::Command;
+    use std::fs;
+    use std::time::Duration;
+
+    use crate::executor::command::{unix_millis, Command, CommandType, TransactionError};
     use crate::executor::command_executor::CommandExecutor;
-    use crate::memory::log::Log;
+    use crate::executor::wal::FsyncPolicy;
+    use crate::memory::budget::{EvictionPolicy, MemoryBudget};
+    use crate::memory::compression::{CompressionAlgorithm, ValueCompression};
+    use crate::memory::counter::CounterError;
+    use crate::memory::key_value::KeyValue;
+    use crate::memory::log::{CompareAndSwapResult, ConcatenateError, Log};
     use crate::memory::options::LogOptions;
+    use crate::memory::snapshot::Snapshot;
 
     #[test]
     fn should_execute_put_command_successfully() {
@@ -113,4 +545,544 @@ mod tests {
         assert_eq!(true, command_response.is_get_response());
         assert_eq!(true, command_response.get_response().is_none());
     }
+
+    #[test]
+    fn should_return_the_original_bytes_of_compressed_values() {
+        let value = "{\"consensus\":\"raft\",\"leader\":true}".repeat(30).into_bytes();
+        let log = Log::new(LogOptions::new(4096, 4096).with_compression(ValueCompression::new(CompressionAlgorithm::Lz4, 512)));
+        let mut executor = CommandExecutor::new(log);
+
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"raft"), value.clone())).put_response());
+        assert_eq!(Some(Ok(())), executor.execute(Command::append(Vec::from(b"raft"), Vec::from(b"!"))).concatenate_response());
+
+        let command_response = executor.execute(Command::get(Vec::from(b"raft")));
+        assert_eq!([&value[..], b"!"].concat(), command_response.get_response().unwrap().unwrap().value());
+        assert_eq!(&[&value[..], b"!"].concat()[..], executor.execute_get_ref(b"raft").unwrap().unwrap().value());
+
+        let stats = executor.stats();
+        assert_eq!(2, stats.compressed_values);
+        assert_eq!(true, stats.compression_ratio() > 2.0);
+        assert_eq!(true, stats.bytes_used < value.len());
+    }
+
+    #[test]
+    fn should_keep_the_client_flags_of_an_item() {
+        let path = std::env::temp_dir().join(format!("memcore-executor-flags-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let log = Log::new(LogOptions::new(256, 256));
+            let mut executor = CommandExecutor::with_write_ahead_log(log, &path, FsyncPolicy::Always).unwrap();
+            let put = Command::put(Vec::from(b"visits"), Vec::from(b"1")).with_flags(5);
+            assert_eq!(true, executor.execute(put).put_response());
+            assert_eq!(Some(Ok(2)), executor.execute(Command::increment(Vec::from(b"visits"), 1)).counter_response());
+            let append = Command::append(Vec::from(b"visits"), Vec::from(b"0"));
+            assert_eq!(Some(Ok(())), executor.execute(append).concatenate_response());
+
+            let command_response = executor.execute(Command::get(Vec::from(b"visits")));
+            assert_eq!(5, command_response.get_response().unwrap().unwrap().flags());
+        }
+
+        let log = Log::new(LogOptions::new(256, 256));
+        let mut executor = CommandExecutor::with_write_ahead_log(log, &path, FsyncPolicy::Never).unwrap();
+        let key_value = executor.execute(Command::get(Vec::from(b"visits"))).get_response().unwrap().unwrap();
+        assert_eq!(b"20", key_value.value());
+        assert_eq!(5, key_value.flags());
+
+        let command_response = executor.execute(Command::get_with_version(Vec::from(b"visits")));
+        assert_eq!(5, command_response.get_with_version_response().unwrap().unwrap().0.flags());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_extend_the_expiry_of_a_key_with_touch_and_get_and_touch() {
+        let mut executor = CommandExecutor::new(Log::new(LogOptions::new(256, 256)));
+        let put = Command::put(Vec::from(b"session"), Vec::from(b"alice")).with_ttl(Duration::from_secs(60));
+        assert_eq!(true, executor.execute(put).put_response());
+        let bytes_used = executor.stats().bytes_used;
+
+        let ttl = Duration::from_secs(120);
+        assert_eq!(true, executor.execute(Command::touch(Vec::from(b"session"), ttl)).touch_response());
+        assert_eq!(false, executor.execute(Command::touch(Vec::from(b"raft"), ttl)).touch_response());
+
+        let command_response = executor.execute(Command::get_and_touch(Vec::from(b"session"), Duration::from_secs(180)));
+        assert_eq!(b"alice", command_response.get_and_touch_response().unwrap().unwrap().value());
+        assert_eq!(bytes_used, executor.stats().bytes_used);
+        assert_eq!(true, executor.expires_at(b"session").unwrap() > unix_millis() + 120_000);
+    }
+
+    #[test]
+    fn should_expire_a_key_touched_with_a_zero_ttl() {
+        let mut executor = CommandExecutor::new(Log::new(LogOptions::new(256, 256)));
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"session"), Vec::from(b"alice"))).put_response());
+        assert_eq!(true, executor.execute(Command::touch(Vec::from(b"session"), Duration::ZERO)).touch_response());
+
+        assert_eq!(true, executor.execute(Command::get(Vec::from(b"session"))).get_response().is_none());
+        assert_eq!(1, executor.stats().expirations);
+        assert_eq!(1, executor.stats().misses);
+        assert_eq!(vec![Vec::from(b"session")], executor.evicted_keys());
+    }
+
+    #[test]
+    fn should_execute_get_ref_successfully_and_get_the_value_of_the_key() {
+        let log_size_bytes = 64;
+        let segment_size_bytes = 64;
+
+        let log = Log::new(LogOptions::new(log_size_bytes, segment_size_bytes));
+        let mut executor = CommandExecutor::new(log);
+
+        let command_response = executor.execute(Command::put(Vec::from(b"raft"), Vec::from(b"consensus")));
+        assert_eq!(true, command_response.put_response());
+
+        let key_value_ref = executor.execute_get_ref(b"raft").unwrap().unwrap();
+        assert_eq!(b"consensus", key_value_ref.value());
+        assert_eq!(false, key_value_ref.is_compressed());
+        assert_eq!(true, executor.execute_get_ref(b"paxos").is_none());
+
+        let stats = executor.stats();
+        assert_eq!((2, 1, 1), (stats.gets, stats.hits, stats.misses));
+        assert_eq!(2, executor.hot_keys().accesses_of(b"raft"));
+    }
+
+    #[test]
+    fn should_expire_a_key_read_with_get_ref() {
+        let mut executor = CommandExecutor::new(Log::new(LogOptions::new(256, 256)));
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"session"), Vec::from(b"alice"))).put_response());
+        assert_eq!(true, executor.execute(Command::touch(Vec::from(b"session"), Duration::ZERO)).touch_response());
+
+        assert_eq!(true, executor.execute_get_ref(b"session").is_none());
+        assert_eq!(1, executor.stats().expirations);
+        assert_eq!(vec![Vec::from(b"session")], executor.evicted_keys());
+    }
+
+    #[test]
+    fn should_recover_from_write_ahead_log() {
+        let path = std::env::temp_dir().join(format!("memcore-executor-wal-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let log = Log::new(LogOptions::new(64, 64));
+            let mut executor = CommandExecutor::with_write_ahead_log(log, &path, FsyncPolicy::Always).unwrap();
+            assert_eq!(true, executor.execute(Command::put(Vec::from(b"raft"), Vec::from(b"consensus"))).put_response());
+            assert_eq!(true, executor.execute(Command::update(Vec::from(b"raft"), Vec::from(b"leader"))).update_response());
+            assert_eq!(true, executor.execute(Command::get(Vec::from(b"raft"))).is_get_response());
+        }
+
+        let log = Log::new(LogOptions::new(64, 64));
+        let mut executor = CommandExecutor::with_write_ahead_log(log, &path, FsyncPolicy::Never).unwrap();
+
+        let command_response = executor.execute(Command::get(Vec::from(b"raft")));
+        assert_eq!(Vec::from(b"leader"), command_response.get_response().unwrap().unwrap().value());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_replay_the_write_ahead_log_after_the_position_of_a_snapshot() {
+        let path = std::env::temp_dir().join(format!("memcore-executor-wal-snapshot-{}.log", std::process::id()));
+        let file_name = format!("memcore-executor-wal-snapshot-{}.snap", std::process::id());
+        let snapshot_path = std::env::temp_dir().join(&file_name);
+        let _ = fs::remove_file(&path);
+        {
+            let log = Log::new(LogOptions::new(256, 128));
+            let mut executor = CommandExecutor::with_write_ahead_log(log, &path, FsyncPolicy::Always)
+                .unwrap()
+                .with_snapshot_directory(std::env::temp_dir());
+            assert_eq!(true, executor.execute(Command::put(Vec::from(b"visits"), Vec::from(b"1"))).put_response());
+            assert_eq!(true, executor.execute(Command::snapshot(file_name.into_bytes())).snapshot_response());
+            assert_eq!(true, executor.snapshot_progress().unwrap().is_complete());
+            assert_eq!(Some(Ok(2)), executor.execute(Command::increment(Vec::from(b"visits"), 1)).counter_response());
+        }
+
+        let log = Snapshot::restore(&snapshot_path, LogOptions::new(256, 128)).unwrap();
+        let mut executor = CommandExecutor::with_write_ahead_log(log, &path, FsyncPolicy::Never).unwrap();
+        assert_eq!((0, 0), (executor.stats().puts, executor.stats().updates));
+        assert_eq!(0, executor.hot_keys().accesses_of(b"visits"));
+        let command_response = executor.execute(Command::get(Vec::from(b"visits")));
+        assert_eq!(b"2", command_response.get_response().unwrap().unwrap().value());
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&snapshot_path).unwrap();
+    }
+
+    #[test]
+    fn should_not_replay_the_write_ahead_log_into_a_log_holding_unknown_writes() {
+        let path = std::env::temp_dir().join(format!("memcore-executor-wal-unknown-{}.log", std::process::id()));
+        let mut log = Log::new(LogOptions::new(256, 128));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"visits"), Vec::from(b"1"))));
+
+        assert_eq!(true, CommandExecutor::with_write_ahead_log(log, &path, FsyncPolicy::Never).is_err());
+        assert_eq!(false, path.exists());
+    }
+
+    #[test]
+    fn should_journal_the_keys_evicted_by_moving_a_segment_between_size_classes() {
+        let path = std::env::temp_dir().join(format!("memcore-executor-size-classes-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let log = Log::new(LogOptions::new(192, 64).with_size_classes(vec![32]));
+            let mut executor = CommandExecutor::with_write_ahead_log(log, &path, FsyncPolicy::Always).unwrap();
+            assert_eq!(true, executor.execute(Command::put(Vec::from(b"ra00"), Vec::from(b"consensus"))).put_response());
+            assert_eq!(true, executor.execute(Command::put(Vec::from(b"paxos"), vec![1; 40])).put_response());
+            assert_eq!(true, executor.execute(Command::put(Vec::from(b"multi"), vec![2; 40])).put_response());
+            assert_eq!(true, executor.execute(Command::put(Vec::from(b"ra01"), Vec::from(b"consensus"))).put_response());
+            assert_eq!(true, executor.execute(Command::put(Vec::from(b"ra02"), Vec::from(b"consensus"))).put_response());
+            assert_eq!(vec![Vec::from(b"paxos")], executor.evicted_keys());
+        }
+
+        let log = Log::new(LogOptions::new(512, 64).with_size_classes(vec![32]));
+        let mut executor = CommandExecutor::with_write_ahead_log(log, &path, FsyncPolicy::Never).unwrap();
+        assert_eq!(None, executor.execute(Command::get(Vec::from(b"paxos"))).get_response().map(|_| ()));
+        assert_eq!(Vec::from(b"consensus"), executor.execute(Command::get(Vec::from(b"ra02"))).get_response().unwrap().unwrap().value());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_take_a_snapshot_while_executing_other_commands() {
+        let file_name = format!("memcore-executor-snapshot-{}.snap", std::process::id());
+        let path = std::env::temp_dir().join(&file_name);
+        let _ = fs::remove_file(&path);
+
+        let log = Log::new(LogOptions::new(128, 32));
+        let mut executor = CommandExecutor::new(log).with_snapshot_directory(std::env::temp_dir());
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"raft"), Vec::from(b"consensus"))).put_response());
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"paxos"), Vec::from(b"consensus"))).put_response());
+
+        let command_response = executor.execute(Command::snapshot(file_name.into_bytes()));
+        assert_eq!(true, command_response.is_snapshot_response());
+        assert_eq!(true, command_response.snapshot_response());
+        assert_eq!(false, executor.snapshot_progress().unwrap().is_complete());
+
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"zab"), Vec::from(b"atomic"))).put_response());
+        assert_eq!(true, executor.snapshot_progress().unwrap().is_complete());
+        assert_eq!(63, executor.snapshot_progress().unwrap().bytes_written);
+
+        let restored = Snapshot::restore(&path, LogOptions::new(128, 32)).unwrap();
+        assert_eq!(true, restored.try_get(b"paxos").is_some());
+        assert_eq!(true, restored.try_get(b"zab").is_none());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_not_take_a_snapshot_outside_the_snapshot_directory() {
+        let log = Log::new(LogOptions::new(128, 32));
+        let mut executor = CommandExecutor::new(log).with_snapshot_directory(std::env::temp_dir());
+
+        assert_eq!(false, executor.execute(Command::snapshot(Vec::from(b"../shard-0.snap"))).snapshot_response());
+        assert_eq!(false, executor.execute(Command::snapshot(Vec::from(b"/etc/shard-0.snap"))).snapshot_response());
+        assert_eq!(false, executor.execute(Command::snapshot(Vec::from(b"nested/shard-0.snap"))).snapshot_response());
+        assert_eq!(false, executor.execute(Command::snapshot(Vec::from(b".."))).snapshot_response());
+        assert_eq!(true, executor.snapshot_progress().is_none());
+    }
+
+    #[test]
+    fn should_not_increment_a_counter_into_a_snapshot_in_progress() {
+        let file_name = format!("memcore-executor-counter-snapshot-{}.snap", std::process::id());
+        let path = std::env::temp_dir().join(&file_name);
+        let _ = fs::remove_file(&path);
+
+        let log = Log::new(LogOptions::new(128, 32));
+        let mut executor = CommandExecutor::new(log).with_snapshot_directory(std::env::temp_dir());
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"paxos"), Vec::from(b"consensus"))).put_response());
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"hits"), Vec::from(b"10"))).put_response());
+        assert_eq!(true, executor.execute(Command::snapshot(file_name.into_bytes())).snapshot_response());
+
+        assert_eq!(Some(Ok(15)), executor.execute(Command::increment(Vec::from(b"hits"), 5)).counter_response());
+        executor.execute(Command::get(Vec::from(b"hits")));
+        assert_eq!(true, executor.snapshot_progress().unwrap().is_complete());
+
+        let restored = Snapshot::restore(&path, LogOptions::new(128, 32)).unwrap();
+        assert_eq!(b"10", restored.try_get(b"hits").unwrap().unwrap().value());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_not_take_a_snapshot_without_a_snapshot_directory() {
+        let log = Log::new(LogOptions::new(128, 32));
+        let mut executor = CommandExecutor::new(log);
+
+        assert_eq!(false, executor.execute(Command::snapshot(Vec::from(b"shard-0.snap"))).snapshot_response());
+    }
+
+    #[test]
+    fn should_execute_get_with_version_command_successfully() {
+        let log = Log::new(LogOptions::new(64, 64));
+        let mut executor = CommandExecutor::new(log);
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"raft"), Vec::from(b"consensus"))).put_response());
+
+        let (key_value, version) = executor.execute(Command::get_with_version(Vec::from(b"raft"))).get_with_version_response().unwrap().unwrap();
+        assert_eq!(b"consensus", key_value.value());
+        assert_eq!(1, version);
+    }
+
+    #[test]
+    fn should_execute_compare_and_swap_command_successfully() {
+        let log = Log::new(LogOptions::new(128, 128));
+        let mut executor = CommandExecutor::new(log);
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"raft"), Vec::from(b"consensus"))).put_response());
+
+        let command_response = executor.execute(Command::compare_and_swap(Vec::from(b"raft"), Vec::from(b"leader"), 1));
+        assert_eq!(Some(CompareAndSwapResult::Swapped(2)), command_response.compare_and_swap_response());
+
+        let command_response = executor.execute(Command::compare_and_swap(Vec::from(b"raft"), Vec::from(b"follower"), 1));
+        assert_eq!(Some(CompareAndSwapResult::VersionMismatch(2)), command_response.compare_and_swap_response());
+
+        let command_response = executor.execute(Command::get(Vec::from(b"raft")));
+        assert_eq!(b"leader", command_response.get_response().unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn should_execute_increment_and_decrement_commands_successfully() {
+        let log = Log::new(LogOptions::new(64, 64));
+        let mut executor = CommandExecutor::new(log);
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"hits"), Vec::from(b"10"))).put_response());
+
+        assert_eq!(Some(Ok(11)), executor.execute(Command::increment(Vec::from(b"hits"), 1)).counter_response());
+        assert_eq!(Some(Ok(1)), executor.execute(Command::decrement(Vec::from(b"hits"), 10)).counter_response());
+
+        let command_response = executor.execute(Command::get(Vec::from(b"hits")));
+        assert_eq!(b"1", command_response.get_response().unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn should_not_execute_increment_command_given_delta_overflows() {
+        let log = Log::new(LogOptions::new(64, 64));
+        let mut executor = CommandExecutor::new(log);
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"hits"), Vec::from(b"10"))).put_response());
+
+        let command_response = executor.execute(Command::increment(Vec::from(b"hits"), u64::MAX));
+        assert_eq!(Some(Err(CounterError::Overflow)), command_response.counter_response());
+    }
+
+    #[test]
+    fn should_execute_append_and_prepend_commands_successfully() {
+        let log = Log::new(LogOptions::new(128, 64));
+        let mut executor = CommandExecutor::new(log);
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"events"), Vec::from(b"b"))).put_response());
+
+        assert_eq!(Some(Ok(())), executor.execute(Command::append(Vec::from(b"events"), Vec::from(b"c"))).concatenate_response());
+        assert_eq!(Some(Ok(())), executor.execute(Command::prepend(Vec::from(b"events"), Vec::from(b"a"))).concatenate_response());
+
+        let command_response = executor.execute(Command::get(Vec::from(b"events")));
+        assert_eq!(b"abc", command_response.get_response().unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn should_not_execute_append_command_given_key_does_not_exist() {
+        let log = Log::new(LogOptions::new(64, 64));
+        let mut executor = CommandExecutor::new(log);
+
+        let command_response = executor.execute(Command::append(Vec::from(b"events"), Vec::from(b"c")));
+        assert_eq!(Some(Err(ConcatenateError::KeyNotFound)), command_response.concatenate_response());
+    }
+
+    #[test]
+    fn should_execute_delete_command_successfully() {
+        let log = Log::new(LogOptions::new(64, 64));
+        let mut executor = CommandExecutor::new(log);
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"raft"), Vec::from(b"consensus"))).put_response());
+
+        assert_eq!(true, executor.execute(Command::delete(Vec::from(b"raft"))).delete_response());
+        assert_eq!(false, executor.execute(Command::delete(Vec::from(b"raft"))).delete_response());
+        assert_eq!(true, executor.execute(Command::get(Vec::from(b"raft"))).get_response().is_none());
+    }
+
+    #[test]
+    fn should_execute_multi_key_commands_locally() {
+        let log = Log::new(LogOptions::new(128, 128));
+        let mut executor = CommandExecutor::new(log);
+
+        let responses = executor.execute(Command::multi_put(vec![
+            (Vec::from(b"raft"), Vec::from(b"consensus")),
+            (Vec::from(b"paxos"), Vec::from(b"consensus")),
+        ])).multi_response();
+        assert_eq!(vec![true, true], responses.iter().map(|response| response.put_response()).collect::<Vec<_>>());
+
+        let responses = executor.execute(Command::multi_get(vec![Vec::from(b"paxos"), Vec::from(b"zab")])).multi_response();
+        let mut responses = responses.into_iter();
+        assert_eq!(b"consensus", responses.next().unwrap().get_response().unwrap().unwrap().value());
+        assert_eq!(true, responses.next().unwrap().get_response().is_none());
+    }
+
+    #[test]
+    fn should_execute_a_transaction_command_successfully() {
+        let log = Log::new(LogOptions::new(128, 128));
+        let mut executor = CommandExecutor::new(log);
+
+        let responses = executor.execute(Command::transaction(vec![
+            Command::put(Vec::from(b"raft"), Vec::from(b"consensus")),
+            Command::append(Vec::from(b"raft"), Vec::from(b"!")),
+        ])).transaction_response().unwrap();
+        assert_eq!(2, responses.len());
+        assert_eq!(b"consensus!", executor.execute(Command::get(Vec::from(b"raft"))).get_response().unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn should_rollback_a_transaction_command_given_a_step_fails() {
+        let log = Log::new(LogOptions::new(128, 128));
+        let mut executor = CommandExecutor::new(log);
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"raft"), Vec::from(b"consensus"))).put_response());
+
+        let command_response = executor.execute(Command::transaction(vec![
+            Command::put(Vec::from(b"raft"), Vec::from(b"leader")),
+            Command::put(Vec::from(b"paxos"), Vec::from(b"consensus")),
+            Command::append(Vec::from(b"zab"), Vec::from(b"!")),
+        ]));
+        assert_eq!(Err(TransactionError::Aborted(2)), command_response.transaction_response().map(|responses| responses.len()));
+        assert_eq!(b"consensus", executor.execute(Command::get(Vec::from(b"raft"))).get_response().unwrap().unwrap().value());
+        assert_eq!(true, executor.execute(Command::get(Vec::from(b"paxos"))).get_response().is_none());
+    }
+
+    #[test]
+    fn should_rollback_a_transaction_command_given_out_of_space() {
+        let log = Log::new(LogOptions::new(64, 64));
+        let mut executor = CommandExecutor::new(log);
+
+        let command_response = executor.execute(Command::transaction(vec![
+            Command::put(Vec::from(b"raft"), Vec::from(b"consensus")),
+            Command::put(Vec::from(b"paxos"), Vec::from(b"consensus")),
+            Command::put(Vec::from(b"zab"), Vec::from(b"consensus")),
+        ]));
+        assert_eq!(Err(TransactionError::Aborted(2)), command_response.transaction_response().map(|responses| responses.len()));
+        assert_eq!(true, executor.execute(Command::get(Vec::from(b"raft"))).get_response().is_none());
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"zab"), Vec::from(b"consensus"))).put_response());
+    }
+
+    #[test]
+    fn should_not_execute_a_transaction_command_containing_a_snapshot() {
+        let log = Log::new(LogOptions::new(64, 64));
+        let mut executor = CommandExecutor::new(log);
+
+        let command_response = executor.execute(Command::transaction(vec![
+            Command::put(Vec::from(b"raft"), Vec::from(b"consensus")),
+            Command::snapshot(Vec::from(b"shard-0.snap")),
+        ]));
+        assert_eq!(Err(TransactionError::UnsupportedCommand(1)), command_response.transaction_response().map(|responses| responses.len()));
+        assert_eq!(true, executor.execute(Command::get(Vec::from(b"raft"))).get_response().is_none());
+    }
+
+    #[test]
+    fn should_execute_scan_keyspace_command_until_the_cursor_returns_to_zero() {
+        let log = Log::new(LogOptions::new(1024, 1024));
+        let mut executor = CommandExecutor::new(log);
+        for key in 0..20 {
+            assert_eq!(true, executor.execute(Command::put(format!("key-{}", key).into_bytes(), Vec::from(b"v"))).put_response());
+        }
+
+        let mut keys = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (page, next) = executor.execute(Command::scan_keyspace(cursor, 4)).scan_keyspace_response().unwrap();
+            keys.extend(page);
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+        keys.sort();
+        keys.dedup();
+        assert_eq!(20, keys.len());
+    }
+
+    #[test]
+    fn should_execute_stats_command() {
+        let log = Log::new(LogOptions::new(32, 32));
+        let mut executor = CommandExecutor::new(log);
+        assert_eq!(true, executor.execute(Command::put(Vec::from(b"raft"), Vec::from(b"consensus"))).put_response());
+        assert_eq!(false, executor.execute(Command::update(Vec::from(b"raft"), Vec::from(b"leader"))).update_response());
+        executor.execute(Command::get(Vec::from(b"raft")));
+        executor.execute(Command::get(Vec::from(b"paxos")));
+
+        let stats = executor.execute(Command::stats()).stats_response().unwrap().total;
+        assert_eq!(2, stats.gets);
+        assert_eq!(1, stats.hits);
+        assert_eq!(1, stats.misses);
+        assert_eq!(1, stats.puts);
+        assert_eq!(1, stats.updates);
+        assert_eq!(1, stats.rejected_appends);
+        assert_eq!(1, stats.index_size);
+        assert_eq!(31, stats.bytes_used);
+    }
+
+    #[test]
+    fn should_execute_slow_log_command() {
+        let log = Log::new(LogOptions::new(32, 32));
+        let mut executor = CommandExecutor::new(log).with_slow_log(Duration::ZERO, 2);
+        executor.execute(Command::put(Vec::from(b"raft"), Vec::from(b"consensus")));
+        executor.execute(Command::get(Vec::from(b"raft")));
+
+        let entries = executor.execute(Command::slow_log(10)).slow_log_response().unwrap();
+        assert_eq!(2, entries.len());
+        assert_eq!(CommandType::Get, entries[0].command_type);
+        assert_eq!(Vec::from(b"raft"), entries[0].key_prefix);
+        assert_eq!(CommandType::Put, entries[1].command_type);
+        assert_eq!(13, entries[1].size);
+    }
+
+    #[test]
+    fn should_not_record_commands_faster_than_the_slow_log_threshold() {
+        let log = Log::new(LogOptions::new(32, 32));
+        let mut executor = CommandExecutor::new(log).with_slow_log(Duration::from_secs(60), 2);
+        executor.execute(Command::put(Vec::from(b"raft"), Vec::from(b"consensus")));
+
+        assert_eq!(true, executor.execute(Command::slow_log(10)).slow_log_response().unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_execute_hot_keys_command() {
+        let log = Log::new(LogOptions::new(32, 32));
+        let mut executor = CommandExecutor::new(log).with_hot_keys(4);
+        executor.execute(Command::put(Vec::from(b"raft"), Vec::from(b"consensus")));
+        executor.execute(Command::multi_get(vec![Vec::from(b"raft"), Vec::from(b"paxos")]));
+        executor.execute(Command::get(Vec::from(b"raft")));
+
+        let hot_keys = executor.execute(Command::hot_keys(1)).hot_keys_response().unwrap();
+        assert_eq!(1, hot_keys.len());
+        assert_eq!(Vec::from(b"raft"), hot_keys[0].key);
+        assert_eq!(3, hot_keys[0].count);
+        assert_eq!(4, executor.hot_keys().total());
+    }
+
+    #[test]
+    fn should_reject_writes_over_the_memory_budget() {
+        let log = Log::new(LogOptions::new(1024, 256));
+        let budget = MemoryBudget::new(log.memory_usage() + 64, EvictionPolicy::Reject);
+        let mut executor = CommandExecutor::new(log).with_memory_budget(budget);
+
+        let mut index = 0;
+        while executor.execute(Command::put(format!("key-{}", index).into_bytes(), Vec::from(b"value"))).put_response() {
+            index += 1;
+        }
+        let response = executor.execute(Command::put(Vec::from(b"raft"), Vec::from(b"consensus")));
+        assert_eq!(true, response.is_out_of_memory());
+        assert_eq!(true, executor.execute(Command::get(Vec::from(b"key-0"))).get_response().is_some());
+        assert_eq!(true, executor.execute(Command::delete(Vec::from(b"key-0"))).delete_response());
+
+        let stats = executor.stats();
+        assert_eq!(2, stats.out_of_memory);
+        assert_eq!(0, stats.evictions);
+        assert_eq!(true, stats.memory_used <= budget.limit_bytes());
+    }
+
+    #[test]
+    fn should_evict_the_least_accessed_keys_over_the_memory_budget() {
+        let log = Log::new(LogOptions::new(4096, 1024));
+        let budget = MemoryBudget::new(log.memory_usage() + 1024, EvictionPolicy::EvictLeastAccessed);
+        let mut executor = CommandExecutor::new(log).with_memory_budget(budget);
+        executor.execute(Command::put(Vec::from(b"raft"), Vec::from(b"consensus")));
+
+        for index in 0..64 {
+            executor.execute(Command::get(Vec::from(b"raft")));
+            let response = executor.execute(Command::put(format!("key-{}", index).into_bytes(), Vec::from(b"value")));
+            assert_eq!(true, response.put_response());
+        }
+
+        let stats = executor.stats();
+        assert_eq!(true, stats.evictions > 0);
+        assert_eq!(0, stats.out_of_memory);
+        assert_eq!(true, stats.memory_used <= budget.limit_bytes() + 256);
+        assert_eq!(true, executor.execute(Command::get(Vec::from(b"raft"))).get_response().is_some());
+    }
 }
\ No newline at end of file
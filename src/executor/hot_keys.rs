@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct HotKey {
+    pub(crate) shard: usize,
+    pub(crate) key: Vec<u8>,
+    pub(crate) count: u64,
+    // How much of count may belong to keys this one replaced, the key was accessed at least count - error times.
+    pub(crate) error: u64,
+}
+
+// Space-Saving keeps a fixed number of counters, a key that is not tracked takes over the counter with the
+// fewest accesses and inherits its count as the error. Any key accessed more than total / capacity times is
+// guaranteed to be tracked.
+pub(crate) struct HotKeys {
+    capacity: usize,
+    counters: Vec<HotKey>,
+    positions: HashMap<Vec<u8>, usize>,
+    hottest: u64,
+    total: u64,
+}
+
+impl HotKeys {
+    pub(crate) fn new(capacity: usize) -> Self {
+        HotKeys {
+            capacity,
+            counters: Vec::with_capacity(capacity),
+            positions: HashMap::with_capacity(capacity),
+            hottest: 0,
+            total: 0,
+        }
+    }
+
+    pub(crate) fn record(&mut self, key: &[u8]) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.total += 1;
+        let position = match self.positions.get(key) {
+            Some(position) => *position,
+            None if self.counters.len() < self.capacity => {
+                self.counters.push(HotKey { shard: 0, key: key.to_vec(), count: 0, error: 0 });
+                self.positions.insert(key.to_vec(), self.counters.len() - 1);
+                self.counters.len() - 1
+            }
+            None => {
+                let (position, coldest) = self.counters
+                    .iter_mut()
+                    .enumerate()
+                    .min_by_key(|(_, counter)| counter.count)
+                    .unwrap();
+                self.positions.remove(&coldest.key);
+                coldest.key = key.to_vec();
+                coldest.error = coldest.count;
+                self.positions.insert(key.to_vec(), position);
+                position
+            }
+        };
+        let counter = &mut self.counters[position];
+        counter.count += 1;
+        self.hottest = self.hottest.max(counter.count);
+    }
+
+    pub(crate) fn top(&self, count: usize) -> Vec<HotKey> {
+        merge_top(self.counters.clone(), count)
+    }
+
+    // The accesses a tracked key is guaranteed to have had, untracked keys have not been accessed often enough
+    // to matter.
+    pub(crate) fn accesses_of(&self, key: &[u8]) -> u64 {
+        self.positions.get(key).map_or(0, |position| {
+            let counter = &self.counters[*position];
+            counter.count - counter.error
+        })
+    }
+
+    pub(crate) fn hottest(&self) -> u64 {
+        self.hottest
+    }
+
+    pub(crate) fn total(&self) -> u64 {
+        self.total
+    }
+}
+
+pub(crate) fn merge_top(mut hot_keys: Vec<HotKey>, count: usize) -> Vec<HotKey> {
+    hot_keys.sort_by(|one, other| other.count.cmp(&one.count).then_with(|| one.key.cmp(&other.key)));
+    hot_keys.truncate(count);
+    hot_keys
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::executor::hot_keys::HotKeys;
+
+    #[test]
+    fn reports_the_most_accessed_keys_first() {
+        let mut hot_keys = HotKeys::new(4);
+        for key in ["raft", "paxos", "raft", "zab", "raft", "paxos"] {
+            hot_keys.record(key.as_bytes());
+        }
+
+        let top = hot_keys.top(2);
+        assert_eq!(Vec::from(b"raft"), top[0].key);
+        assert_eq!(3, top[0].count);
+        assert_eq!(Vec::from(b"paxos"), top[1].key);
+        assert_eq!(2, top[1].count);
+        assert_eq!(3, hot_keys.hottest());
+        assert_eq!(6, hot_keys.total());
+    }
+
+    #[test]
+    fn replaces_the_coldest_key_given_no_free_counter() {
+        let mut hot_keys = HotKeys::new(2);
+        for key in ["raft", "raft", "paxos", "zab"] {
+            hot_keys.record(key.as_bytes());
+        }
+
+        let top = hot_keys.top(2);
+        assert_eq!(Vec::from(b"raft"), top[0].key);
+        assert_eq!(Vec::from(b"zab"), top[1].key);
+        assert_eq!(2, top[1].count);
+        assert_eq!(1, top[1].error);
+        assert_eq!(1, hot_keys.accesses_of(b"zab"));
+        assert_eq!(0, hot_keys.accesses_of(b"paxos"));
+    }
+
+    #[test]
+    fn keeps_a_dominant_key_among_many_cold_ones() {
+        let mut hot_keys = HotKeys::new(8);
+        for index in 0..1000 {
+            hot_keys.record(b"hot");
+            hot_keys.record(format!("cold-{}", index).as_bytes());
+        }
+
+        let top = hot_keys.top(1);
+        assert_eq!(Vec::from(b"hot"), top[0].key);
+        assert_eq!(1000, top[0].count - top[0].error);
+    }
+}
\ No newline at end of file
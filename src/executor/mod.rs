@@ -1,2 +1,6 @@
 pub(crate) mod command_executor;
-mod command;
\ No newline at end of file
+pub(crate) mod hot_keys;
+pub(crate) mod command;
+pub(crate) mod slow_log;
+pub(crate) mod stats;
+mod wal;
\ No newline at end of file
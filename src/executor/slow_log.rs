@@ -0,0 +1,142 @@
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+use crate::executor::command::{Command, CommandType};
+
+const KEY_PREFIX_SIZE: usize = 32;
+
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct SlowLogEntry {
+    pub(crate) id: u64,
+    pub(crate) shard: usize,
+    pub(crate) timestamp: SystemTime,
+    pub(crate) duration: Duration,
+    pub(crate) command_type: CommandType,
+    pub(crate) key_prefix: Vec<u8>,
+    pub(crate) size: usize,
+}
+
+// What is known about a command before it executes, captured without allocating since most commands are fast.
+pub(crate) struct CommandSummary {
+    command_type: CommandType,
+    key_prefix: [u8; KEY_PREFIX_SIZE],
+    key_prefix_length: usize,
+    size: usize,
+}
+
+pub(crate) struct SlowLog {
+    threshold: Duration,
+    capacity: usize,
+    entries: VecDeque<SlowLogEntry>,
+    next_id: u64,
+}
+
+impl CommandSummary {
+    pub(crate) fn of(command: &Command) -> Self {
+        let key = match command.batch.first() {
+            Some(first) if command.key.is_empty() => &first.key,
+            _ => &command.key,
+        };
+        let key_prefix_length = key.len().min(KEY_PREFIX_SIZE);
+        let mut key_prefix = [0; KEY_PREFIX_SIZE];
+        key_prefix[..key_prefix_length].copy_from_slice(&key[..key_prefix_length]);
+
+        CommandSummary { command_type: command.command_type, key_prefix, key_prefix_length, size: command.payload_bytes() }
+    }
+}
+
+impl SlowLog {
+    pub(crate) fn new(threshold: Duration, capacity: usize) -> Self {
+        SlowLog { threshold, capacity, entries: VecDeque::with_capacity(capacity), next_id: 0 }
+    }
+
+    pub(crate) fn record(&mut self, summary: CommandSummary, duration: Duration) {
+        if duration < self.threshold || self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_back();
+        }
+        self.entries.push_front(SlowLogEntry {
+            id: self.next_id,
+            shard: 0,
+            timestamp: SystemTime::now(),
+            duration,
+            command_type: summary.command_type,
+            key_prefix: summary.key_prefix[..summary.key_prefix_length].to_vec(),
+            size: summary.size,
+        });
+        self.next_id += 1;
+    }
+
+    pub(crate) fn latest(&self, count: usize) -> Vec<SlowLogEntry> {
+        self.entries.iter().take(count).cloned().collect()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+pub(crate) fn merge_latest(mut entries: Vec<SlowLogEntry>, count: usize) -> Vec<SlowLogEntry> {
+    entries.sort_by(|one, other| other.timestamp.cmp(&one.timestamp).then(other.id.cmp(&one.id)));
+    entries.truncate(count);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use crate::executor::command::{Command, CommandType};
+    use crate::executor::slow_log::{merge_latest, CommandSummary, SlowLog, SlowLogEntry};
+
+    #[test]
+    fn ignores_commands_under_the_threshold() {
+        let mut slow_log = SlowLog::new(Duration::from_millis(10), 4);
+        slow_log.record(CommandSummary::of(&Command::get(Vec::from(b"raft"))), Duration::from_millis(1));
+        assert_eq!(0, slow_log.len());
+    }
+
+    #[test]
+    fn records_slow_commands_newest_first() {
+        let mut slow_log = SlowLog::new(Duration::from_millis(10), 2);
+        slow_log.record(CommandSummary::of(&Command::get(Vec::from(b"raft"))), Duration::from_millis(10));
+        slow_log.record(CommandSummary::of(&Command::put(Vec::from(b"paxos"), Vec::from(b"consensus"))), Duration::from_millis(20));
+        slow_log.record(CommandSummary::of(&Command::delete(Vec::from(b"zab"))), Duration::from_millis(30));
+
+        let entries = slow_log.latest(10);
+        assert_eq!(2, entries.len());
+        assert_eq!(CommandType::Delete, entries[0].command_type);
+        assert_eq!(Duration::from_millis(30), entries[0].duration);
+        assert_eq!(Vec::from(b"paxos"), entries[1].key_prefix);
+        assert_eq!(14, entries[1].size);
+    }
+
+    #[test]
+    fn summarizes_a_batch_by_its_first_key() {
+        let key = vec![b'k'; 100];
+        let summary = CommandSummary::of(&Command::multi_get(vec![key, Vec::from(b"raft")]));
+
+        let mut slow_log = SlowLog::new(Duration::ZERO, 1);
+        slow_log.record(summary, Duration::from_millis(1));
+        let entry = slow_log.latest(1).pop().unwrap();
+        assert_eq!(vec![b'k'; 32], entry.key_prefix);
+        assert_eq!(104, entry.size);
+    }
+
+    #[test]
+    fn merges_the_latest_entries() {
+        let entry = |id: u64, seconds: u64| SlowLogEntry {
+            id,
+            shard: 0,
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(seconds),
+            duration: Duration::from_millis(10),
+            command_type: CommandType::Get,
+            key_prefix: Vec::new(),
+            size: 0,
+        };
+        let merged = merge_latest(vec![entry(0, 10), entry(0, 30), entry(1, 20)], 2);
+        assert_eq!(vec![entry(0, 30), entry(1, 20)], merged);
+    }
+}
\ No newline at end of file
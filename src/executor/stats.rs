@@ -0,0 +1,111 @@
+#[derive(Clone, Default, PartialEq, Debug)]
+pub(crate) struct Stats {
+    pub(crate) gets: u64,
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+    pub(crate) puts: u64,
+    pub(crate) updates: u64,
+    pub(crate) rejected_appends: u64,
+    pub(crate) out_of_memory: u64,
+    pub(crate) evictions: u64,
+    pub(crate) expirations: u64,
+    pub(crate) compressed_values: u64,
+    pub(crate) uncompressed_value_bytes: u64,
+    pub(crate) compressed_value_bytes: u64,
+    pub(crate) index_size: usize,
+    pub(crate) bytes_used: usize,
+    pub(crate) memory_used: usize,
+    pub(crate) segment_bytes: Vec<usize>,
+    pub(crate) queue_depth: usize,
+    // Progress of the snapshot in progress or the last one taken, and the snapshots that failed to be written.
+    pub(crate) snapshot_segments_written: usize,
+    pub(crate) snapshot_segments_total: usize,
+    pub(crate) snapshot_bytes_written: usize,
+    pub(crate) snapshot_bytes_total: usize,
+    pub(crate) snapshot_failures: u64,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct StatsReport {
+    pub(crate) total: Stats,
+    pub(crate) shards: Vec<Stats>,
+}
+
+impl Stats {
+    // Per segment bytes only make sense within a shard, the total keeps the bytes used instead.
+    pub(crate) fn sum(stats: &[Stats]) -> Stats {
+        stats.iter().fold(Stats::default(), |total, stats| Stats {
+            gets: total.gets + stats.gets,
+            hits: total.hits + stats.hits,
+            misses: total.misses + stats.misses,
+            puts: total.puts + stats.puts,
+            updates: total.updates + stats.updates,
+            rejected_appends: total.rejected_appends + stats.rejected_appends,
+            out_of_memory: total.out_of_memory + stats.out_of_memory,
+            evictions: total.evictions + stats.evictions,
+            expirations: total.expirations + stats.expirations,
+            compressed_values: total.compressed_values + stats.compressed_values,
+            uncompressed_value_bytes: total.uncompressed_value_bytes + stats.uncompressed_value_bytes,
+            compressed_value_bytes: total.compressed_value_bytes + stats.compressed_value_bytes,
+            index_size: total.index_size + stats.index_size,
+            bytes_used: total.bytes_used + stats.bytes_used,
+            memory_used: total.memory_used + stats.memory_used,
+            segment_bytes: Vec::new(),
+            queue_depth: total.queue_depth + stats.queue_depth,
+            snapshot_segments_written: total.snapshot_segments_written + stats.snapshot_segments_written,
+            snapshot_segments_total: total.snapshot_segments_total + stats.snapshot_segments_total,
+            snapshot_bytes_written: total.snapshot_bytes_written + stats.snapshot_bytes_written,
+            snapshot_bytes_total: total.snapshot_bytes_total + stats.snapshot_bytes_total,
+            snapshot_failures: total.snapshot_failures + stats.snapshot_failures,
+        })
+    }
+
+    // Original over stored bytes of the compressed values, 1.0 when nothing was compressed.
+    pub(crate) fn compression_ratio(&self) -> f64 {
+        if self.compressed_value_bytes == 0 {
+            return 1.0;
+        }
+        self.uncompressed_value_bytes as f64 / self.compressed_value_bytes as f64
+    }
+}
+
+impl StatsReport {
+    pub(crate) fn of(shards: Vec<Stats>) -> Self {
+        StatsReport { total: Stats::sum(&shards), shards }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::executor::stats::{Stats, StatsReport};
+
+    #[test]
+    fn sums_the_stats_of_all_shards() {
+        let report = StatsReport::of(vec![
+            Stats { gets: 2, hits: 1, misses: 1, bytes_used: 46, segment_bytes: vec![23, 23], ..Stats::default() },
+            Stats { gets: 3, hits: 3, puts: 1, bytes_used: 23, segment_bytes: vec![23], queue_depth: 2, ..Stats::default() },
+        ]);
+
+        assert_eq!(5, report.total.gets);
+        assert_eq!(4, report.total.hits);
+        assert_eq!(1, report.total.misses);
+        assert_eq!(1, report.total.puts);
+        assert_eq!(69, report.total.bytes_used);
+        assert_eq!(2, report.total.queue_depth);
+        assert_eq!(0, report.total.snapshot_failures);
+        assert_eq!(true, report.total.segment_bytes.is_empty());
+        assert_eq!(vec![23, 23], report.shards[0].segment_bytes);
+    }
+
+    #[test]
+    fn compression_ratio_of_all_shards() {
+        let report = StatsReport::of(vec![
+            Stats { compressed_values: 1, uncompressed_value_bytes: 3000, compressed_value_bytes: 1000, ..Stats::default() },
+            Stats { compressed_values: 2, uncompressed_value_bytes: 1000, compressed_value_bytes: 1000, ..Stats::default() },
+        ]);
+
+        assert_eq!(3, report.total.compressed_values);
+        assert_eq!(2.0, report.total.compression_ratio());
+        assert_eq!(1.0, Stats::default().compression_ratio());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,244 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use bytes::{BufMut, BytesMut};
+
+use crate::executor::command::{Command, COMMAND_HEADER_SIZE};
+
+// length (u32) | checksum (u32) | encoded command
+const RECORD_HEADER_SIZE: usize = 8;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum FsyncPolicy {
+    Always,
+    Every(Duration),
+    Never,
+}
+
+pub(crate) struct WriteAheadLog {
+    file: File,
+    fsync_policy: FsyncPolicy,
+    last_sync: Instant,
+    unsynced: bool,
+    length: u64,
+}
+
+impl WriteAheadLog {
+    pub(crate) fn open(path: &Path, fsync_policy: FsyncPolicy) -> Result<Self, Error> {
+        let file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        let length = file.metadata()?.len();
+        Ok(WriteAheadLog {
+            file,
+            fsync_policy,
+            last_sync: Instant::now(),
+            unsynced: false,
+            length,
+        })
+    }
+
+    // Returns the commands after the position, a snapshot records the position its log reached so that only the
+    // commands written after it are applied to the restored log.
+    pub(crate) fn replay(&mut self, from: u64) -> Result<Vec<Command>, Error> {
+        let mut contents = Vec::new();
+        (&self.file).read_to_end(&mut contents)?;
+        let mut position = usize::try_from(from)
+            .ok()
+            .filter(|from| *from <= contents.len())
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "position is past the end of the write-ahead log"))?;
+
+        let mut commands = Vec::new();
+        while let Some((command, record_size)) = Self::decode_record(&contents[position..]) {
+            commands.push(command);
+            position += record_size;
+        }
+        if position < contents.len() {
+            self.file.set_len(position as u64)?;
+            self.file.sync_all()?;
+        }
+        self.length = position as u64;
+        Ok(commands)
+    }
+
+    // The end of the last record written, the position a snapshot taken now holds the writes up to.
+    pub(crate) fn position(&self) -> u64 {
+        self.length
+    }
+
+    pub(crate) fn append(&mut self, command: &Command) -> Result<(), Error> {
+        let encoded = command.encode();
+
+        let mut record = BytesMut::with_capacity(RECORD_HEADER_SIZE + encoded.len());
+        record.put_u32_le(encoded.len() as u32);
+        record.put_u32_le(crc32fast::hash(&encoded));
+        record.put_slice(&encoded);
+        self.file.write_all(&record)?;
+        self.length += record.len() as u64;
+        self.unsynced = true;
+
+        match self.fsync_policy {
+            FsyncPolicy::Always => self.sync(),
+            _ => self.sync_if_due(),
+        }
+    }
+
+    // Called on every append and from the shard poll loop, so an idle log still gets synced once its interval elapses.
+    pub(crate) fn sync_if_due(&mut self) -> Result<(), Error> {
+        match self.fsync_policy {
+            FsyncPolicy::Every(interval) if self.unsynced && self.last_sync.elapsed() >= interval => self.sync(),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn sync(&mut self) -> Result<(), Error> {
+        self.file.sync_data()?;
+        self.last_sync = Instant::now();
+        self.unsynced = false;
+        Ok(())
+    }
+
+    fn decode_record(buffer: &[u8]) -> Option<(Command, usize)> {
+        if buffer.len() < RECORD_HEADER_SIZE {
+            return None;
+        }
+        let length = u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as usize;
+        let checksum = u32::from_le_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]);
+        if length < COMMAND_HEADER_SIZE || buffer.len() < RECORD_HEADER_SIZE + length {
+            return None;
+        }
+        let encoded = &buffer[RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + length];
+        if crc32fast::hash(encoded) != checksum {
+            return None;
+        }
+        Command::decode_from(BytesMut::from(encoded))
+            .ok()
+            .map(|command| (command, RECORD_HEADER_SIZE + length))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use crate::executor::command::{Command, CommandType};
+    use crate::executor::wal::{FsyncPolicy, WriteAheadLog};
+
+    fn wal_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("memcore-wal-{}-{}.log", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn append_and_replay_commands() {
+        let path = wal_file("append-replay");
+        {
+            let mut wal = WriteAheadLog::open(&path, FsyncPolicy::Always).unwrap();
+            wal.append(&Command::put(Vec::from(b"raft"), Vec::from(b"consensus"))).unwrap();
+            wal.append(&Command::update(Vec::from(b"raft"), Vec::from(b"leader"))).unwrap();
+        }
+
+        let mut wal = WriteAheadLog::open(&path, FsyncPolicy::Never).unwrap();
+        let commands = wal.replay(0).unwrap();
+        assert_eq!(2, commands.len());
+        assert_eq!(CommandType::Put, commands[0].command_type);
+        assert_eq!(CommandType::Update, commands[1].command_type);
+        assert_eq!(Vec::from(b"leader"), commands[1].value.clone().unwrap());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replay_the_commands_after_a_position() {
+        let path = wal_file("replay-from");
+        let mut wal = WriteAheadLog::open(&path, FsyncPolicy::Never).unwrap();
+        wal.append(&Command::put(Vec::from(b"raft"), Vec::from(b"consensus"))).unwrap();
+        let position = wal.position();
+        wal.append(&Command::put(Vec::from(b"paxos"), Vec::from(b"consensus"))).unwrap();
+
+        let mut wal = WriteAheadLog::open(&path, FsyncPolicy::Never).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), wal.position());
+        let commands = wal.replay(position).unwrap();
+        assert_eq!(1, commands.len());
+        assert_eq!(Vec::from(b"paxos"), commands[0].key);
+        assert_eq!(true, wal.replay(wal.position() + 1).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replay_tolerates_a_torn_last_record() {
+        let path = wal_file("torn-record");
+        {
+            let mut wal = WriteAheadLog::open(&path, FsyncPolicy::Every(Duration::from_millis(10))).unwrap();
+            wal.append(&Command::put(Vec::from(b"raft"), Vec::from(b"consensus"))).unwrap();
+            wal.append(&Command::put(Vec::from(b"paxos"), Vec::from(b"consensus"))).unwrap();
+        }
+        let length = fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(length - 3).unwrap();
+
+        let mut wal = WriteAheadLog::open(&path, FsyncPolicy::Always).unwrap();
+        let commands = wal.replay(0).unwrap();
+        assert_eq!(1, commands.len());
+        assert_eq!(Vec::from(b"raft"), commands[0].key);
+
+        wal.append(&Command::put(Vec::from(b"zab"), Vec::from(b"atomic"))).unwrap();
+        let commands = WriteAheadLog::open(&path, FsyncPolicy::Never).unwrap().replay(0).unwrap();
+        assert_eq!(2, commands.len());
+        assert_eq!(Vec::from(b"zab"), commands[1].key);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replay_stops_at_a_corrupted_record() {
+        let path = wal_file("corrupted-record");
+        {
+            let mut wal = WriteAheadLog::open(&path, FsyncPolicy::Never).unwrap();
+            wal.append(&Command::put(Vec::from(b"raft"), Vec::from(b"consensus"))).unwrap();
+        }
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&[8, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]).unwrap();
+
+        let commands = WriteAheadLog::open(&path, FsyncPolicy::Never).unwrap().replay(0).unwrap();
+        assert_eq!(1, commands.len());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replay_truncates_a_zeroed_tail() {
+        let path = wal_file("zeroed-tail");
+        {
+            let mut wal = WriteAheadLog::open(&path, FsyncPolicy::Never).unwrap();
+            wal.append(&Command::put(Vec::from(b"raft"), Vec::from(b"consensus"))).unwrap();
+        }
+        let length = fs::metadata(&path).unwrap().len();
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&[0; 64]).unwrap();
+
+        let commands = WriteAheadLog::open(&path, FsyncPolicy::Never).unwrap().replay(0).unwrap();
+        assert_eq!(1, commands.len());
+        assert_eq!(length, fs::metadata(&path).unwrap().len());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn sync_an_idle_log_once_the_interval_elapses() {
+        let path = wal_file("idle-sync");
+        let mut wal = WriteAheadLog::open(&path, FsyncPolicy::Every(Duration::from_millis(5))).unwrap();
+        wal.sync().unwrap();
+        wal.append(&Command::put(Vec::from(b"raft"), Vec::from(b"consensus"))).unwrap();
+        assert_eq!(true, wal.unsynced);
+
+        std::thread::sleep(Duration::from_millis(10));
+        wal.sync_if_due().unwrap();
+        assert_eq!(false, wal.unsynced);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
\ No newline at end of file
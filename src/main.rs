@@ -1,7 +1,54 @@
 pub(crate) mod memory;
 pub(crate) mod queue;
 mod executor;
+mod metrics;
+mod shard;
 
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::thread;
+
+use crate::executor::command_executor::CommandExecutor;
+use crate::memory::log::Log;
+use crate::memory::options::LogOptions;
+use crate::metrics::registry::MetricsRegistry;
+use crate::metrics::server::MetricsServer;
+use crate::shard::worker::Shard;
+
+const LOG_SIZE_BYTES: usize = 16 * 1024 * 1024;
+const SEGMENT_SIZE_BYTES: usize = 1024 * 1024;
+const QUEUE_CAPACITY: usize = 1024;
+const METRICS_ADDRESS: &str = "127.0.0.1:9100";
+
+// One shard per core, each polled by its own thread, and the metrics server on a thread of its own.
 fn main() {
-    println!("Hello, world!");
-}
+    let number_of_shards = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let executors = (0..number_of_shards)
+        .map(|_| CommandExecutor::new(Log::new(LogOptions::new(LOG_SIZE_BYTES, SEGMENT_SIZE_BYTES))))
+        .collect();
+    let shards = Shard::all(executors, QUEUE_CAPACITY);
+
+    let registry = Arc::new(MetricsRegistry::new(shards.iter().map(|shard| shard.metrics()).collect()));
+    let server = MetricsServer::bind(METRICS_ADDRESS, registry).expect("failed to bind the metrics server");
+    thread::Builder::new()
+        .name("metrics".to_string())
+        .spawn(move || server.serve())
+        .expect("failed to start the metrics server");
+
+    let workers: Vec<_> = shards
+        .into_iter()
+        .map(|mut shard| {
+            thread::Builder::new()
+                .name(format!("shard-{}", shard.id()))
+                .spawn(move || loop {
+                    if shard.poll().is_empty() {
+                        thread::yield_now();
+                    }
+                })
+                .expect("failed to start a shard")
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+}
\ No newline at end of file
@@ -0,0 +1,280 @@
+use std::alloc::{alloc_zeroed, dealloc, Layout};
+use std::fs::OpenOptions;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::ptr;
+
+const PAGE_SIZE: usize = 4096;
+#[cfg(target_os = "linux")]
+const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum ArenaBacking {
+    Heap,
+    Mapped,
+    HugePages,
+    File,
+}
+
+pub(crate) struct Arena {
+    memory: *mut u8,
+    size: usize,
+    mapped_size: usize,
+    backing: ArenaBacking,
+}
+
+// The arena is carved into disjoint segments and each segment is written by its single owner,
+// so the raw pointer can move across threads along with the log that owns the arena.
+unsafe impl Send for Arena {}
+unsafe impl Sync for Arena {}
+
+impl Arena {
+    pub(crate) fn new(size: usize, huge_pages: bool, pre_fault: bool) -> Self {
+        assert!(size > 0);
+        #[cfg(target_os = "linux")]
+        {
+            if huge_pages {
+                if let Some(arena) = Self::try_mmap(size, round_up(size, HUGE_PAGE_SIZE), libc::MAP_HUGETLB, pre_fault) {
+                    return arena;
+                }
+            }
+            if let Some(arena) = Self::try_mmap(size, round_up(size, PAGE_SIZE), 0, pre_fault) {
+                if huge_pages {
+                    arena.advise_transparent_huge_pages();
+                }
+                return arena;
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = huge_pages;
+        Self::heap(size, pre_fault)
+    }
+
+    pub(crate) fn heap(size: usize, pre_fault: bool) -> Self {
+        assert!(size > 0);
+        let layout = Layout::from_size_align(size, PAGE_SIZE).expect("invalid arena layout");
+        let memory = unsafe { alloc_zeroed(layout) };
+        assert!(!memory.is_null(), "failed to allocate arena of {} bytes", size);
+
+        let arena = Arena {
+            memory,
+            size,
+            mapped_size: size,
+            backing: ArenaBacking::Heap,
+        };
+        if pre_fault {
+            arena.pre_fault();
+        }
+        arena
+    }
+
+    #[cfg(target_os = "linux")]
+    pub(crate) fn file_backed(path: &Path, size: usize) -> Result<Self, Error> {
+        use std::os::fd::AsRawFd;
+
+        assert!(size > 0);
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let file_size = file.metadata()?.len() as usize;
+        if file_size == 0 {
+            file.set_len(size as u64)?;
+        } else if file_size != size {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("backing file has {} bytes but the arena needs {} bytes", file_size, size),
+            ));
+        }
+        let memory = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if memory == libc::MAP_FAILED {
+            return Err(Error::last_os_error());
+        }
+        Ok(Arena {
+            memory: memory as *mut u8,
+            size,
+            mapped_size: size,
+            backing: ArenaBacking::File,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(crate) fn file_backed(_path: &Path, _size: usize) -> Result<Self, Error> {
+        Err(Error::new(ErrorKind::Unsupported, "file backed arena is only supported on linux"))
+    }
+
+    pub(crate) fn flush(&self) -> Result<(), Error> {
+        #[cfg(target_os = "linux")]
+        if self.backing == ArenaBacking::File {
+            let result = unsafe { libc::msync(self.memory as *mut libc::c_void, self.mapped_size, libc::MS_SYNC) };
+            if result != 0 {
+                return Err(Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    pub(crate) fn backing(&self) -> ArenaBacking {
+        self.backing
+    }
+
+    pub(crate) fn slice(&self, offset: usize, size: usize) -> &[u8] {
+        assert!(offset + size <= self.size);
+        unsafe { std::slice::from_raw_parts(self.memory.add(offset), size) }
+    }
+
+    // SAFETY: the caller must be the only writer of [offset, offset + slice.len()) and there must be
+    // no outstanding reads of that range, which holds for a segment writing past its own length.
+    pub(crate) unsafe fn write(&self, offset: usize, slice: &[u8]) {
+        assert!(offset + slice.len() <= self.size);
+        ptr::copy_nonoverlapping(slice.as_ptr(), self.memory.add(offset), slice.len());
+    }
+
+    fn pre_fault(&self) {
+        let mut offset = 0;
+        while offset < self.mapped_size {
+            unsafe { ptr::write_volatile(self.memory.add(offset), 0) };
+            offset += PAGE_SIZE;
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn try_mmap(size: usize, mapped_size: usize, flags: libc::c_int, pre_fault: bool) -> Option<Self> {
+        let populate = if pre_fault { libc::MAP_POPULATE } else { 0 };
+        let memory = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                mapped_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | flags | populate,
+                -1,
+                0,
+            )
+        };
+        if memory == libc::MAP_FAILED {
+            return None;
+        }
+        Some(Arena {
+            memory: memory as *mut u8,
+            size,
+            mapped_size,
+            backing: if flags & libc::MAP_HUGETLB != 0 { ArenaBacking::HugePages } else { ArenaBacking::Mapped },
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    fn advise_transparent_huge_pages(&self) {
+        unsafe {
+            libc::madvise(self.memory as *mut libc::c_void, self.mapped_size, libc::MADV_HUGEPAGE);
+        }
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        match self.backing {
+            ArenaBacking::Heap => unsafe {
+                dealloc(self.memory, Layout::from_size_align_unchecked(self.size, PAGE_SIZE));
+            },
+            #[cfg(target_os = "linux")]
+            ArenaBacking::Mapped | ArenaBacking::HugePages | ArenaBacking::File => unsafe {
+                libc::munmap(self.memory as *mut libc::c_void, self.mapped_size);
+            },
+            #[cfg(not(target_os = "linux"))]
+            ArenaBacking::Mapped | ArenaBacking::HugePages | ArenaBacking::File => unreachable!(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn round_up(size: usize, multiple: usize) -> usize {
+    size.div_ceil(multiple) * multiple
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::memory::arena::{Arena, ArenaBacking};
+
+    #[test]
+    fn allocates_a_heap_arena() {
+        let arena = Arena::heap(64, false);
+        assert_eq!(64, arena.size());
+        assert_eq!(ArenaBacking::Heap, arena.backing());
+    }
+
+    #[test]
+    fn allocates_a_pre_faulted_heap_arena() {
+        let arena = Arena::heap(3 * 4096, true);
+        assert_eq!(&[0, 0, 0, 0], arena.slice(2 * 4096, 4));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn allocates_a_mapped_arena() {
+        let arena = Arena::new(64, false, true);
+        assert_eq!(64, arena.size());
+        assert_eq!(ArenaBacking::Mapped, arena.backing());
+    }
+
+    #[test]
+    fn falls_back_given_huge_pages_are_not_available() {
+        let arena = Arena::new(64, true, false);
+        assert_eq!(64, arena.size());
+    }
+
+    #[test]
+    fn writes_to_and_reads_from_arena() {
+        let arena = Arena::new(64, false, false);
+        unsafe { arena.write(16, b"memcore") };
+
+        assert_eq!(b"memcore", arena.slice(16, 7));
+    }
+
+    #[test]
+    #[should_panic]
+    fn should_panic_given_slice_beyond_the_arena() {
+        let arena = Arena::new(16, false, false);
+        let _ = arena.slice(10, 7);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn retains_the_contents_of_a_file_backed_arena() {
+        let path = std::env::temp_dir().join(format!("memcore-arena-{}.data", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let arena = Arena::file_backed(&path, 64).unwrap();
+            assert_eq!(ArenaBacking::File, arena.backing());
+
+            unsafe { arena.write(8, b"memcore") };
+            arena.flush().unwrap();
+        }
+        let arena = Arena::file_backed(&path, 64).unwrap();
+        assert_eq!(b"memcore", arena.slice(8, 7));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn fails_to_open_a_file_backed_arena_given_size_mismatch() {
+        let path = std::env::temp_dir().join(format!("memcore-arena-mismatch-{}.data", std::process::id()));
+        let _ = fs::remove_file(&path);
+        drop(Arena::file_backed(&path, 64).unwrap());
+
+        assert_eq!(true, Arena::file_backed(&path, 128).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
\ No newline at end of file
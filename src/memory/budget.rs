@@ -0,0 +1,63 @@
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum EvictionPolicy {
+    Reject,
+    EvictLeastAccessed,
+}
+
+// A memory limit covering the bytes written to the log, the index and the queued messages and replicas of a shard.
+// The arena is reserved up front and only bounds the log. Writes that arrive over the limit either free a segment
+// by evicting its least accessed keys or are rejected as out of memory.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct MemoryBudget {
+    limit_bytes: usize,
+    policy: EvictionPolicy,
+}
+
+impl MemoryBudget {
+    pub(crate) fn new(limit_bytes: usize, policy: EvictionPolicy) -> Self {
+        MemoryBudget { limit_bytes, policy }
+    }
+
+    // Splits a process-wide budget into one budget per shard, the first shards take the remainder.
+    pub(crate) fn split(&self, number_of_shards: usize) -> Vec<MemoryBudget> {
+        assert!(number_of_shards > 0);
+        (0..number_of_shards)
+            .map(|shard| {
+                let limit_bytes = self.limit_bytes / number_of_shards +
+                    usize::from(shard < self.limit_bytes % number_of_shards);
+                MemoryBudget::new(limit_bytes, self.policy)
+            })
+            .collect()
+    }
+
+    pub(crate) fn limit_bytes(&self) -> usize {
+        self.limit_bytes
+    }
+
+    pub(crate) fn policy(&self) -> EvictionPolicy {
+        self.policy
+    }
+
+    pub(crate) fn is_exceeded_by(&self, used_bytes: usize) -> bool {
+        used_bytes > self.limit_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::budget::{EvictionPolicy, MemoryBudget};
+
+    #[test]
+    fn splits_a_budget_across_shards() {
+        let budgets = MemoryBudget::new(10, EvictionPolicy::Reject).split(3);
+        assert_eq!(vec![4, 3, 3], budgets.iter().map(MemoryBudget::limit_bytes).collect::<Vec<_>>());
+        assert_eq!(true, budgets.iter().all(|budget| budget.policy() == EvictionPolicy::Reject));
+    }
+
+    #[test]
+    fn is_exceeded_past_the_limit() {
+        let budget = MemoryBudget::new(1024, EvictionPolicy::EvictLeastAccessed);
+        assert_eq!(false, budget.is_exceeded_by(1024));
+        assert_eq!(true, budget.is_exceeded_by(1025));
+    }
+}
\ No newline at end of file
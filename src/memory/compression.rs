@@ -0,0 +1,96 @@
+use std::io::{Error, ErrorKind};
+
+pub(crate) const LZ4_FLAG: u8 = 0x02;
+pub(crate) const ZSTD_FLAG: u8 = 0x04;
+pub(crate) const COMPRESSION_FLAGS: u8 = LZ4_FLAG | ZSTD_FLAG;
+
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum CompressionAlgorithm {
+    None,
+    Lz4,
+    Zstd,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct ValueCompression {
+    algorithm: CompressionAlgorithm,
+    threshold_bytes: usize,
+}
+
+impl ValueCompression {
+    pub(crate) fn new(algorithm: CompressionAlgorithm, threshold_bytes: usize) -> Self {
+        ValueCompression { algorithm, threshold_bytes }
+    }
+
+    pub(crate) fn none() -> Self {
+        ValueCompression::new(CompressionAlgorithm::None, 0)
+    }
+
+    // Returns the record flag and the compressed value, values below the threshold or that do not shrink stay as is.
+    pub(crate) fn compress(&self, value: &[u8]) -> Option<(u8, Vec<u8>)> {
+        if value.len() < self.threshold_bytes {
+            return None;
+        }
+        let (flag, compressed) = match self.algorithm {
+            CompressionAlgorithm::None => return None,
+            CompressionAlgorithm::Lz4 => (LZ4_FLAG, lz4_flex::compress_prepend_size(value)),
+            CompressionAlgorithm::Zstd => (ZSTD_FLAG, zstd::encode_all(value, ZSTD_LEVEL).ok()?),
+        };
+        if compressed.len() >= value.len() {
+            return None;
+        }
+        Some((flag, compressed))
+    }
+}
+
+pub(crate) fn decompress(flags: u8, value: &[u8]) -> Result<Vec<u8>, Error> {
+    match flags & COMPRESSION_FLAGS {
+        LZ4_FLAG => lz4_flex::decompress_size_prepended(value).map_err(|error| Error::new(ErrorKind::InvalidData, error)),
+        ZSTD_FLAG => zstd::decode_all(value),
+        _ => Err(Error::new(ErrorKind::InvalidData, "key/value record has an unknown compression")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::compression::{decompress, CompressionAlgorithm, ValueCompression, LZ4_FLAG, ZSTD_FLAG};
+
+    fn json_value() -> Vec<u8> {
+        "{\"consensus\":\"raft\",\"leader\":true}".repeat(40).into_bytes()
+    }
+
+    #[test]
+    fn compresses_and_decompresses_with_lz4() {
+        let value = json_value();
+        let (flag, compressed) = ValueCompression::new(CompressionAlgorithm::Lz4, 64).compress(&value).unwrap();
+
+        assert_eq!(LZ4_FLAG, flag);
+        assert_eq!(true, compressed.len() < value.len());
+        assert_eq!(value, decompress(flag, &compressed).unwrap());
+    }
+
+    #[test]
+    fn compresses_and_decompresses_with_zstd() {
+        let value = json_value();
+        let (flag, compressed) = ValueCompression::new(CompressionAlgorithm::Zstd, 64).compress(&value).unwrap();
+
+        assert_eq!(ZSTD_FLAG, flag);
+        assert_eq!(true, compressed.len() < value.len());
+        assert_eq!(value, decompress(flag, &compressed).unwrap());
+    }
+
+    #[test]
+    fn does_not_compress_values_below_the_threshold() {
+        let compression = ValueCompression::new(CompressionAlgorithm::Lz4, 64);
+        assert_eq!(None, compression.compress(b"consensus"));
+        assert_eq!(None, ValueCompression::none().compress(&json_value()));
+    }
+
+    #[test]
+    fn does_not_compress_values_that_do_not_shrink() {
+        let value: Vec<u8> = (0..=255).collect();
+        assert_eq!(None, ValueCompression::new(CompressionAlgorithm::Zstd, 64).compress(&value));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,76 @@
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum CounterEncoding {
+    Decimal,
+    LittleEndian,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum CounterError {
+    KeyNotFound,
+    NonNumericValue,
+    Overflow,
+    OutOfSpace,
+}
+
+impl CounterEncoding {
+    pub(crate) fn decode(&self, value: &[u8]) -> Result<i64, CounterError> {
+        match self {
+            CounterEncoding::Decimal => std::str::from_utf8(value)
+                .ok()
+                .and_then(|value| value.parse::<i64>().ok())
+                .ok_or(CounterError::NonNumericValue),
+            CounterEncoding::LittleEndian => <[u8; 8]>::try_from(value)
+                .map(i64::from_le_bytes)
+                .map_err(|_| CounterError::NonNumericValue),
+        }
+    }
+
+    pub(crate) fn encode(&self, counter: i64) -> Vec<u8> {
+        match self {
+            CounterEncoding::Decimal => counter.to_string().into_bytes(),
+            CounterEncoding::LittleEndian => counter.to_le_bytes().to_vec(),
+        }
+    }
+}
+
+pub(crate) fn apply_delta(counter: i64, delta: i64) -> Result<i64, CounterError> {
+    counter.checked_add(delta).ok_or(CounterError::Overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::counter::{apply_delta, CounterEncoding, CounterError};
+
+    #[test]
+    fn decodes_a_decimal_counter() {
+        assert_eq!(Ok(-42), CounterEncoding::Decimal.decode(b"-42"));
+    }
+
+    #[test]
+    fn fails_to_decode_a_non_numeric_decimal_counter() {
+        assert_eq!(Err(CounterError::NonNumericValue), CounterEncoding::Decimal.decode(b"consensus"));
+    }
+
+    #[test]
+    fn decodes_a_little_endian_counter() {
+        assert_eq!(Ok(42), CounterEncoding::LittleEndian.decode(&42i64.to_le_bytes()));
+    }
+
+    #[test]
+    fn fails_to_decode_a_little_endian_counter_of_invalid_width() {
+        assert_eq!(Err(CounterError::NonNumericValue), CounterEncoding::LittleEndian.decode(b"42"));
+    }
+
+    #[test]
+    fn encodes_counters() {
+        assert_eq!(Vec::from(b"100"), CounterEncoding::Decimal.encode(100));
+        assert_eq!(100i64.to_le_bytes().to_vec(), CounterEncoding::LittleEndian.encode(100));
+    }
+
+    #[test]
+    fn fails_to_apply_delta_given_overflow() {
+        assert_eq!(Err(CounterError::Overflow), apply_delta(i64::MAX, 1));
+        assert_eq!(Err(CounterError::Overflow), apply_delta(i64::MIN, -1));
+        assert_eq!(Ok(9), apply_delta(10, -1));
+    }
+}
\ No newline at end of file
@@ -1,21 +1,40 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::mem::size_of;
+use std::ops::Bound;
+use std::sync::Arc;
 
+use crate::memory::scan::{ScanPage, ScanRequest};
+
+// Every key is also kept in a dense vector shared with the map, the keyspace cursor walks that vector from the end.
 pub(crate) struct Index {
-    marker_by_key: HashMap<Vec<u8>, IndexMarker>,
+    marker_by_key: HashMap<Arc<[u8]>, IndexEntry>,
+    keys: Vec<Arc<[u8]>>,
+    key_bytes: usize,
+    ordered_keys: Option<BTreeSet<Vec<u8>>>,
+}
+
+struct IndexEntry {
+    marker: IndexMarker,
+    position: usize,
 }
 
+#[derive(Copy, Clone)]
 pub(crate) struct IndexMarker {
     pub(crate) segment_index: usize,
     pub(crate) segment_position: usize,
     pub(crate) key_value_size: usize,
+    pub(crate) version: u64,
+    pub(crate) expires_at: Option<u64>,
 }
 
 impl IndexMarker {
-    pub(crate) fn new(segment_index: usize, segment_position: usize, key_value_size: usize) -> Self {
+    pub(crate) fn new(segment_index: usize, segment_position: usize, key_value_size: usize, version: u64) -> Self {
         IndexMarker {
             segment_index,
             segment_position,
             key_value_size,
+            version,
+            expires_at: None,
         }
     }
 }
@@ -23,22 +42,104 @@ impl IndexMarker {
 impl Index {
     pub(crate) fn new() -> Self {
         Index {
-            marker_by_key: HashMap::new()
+            marker_by_key: HashMap::new(),
+            keys: Vec::new(),
+            key_bytes: 0,
+            ordered_keys: None,
+        }
+    }
+
+    pub(crate) fn ordered() -> Self {
+        Index {
+            ordered_keys: Some(BTreeSet::new()),
+            ..Index::new()
         }
     }
 
     pub(crate) fn insert(&mut self, key: Vec<u8>, value: IndexMarker) {
-        self.marker_by_key.insert(key, value);
+        if let Some(marker) = self.get_mut(&key) {
+            *marker = value;
+            return;
+        }
+        if let Some(ordered_keys) = self.ordered_keys.as_mut() {
+            ordered_keys.insert(key.clone());
+        }
+        let key: Arc<[u8]> = key.into();
+        self.key_bytes += key.len();
+        self.marker_by_key.insert(key.clone(), IndexEntry { marker: value, position: self.keys.len() });
+        self.keys.push(key);
     }
 
     pub(crate) fn get(&self, key: &[u8]) -> Option<&IndexMarker> {
-        self.marker_by_key.get(key)
+        self.marker_by_key.get(key).map(|entry| &entry.marker)
+    }
+
+    pub(crate) fn get_mut(&mut self, key: &[u8]) -> Option<&mut IndexMarker> {
+        self.marker_by_key.get_mut(key).map(|entry| &mut entry.marker)
+    }
+
+    pub(crate) fn remove(&mut self, key: &[u8]) -> Option<IndexMarker> {
+        let entry = self.marker_by_key.remove(key)?;
+        self.keys.swap_remove(entry.position);
+        if let Some(moved) = self.keys.get(entry.position) {
+            self.marker_by_key.get_mut(moved).unwrap().position = entry.position;
+        }
+        if let Some(ordered_keys) = self.ordered_keys.as_mut() {
+            ordered_keys.remove(key);
+        }
+        self.key_bytes -= key.len();
+        Some(entry.marker)
+    }
+
+    // Walks the dense keys from the end, the cursor is the number of positions left to visit. A removal only moves
+    // the last key, which is visited already or still below the cursor, so every key present for the whole scan is
+    // returned at least once. A returned cursor of 0 completes the scan.
+    pub(crate) fn scan_keys(&self, cursor: u64, count: usize) -> (Vec<Vec<u8>>, u64) {
+        let mut position = match cursor {
+            0 => self.keys.len(),
+            cursor => (cursor as usize).min(self.keys.len()),
+        };
+        let mut keys = Vec::new();
+        while position > 0 && keys.len() < count.max(1) {
+            position -= 1;
+            keys.push(self.keys[position].to_vec());
+        }
+        (keys, position as u64)
+    }
+
+    pub(crate) fn scan(&self, request: &ScanRequest) -> Option<ScanPage> {
+        let ordered_keys = self.ordered_keys.as_ref()?;
+        let keys = ordered_keys
+            .range((request.lower_bound(), Bound::Unbounded))
+            .take_while(|key| request.may_continue_with(key))
+            .take(request.limit() + 1)
+            .cloned()
+            .collect();
+        Some(ScanPage::from_keys(keys, request.limit()))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.marker_by_key.len()
+    }
+
+    // An estimate of the heap held by the index, every key is shared by the map and the dense keys behind the two
+    // reference counts of its Arc. The ordered index keeps a second copy of every key.
+    pub(crate) fn memory_usage(&self) -> usize {
+        let entry = 2 * size_of::<Arc<[u8]>>() + size_of::<IndexEntry>() + 2 * size_of::<usize>();
+        let table = self.len() * entry + self.key_bytes;
+        match self.ordered_keys {
+            Some(_) => table + self.len() * size_of::<Vec<u8>>() + self.key_bytes,
+            None => table,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+
     use crate::memory::index::{Index, IndexMarker};
+    use crate::memory::scan::ScanRequest;
 
     #[test]
     fn should_not_find_the_key_in_index() {
@@ -51,12 +152,172 @@ mod tests {
     #[test]
     fn should_find_the_key_in_index() {
         let mut index = Index::new();
-        index.insert(Vec::from(b"raft"), IndexMarker::new(0, 16, 100));
+        index.insert(Vec::from(b"raft"), IndexMarker::new(0, 16, 100, 1));
 
         let optional_marker = index.get(b"raft");
         assert_eq!(true, optional_marker.is_some());
         assert_eq!(0, optional_marker.unwrap().segment_index);
         assert_eq!(16, optional_marker.unwrap().segment_position);
         assert_eq!(100, optional_marker.unwrap().key_value_size);
+        assert_eq!(1, optional_marker.unwrap().version);
+    }
+
+    #[test]
+    fn should_remove_the_key_from_index() {
+        let mut index = Index::new();
+        index.insert(Vec::from(b"raft"), IndexMarker::new(0, 16, 100, 1));
+
+        assert_eq!(true, index.remove(b"raft").is_some());
+        assert_eq!(true, index.get(b"raft").is_none());
+        assert_eq!(0, index.len());
+    }
+
+    #[test]
+    fn should_not_scan_an_unordered_index() {
+        let index = Index::new();
+        assert_eq!(true, index.scan(&ScanRequest::new(10)).is_none());
+    }
+
+    #[test]
+    fn should_scan_the_keys_by_prefix() {
+        let mut index = Index::ordered();
+        for (position, key) in [b"session:2".as_slice(), b"user:1", b"session:1", b"session:3"].iter().enumerate() {
+            index.insert(key.to_vec(), IndexMarker::new(0, position, 10, 1));
+        }
+        index.remove(b"session:3");
+
+        let page = index.scan(&ScanRequest::new(10).with_prefix(Vec::from(b"session:"))).unwrap();
+        assert_eq!(vec![Vec::from(b"session:1"), Vec::from(b"session:2")], page.keys);
+        assert_eq!(None, page.cursor);
+    }
+
+    #[test]
+    fn should_scan_the_keys_page_by_page() {
+        let mut index = Index::ordered();
+        for key in [b"a".as_slice(), b"b", b"c"] {
+            index.insert(key.to_vec(), IndexMarker::new(0, 0, 10, 1));
+            index.insert(key.to_vec(), IndexMarker::new(0, 10, 10, 2));
+        }
+
+        let page = index.scan(&ScanRequest::new(2)).unwrap();
+        assert_eq!(vec![Vec::from(b"a"), Vec::from(b"b")], page.keys);
+
+        let page = index.scan(&ScanRequest::new(2).with_cursor(page.cursor)).unwrap();
+        assert_eq!(vec![Vec::from(b"c")], page.keys);
+        assert_eq!(None, page.cursor);
+    }
+
+    #[test]
+    fn should_replace_the_marker_of_an_existing_key() {
+        let mut index = Index::new();
+        index.insert(Vec::from(b"raft"), IndexMarker::new(0, 16, 100, 1));
+        index.insert(Vec::from(b"raft"), IndexMarker::new(1, 0, 100, 2));
+
+        assert_eq!(1, index.len());
+        assert_eq!(2, index.get(b"raft").unwrap().version);
+    }
+
+    #[test]
+    fn should_find_all_the_keys_after_growing_and_shrinking() {
+        let mut index = Index::new();
+        for key in 0..1000 {
+            index.insert(format!("key-{}", key).into_bytes(), IndexMarker::new(0, key, 10, 1));
+        }
+        for key in 0..990 {
+            assert_eq!(true, index.remove(format!("key-{}", key).as_bytes()).is_some());
+        }
+        assert_eq!(10, index.len());
+        assert_eq!(995, index.get(b"key-995").unwrap().segment_position);
+    }
+
+    fn scan_all(index: &Index, count: usize) -> Vec<Vec<u8>> {
+        let mut keys = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (page, next) = index.scan_keys(cursor, count);
+            keys.extend(page);
+            cursor = next;
+            if cursor == 0 {
+                return keys;
+            }
+        }
+    }
+
+    #[test]
+    fn should_scan_every_key_exactly_once_without_removals() {
+        let mut index = Index::new();
+        for key in 0..100 {
+            index.insert(format!("key-{}", key).into_bytes(), IndexMarker::new(0, key, 10, 1));
+        }
+        let mut keys = scan_all(&index, 7);
+        keys.sort();
+        keys.dedup();
+        assert_eq!(100, keys.len());
+    }
+
+    #[test]
+    fn should_scan_every_key_present_throughout_while_keys_are_added_and_removed() {
+        let mut index = Index::new();
+        for key in 0..10 {
+            index.insert(format!("stable-{}", key).into_bytes(), IndexMarker::new(0, key, 10, 1));
+        }
+
+        let mut scanned = HashSet::new();
+        let mut cursor = 0;
+        let mut round = 0;
+        loop {
+            let (page, next) = index.scan_keys(cursor, 2);
+            scanned.extend(page);
+            for key in 0..100 {
+                let transient = format!("transient-{}-{}", round, key).into_bytes();
+                if round % 2 == 0 {
+                    index.insert(transient, IndexMarker::new(0, key, 10, 1));
+                } else {
+                    index.remove(format!("transient-{}-{}", round - 1, key).as_bytes());
+                }
+            }
+            round += 1;
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+        for key in 0..10 {
+            assert_eq!(true, scanned.contains(format!("stable-{}", key).as_bytes()));
+        }
+    }
+
+    #[test]
+    fn should_scan_a_key_moved_by_a_removal_during_the_scan() {
+        let mut index = Index::new();
+        for key in [b"a".as_slice(), b"b", b"c", b"d", b"e"] {
+            index.insert(key.to_vec(), IndexMarker::new(0, 0, 10, 1));
+        }
+        let (page, cursor) = index.scan_keys(0, 2);
+        assert_eq!(vec![Vec::from(b"e"), Vec::from(b"d")], page);
+
+        index.remove(b"a");
+        index.remove(b"b");
+        let (page, cursor) = index.scan_keys(cursor, 10);
+        assert_eq!(0, cursor);
+        assert_eq!(vec![Vec::from(b"c"), Vec::from(b"d"), Vec::from(b"e")], page);
+    }
+
+    #[test]
+    fn tracks_the_memory_usage_of_keys() {
+        let mut index = Index::new();
+        let empty = index.memory_usage();
+        index.insert(Vec::from(b"raft"), IndexMarker::new(0, 0, 23, 1));
+        let with_key = index.memory_usage();
+        assert_eq!(true, with_key > empty + 4);
+
+        index.insert(Vec::from(b"raft"), IndexMarker::new(0, 23, 23, 2));
+        assert_eq!(with_key, index.memory_usage());
+        index.remove(b"raft");
+        assert_eq!(empty, index.memory_usage());
+
+        let mut ordered = Index::ordered();
+        ordered.insert(Vec::from(b"raft"), IndexMarker::new(0, 0, 23, 1));
+        assert_eq!(true, ordered.memory_usage() > with_key);
     }
 }
\ No newline at end of file
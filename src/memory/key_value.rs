@@ -1,51 +1,112 @@
+use std::borrow::Cow;
 use std::hash::{Hash, Hasher};
-use std::io::{Error, Read};
+use std::io::{Error, ErrorKind};
 
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{BufMut, BytesMut};
 use fasthash::{FastHasher, MurmurHasher};
 
+use crate::memory::compression::{decompress, ValueCompression, COMPRESSION_FLAGS};
+
+#[derive(Clone)]
 pub(crate) struct KeyValue {
     key: Vec<u8>,
     value: Vec<u8>,
+    flags: u32,
+    version: u64,
+    expires_at: Option<u64>,
+    tombstone: bool,
+}
+
+// Borrows the value from the segment unless the record holds a compressed value.
+pub(crate) struct KeyValueRef<'a> {
+    key: &'a [u8],
+    value: Cow<'a, [u8]>,
+    flags: u32,
+    version: u64,
+    expires_at: Option<u64>,
+    tombstone: bool,
+    encoded_size: usize,
 }
 
+// The header and key of a record with the value as stored, the checksum is verified but a compressed value is left
+// compressed for the readers that only need the key.
+pub(crate) struct KeyRef<'a> {
+    key: &'a [u8],
+    stored_value: &'a [u8],
+    record_flags: u8,
+    flags: u32,
+    version: u64,
+    expires_at: Option<u64>,
+    encoded_size: usize,
+}
+
+// magic (u8) | flags (u8) | key length (u16) | value length (u16) | checksum (u32) | key | value
+// the value length and the checksum cover the value as stored, compressed when a compression flag is set.
+// Non-zero client flags follow the header as a u32, then a non-zero version and an expiry deadline as u64s, records
+// without them keep their size.
+pub(crate) const KEY_VALUE_HEADER_SIZE: usize = 10;
+const KEY_VALUE_MAGIC: u8 = 0xC5;
+const TOMBSTONE_FLAG: u8 = 0x01;
+const CLIENT_FLAGS_FLAG: u8 = 0x08;
+const CLIENT_FLAGS_SIZE: usize = 4;
+const VERSION_FLAG: u8 = 0x10;
+const VERSION_SIZE: usize = 8;
+const EXPIRY_FLAG: u8 = 0x20;
+const EXPIRY_SIZE: usize = 8;
+
 impl KeyValue {
     pub(crate) fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
         assert!(key.len() > 0);
         assert!(value.len() > 0);
-        KeyValue { key, value }
+        KeyValue { key, value, flags: 0, version: 0, expires_at: None, tombstone: false }
     }
 
-    pub(crate) fn encode(&self) -> BytesMut {
-        let mut buffer = BytesMut::new();
-        buffer.put_u16_le(self.key.len() as u16);
-        buffer.put_u16_le(self.value.len() as u16);
-        buffer.put_slice(&self.key);
-        buffer.put_slice(&self.value);
-        return buffer;
+    // Opaque to the store, memcached clients keep the serialization format of the value in them.
+    pub(crate) fn with_flags(mut self, flags: u32) -> Self {
+        self.flags = flags;
+        self
     }
 
-    pub(crate) fn decode_from(mut buffer: BytesMut) -> Result<KeyValue, Error> {
-        let key_length = buffer.get_u16_le();
-        let value_length = buffer.get_u16_le();
+    // Written by the log, recovery keeps the record of a key with the highest version wherever it was stored.
+    pub(crate) fn with_version(mut self, version: u64) -> Self {
+        self.version = version;
+        self
+    }
 
-        let mut buffer_reader = buffer.reader();
+    // A unix timestamp in milliseconds, written by the log so that recovery and snapshots restore the deadline.
+    pub(crate) fn with_expires_at(mut self, expires_at: Option<u64>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
 
-        let mut key = Vec::with_capacity(key_length as usize);
-        key.resize(key_length as usize, 0);
-        buffer_reader.read_exact(&mut key)?;
+    pub(crate) fn tombstone(key: Vec<u8>) -> Self {
+        assert!(!key.is_empty());
+        KeyValue { key, value: Vec::new(), flags: 0, version: 0, expires_at: None, tombstone: true }
+    }
 
-        let mut value = Vec::with_capacity(value_length as usize);
-        value.resize(value_length as usize, 0);
-        buffer_reader.read_exact(&mut value)?;
+    pub(crate) fn encode(&self) -> BytesMut {
+        self.encode_with(ValueCompression::none())
+    }
 
-        return Ok(KeyValue::new(key, value));
+    pub(crate) fn encode_with(&self, compression: ValueCompression) -> BytesMut {
+        let mut flags = if self.tombstone { TOMBSTONE_FLAG } else { 0 };
+        let compressed = compression.compress(&self.value);
+        let value = match &compressed {
+            Some((compression_flag, compressed_value)) => {
+                flags |= compression_flag;
+                &compressed_value[..]
+            }
+            None => &self.value[..],
+        };
+        encode_record(flags, self.flags, self.version, self.expires_at, &self.key, value)
+    }
+
+    pub(crate) fn decode_from(buffer: BytesMut) -> Result<KeyValue, Error> {
+        KeyValueRef::decode_from(&buffer).map(|key_value_ref| key_value_ref.to_key_value())
     }
 
     pub(crate) fn hash_of(&self) -> u64 {
-        let mut hasher: MurmurHasher = MurmurHasher::new();
-        self.key.hash(&mut hasher);
-        hasher.finish()
+        hash_of_key(&self.key)
     }
 
     pub(crate) fn key(&self) -> Vec<u8> {
@@ -55,11 +116,300 @@ impl KeyValue {
     pub(crate) fn value(&self) -> &[u8] {
         return &self.value
     }
+
+    pub(crate) fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    pub(crate) fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub(crate) fn expires_at(&self) -> Option<u64> {
+        self.expires_at
+    }
+
+    pub(crate) fn is_tombstone(&self) -> bool {
+        self.tombstone
+    }
+
+    // A view of a key value held outside the log, such as a replica, for the readers of borrowed values.
+    pub(crate) fn as_key_value_ref(&self) -> KeyValueRef<'_> {
+        KeyValueRef {
+            key: &self.key,
+            value: Cow::Borrowed(&self.value),
+            flags: self.flags,
+            version: self.version,
+            expires_at: self.expires_at,
+            tombstone: self.tombstone,
+            encoded_size: self.encoded_size(),
+        }
+    }
+
+    // The size without compression, the encoded record is never larger.
+    pub(crate) fn encoded_size(&self) -> usize {
+        client_flags_size(self.flags) + version_size(self.version) + expiry_size(self.expires_at) +
+            KEY_VALUE_HEADER_SIZE + self.key.len() + self.value.len()
+    }
+}
+
+impl<'a> KeyValueRef<'a> {
+    pub(crate) fn decode_from(buffer: &'a [u8]) -> Result<KeyValueRef<'a>, Error> {
+        KeyRef::decode_from(buffer)?.to_key_value_ref()
+    }
+
+    pub(crate) fn key(&self) -> &'a [u8] {
+        self.key
+    }
+
+    pub(crate) fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    pub(crate) fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    pub(crate) fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub(crate) fn expires_at(&self) -> Option<u64> {
+        self.expires_at
+    }
+
+    pub(crate) fn is_tombstone(&self) -> bool {
+        self.tombstone
+    }
+
+    pub(crate) fn encoded_size(&self) -> usize {
+        self.encoded_size
+    }
+
+    pub(crate) fn is_compressed(&self) -> bool {
+        matches!(self.value, Cow::Owned(_))
+    }
+
+    pub(crate) fn to_key_value(&self) -> KeyValue {
+        KeyValue {
+            key: self.key.to_vec(),
+            value: self.value.to_vec(),
+            flags: self.flags,
+            version: self.version,
+            expires_at: self.expires_at,
+            tombstone: self.tombstone,
+        }
+    }
+}
+
+impl<'a> KeyRef<'a> {
+    pub(crate) fn decode_from(buffer: &'a [u8]) -> Result<KeyRef<'a>, Error> {
+        if buffer.len() < KEY_VALUE_HEADER_SIZE {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "buffer is smaller than the key/value header"));
+        }
+        if buffer[0] != KEY_VALUE_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "buffer does not start with a key/value record"));
+        }
+        let flags = buffer[1];
+        let key_length = u16::from_le_bytes([buffer[2], buffer[3]]) as usize;
+        let value_length = u16::from_le_bytes([buffer[4], buffer[5]]) as usize;
+        let checksum = u32::from_le_bytes([buffer[6], buffer[7], buffer[8], buffer[9]]);
+
+        let version_start = if flags & CLIENT_FLAGS_FLAG != 0 {
+            KEY_VALUE_HEADER_SIZE + CLIENT_FLAGS_SIZE
+        } else {
+            KEY_VALUE_HEADER_SIZE
+        };
+        let expiry_start = if flags & VERSION_FLAG != 0 { version_start + VERSION_SIZE } else { version_start };
+        let key_start = if flags & EXPIRY_FLAG != 0 { expiry_start + EXPIRY_SIZE } else { expiry_start };
+        let key_end = key_start + key_length;
+        let value_end = key_end + value_length;
+        if buffer.len() < value_end {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "buffer is smaller than the encoded key/value"));
+        }
+        let client_flags = if version_start > KEY_VALUE_HEADER_SIZE {
+            u32::from_le_bytes([buffer[10], buffer[11], buffer[12], buffer[13]])
+        } else {
+            0
+        };
+        let version = if expiry_start > version_start {
+            u64::from_le_bytes(buffer[version_start..expiry_start].try_into().unwrap())
+        } else {
+            0
+        };
+        let expires_at = (key_start > expiry_start)
+            .then(|| u64::from_le_bytes(buffer[expiry_start..key_start].try_into().unwrap()));
+        let key = &buffer[key_start..key_end];
+        let stored_value = &buffer[key_end..value_end];
+        if key.is_empty() || checksum != checksum_of(flags, client_flags, version, expires_at, key, stored_value) {
+            return Err(Error::new(ErrorKind::InvalidData, "key/value record is corrupted"));
+        }
+        Ok(KeyRef {
+            key,
+            stored_value,
+            record_flags: flags,
+            flags: client_flags,
+            version,
+            expires_at,
+            encoded_size: value_end,
+        })
+    }
+
+    pub(crate) fn key(&self) -> &'a [u8] {
+        self.key
+    }
+
+    pub(crate) fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    pub(crate) fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub(crate) fn expires_at(&self) -> Option<u64> {
+        self.expires_at
+    }
+
+    pub(crate) fn is_tombstone(&self) -> bool {
+        self.record_flags & TOMBSTONE_FLAG != 0
+    }
+
+    pub(crate) fn is_compressed(&self) -> bool {
+        self.record_flags & COMPRESSION_FLAGS != 0
+    }
+
+    pub(crate) fn encoded_size(&self) -> usize {
+        self.encoded_size
+    }
+
+    // The record with another version and deadline and the value as stored, a compressed value is not
+    // decompressed. It keeps its size if the record already held a deadline and is given one again.
+    pub(crate) fn encode_touched(&self, version: u64, expires_at: Option<u64>) -> BytesMut {
+        let flags = self.record_flags & (TOMBSTONE_FLAG | COMPRESSION_FLAGS);
+        encode_record(flags, self.flags, version, expires_at, self.key, self.stored_value)
+    }
+
+    pub(crate) fn to_key_value_ref(&self) -> Result<KeyValueRef<'a>, Error> {
+        let value = if self.is_compressed() {
+            Cow::Owned(decompress(self.record_flags, self.stored_value)?)
+        } else {
+            Cow::Borrowed(self.stored_value)
+        };
+        Ok(KeyValueRef {
+            key: self.key,
+            value,
+            flags: self.flags,
+            version: self.version,
+            expires_at: self.expires_at,
+            tombstone: self.is_tombstone(),
+            encoded_size: self.encoded_size,
+        })
+    }
+}
+
+pub(crate) struct KeyRefs<'a> {
+    buffer: &'a [u8],
+    position: usize,
+}
+
+impl<'a> KeyRefs<'a> {
+    pub(crate) fn new(buffer: &'a [u8]) -> Self {
+        KeyRefs { buffer, position: 0 }
+    }
+
+    pub(crate) fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl<'a> Iterator for KeyRefs<'a> {
+    type Item = (usize, KeyRef<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key_ref = KeyRef::decode_from(&self.buffer[self.position..]).ok()?;
+        let position = self.position;
+        self.position += key_ref.encoded_size();
+        Some((position, key_ref))
+    }
+}
+
+pub(crate) fn hash_of_key(key: &[u8]) -> u64 {
+    let mut hasher: MurmurHasher = MurmurHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+// The flags name the tombstone and the compression of the value, the flags of the optional fields are set here.
+fn encode_record(flags: u8, client_flags: u32, version: u64, expires_at: Option<u64>, key: &[u8], value: &[u8]) -> BytesMut {
+    let mut flags = flags;
+    if client_flags != 0 {
+        flags |= CLIENT_FLAGS_FLAG;
+    }
+    if version != 0 {
+        flags |= VERSION_FLAG;
+    }
+    if expires_at.is_some() {
+        flags |= EXPIRY_FLAG;
+    }
+    let encoded_size = client_flags_size(client_flags) + version_size(version) + expiry_size(expires_at) +
+        KEY_VALUE_HEADER_SIZE + key.len() + value.len();
+
+    let mut buffer = BytesMut::with_capacity(encoded_size);
+    buffer.put_u8(KEY_VALUE_MAGIC);
+    buffer.put_u8(flags);
+    buffer.put_u16_le(key.len() as u16);
+    buffer.put_u16_le(value.len() as u16);
+    buffer.put_u32_le(checksum_of(flags, client_flags, version, expires_at, key, value));
+    if client_flags != 0 {
+        buffer.put_u32_le(client_flags);
+    }
+    if version != 0 {
+        buffer.put_u64_le(version);
+    }
+    if let Some(expires_at) = expires_at {
+        buffer.put_u64_le(expires_at);
+    }
+    buffer.put_slice(key);
+    buffer.put_slice(value);
+    buffer
+}
+
+fn client_flags_size(client_flags: u32) -> usize {
+    if client_flags != 0 { CLIENT_FLAGS_SIZE } else { 0 }
+}
+
+fn version_size(version: u64) -> usize {
+    if version != 0 { VERSION_SIZE } else { 0 }
+}
+
+fn expiry_size(expires_at: Option<u64>) -> usize {
+    if expires_at.is_some() { EXPIRY_SIZE } else { 0 }
+}
+
+fn checksum_of(flags: u8, client_flags: u32, version: u64, expires_at: Option<u64>, key: &[u8], value: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&[flags]);
+    if client_flags != 0 {
+        hasher.update(&client_flags.to_le_bytes());
+    }
+    if version != 0 {
+        hasher.update(&version.to_le_bytes());
+    }
+    if let Some(expires_at) = expires_at {
+        hasher.update(&expires_at.to_le_bytes());
+    }
+    hasher.update(key);
+    hasher.update(value);
+    hasher.finalize()
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::memory::key_value::KeyValue;
+    use crate::memory::compression::{CompressionAlgorithm, ValueCompression, ZSTD_FLAG};
+    use crate::memory::key_value::{
+        checksum_of, KeyRef, KeyRefs, KeyValue, KeyValueRef, KEY_VALUE_HEADER_SIZE, KEY_VALUE_MAGIC,
+    };
 
     #[test]
     fn encodes_and_decodes_key_value() {
@@ -71,9 +421,204 @@ mod tests {
         assert_eq!(b"consensus", &decoded.value[..]);
     }
 
+    #[test]
+    fn encodes_and_decodes_a_compressed_key_value() {
+        let value = "{\"consensus\":\"raft\"}".repeat(20).into_bytes();
+        let key_value = KeyValue::new(Vec::from(b"raft"), value.clone());
+        let encoded = key_value.encode_with(ValueCompression::new(CompressionAlgorithm::Zstd, 64));
+        assert_eq!(true, encoded.len() < key_value.encoded_size());
+
+        let decoded = KeyValueRef::decode_from(&encoded).expect("Failed to decode the compressed key_value");
+        assert_eq!(true, decoded.is_compressed());
+        assert_eq!(b"raft", decoded.key());
+        assert_eq!(&value[..], decoded.value());
+        assert_eq!(encoded.len(), decoded.encoded_size());
+    }
+
+    #[test]
+    fn decodes_the_key_of_a_record_without_decompressing_the_value() {
+        let value = Vec::from(&b"not zstd"[..]);
+        let mut encoded = vec![KEY_VALUE_MAGIC, ZSTD_FLAG, 4, 0, value.len() as u8, 0];
+        encoded.extend_from_slice(&checksum_of(ZSTD_FLAG, 0, 0, None, b"raft", &value).to_le_bytes());
+        encoded.extend_from_slice(b"raft");
+        encoded.extend_from_slice(&value);
+
+        let key_ref = KeyRef::decode_from(&encoded).expect("Failed to decode the key of the record");
+        assert_eq!(b"raft", key_ref.key());
+        assert_eq!(true, key_ref.is_compressed());
+        assert_eq!(encoded.len(), key_ref.encoded_size());
+        assert_eq!(true, key_ref.to_key_value_ref().is_err());
+        assert_eq!(true, KeyValueRef::decode_from(&encoded).is_err());
+    }
+
+    #[test]
+    fn keeps_a_key_value_below_the_compression_threshold_uncompressed() {
+        let key_value = KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"));
+        let encoded = key_value.encode_with(ValueCompression::new(CompressionAlgorithm::Lz4, 64));
+
+        assert_eq!(key_value.encode(), encoded);
+        assert_eq!(false, KeyValueRef::decode_from(&encoded).unwrap().is_compressed());
+    }
+
+    #[test]
+    fn encodes_and_decodes_the_client_flags() {
+        let key_value = KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus")).with_flags(0xCAFE);
+        let encoded = key_value.encode();
+        assert_eq!(key_value.encoded_size(), encoded.len());
+        assert_eq!(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus")).encoded_size() + 4, encoded.len());
+
+        let decoded = KeyValueRef::decode_from(&encoded).expect("Failed to decode the key_value with client flags");
+        assert_eq!(0xCAFE, decoded.flags());
+        assert_eq!(b"raft", decoded.key());
+        assert_eq!(b"consensus", decoded.value());
+        assert_eq!(0xCAFE, decoded.to_key_value().flags());
+    }
+
+    #[test]
+    fn fails_to_decode_a_key_value_given_corrupted_client_flags() {
+        let mut encoded = KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus")).with_flags(7).encode();
+        encoded[KEY_VALUE_HEADER_SIZE] ^= 0xFF;
+
+        assert_eq!(true, KeyValueRef::decode_from(&encoded).is_err());
+    }
+
+    #[test]
+    fn encodes_and_decodes_the_version_after_the_client_flags() {
+        let key_value = KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus")).with_flags(0xCAFE).with_version(42);
+        let encoded = key_value.encode();
+        assert_eq!(key_value.encoded_size(), encoded.len());
+        assert_eq!(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus")).encoded_size() + 12, encoded.len());
+
+        let decoded = KeyValueRef::decode_from(&encoded).expect("Failed to decode the key_value with a version");
+        assert_eq!(42, decoded.version());
+        assert_eq!(0xCAFE, decoded.flags());
+        assert_eq!(b"raft", decoded.key());
+        assert_eq!(b"consensus", decoded.value());
+    }
+
+    #[test]
+    fn encodes_and_decodes_the_expiry_after_the_version() {
+        let key_value = KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))
+            .with_version(42)
+            .with_expires_at(Some(1_700_000_000_000));
+        let encoded = key_value.encode();
+        assert_eq!(key_value.encoded_size(), encoded.len());
+        assert_eq!(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus")).encoded_size() + 16, encoded.len());
+
+        let decoded = KeyValueRef::decode_from(&encoded).expect("Failed to decode the key_value with an expiry");
+        assert_eq!(Some(1_700_000_000_000), decoded.expires_at());
+        assert_eq!(42, decoded.version());
+        assert_eq!(b"consensus", decoded.value());
+        assert_eq!(Some(1_700_000_000_000), decoded.to_key_value().expires_at());
+    }
+
+    #[test]
+    fn encodes_a_touched_record_with_its_stored_value() {
+        let value = "{\"consensus\":\"raft\"}".repeat(20).into_bytes();
+        let key_value = KeyValue::new(Vec::from(b"raft"), value.clone())
+            .with_flags(3)
+            .with_version(1)
+            .with_expires_at(Some(7));
+        let encoded = key_value.encode_with(ValueCompression::new(CompressionAlgorithm::Zstd, 64));
+
+        let touched = KeyRef::decode_from(&encoded).unwrap().encode_touched(2, Some(9));
+        assert_eq!(encoded.len(), touched.len());
+        let decoded = KeyValueRef::decode_from(&touched).unwrap();
+        assert_eq!((3, 2, Some(9)), (decoded.flags(), decoded.version(), decoded.expires_at()));
+        assert_eq!(true, decoded.is_compressed());
+        assert_eq!(&value[..], decoded.value());
+
+        let touched = KeyRef::decode_from(&encoded).unwrap().encode_touched(2, None);
+        assert_eq!(encoded.len() - 8, touched.len());
+        assert_eq!(None, KeyValueRef::decode_from(&touched).unwrap().expires_at());
+    }
+
+    #[test]
+    fn fails_to_decode_a_key_value_given_a_corrupted_expiry() {
+        let mut encoded = KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus")).with_expires_at(Some(7)).encode();
+        encoded[KEY_VALUE_HEADER_SIZE] ^= 0xFF;
+
+        assert_eq!(true, KeyValueRef::decode_from(&encoded).is_err());
+    }
+
+    #[test]
+    fn fails_to_decode_a_key_value_given_a_corrupted_version() {
+        let mut encoded = KeyValue::tombstone(Vec::from(b"raft")).with_version(42).encode();
+        encoded[KEY_VALUE_HEADER_SIZE] ^= 0xFF;
+
+        assert_eq!(true, KeyValueRef::decode_from(&encoded).is_err());
+    }
+
     #[test]
     fn get_the_hash_of_the_key() {
         let key_value = KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"));
         assert!(key_value.hash_of() > 0);
     }
+
+    #[test]
+    fn decodes_a_key_value_reference_without_copying() {
+        let key_value = KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"));
+        let encoded = key_value.encode();
+
+        let decoded = KeyValueRef::decode_from(&encoded).expect("Failed to decode the key_value reference");
+        assert_eq!(b"raft", decoded.key());
+        assert_eq!(b"consensus", decoded.value());
+        assert_eq!(encoded[KEY_VALUE_HEADER_SIZE..].as_ptr(), decoded.key().as_ptr());
+    }
+
+    #[test]
+    fn fails_to_decode_a_key_value_reference_given_truncated_buffer() {
+        let key_value = KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"));
+        let encoded = key_value.encode();
+
+        let decoded = KeyValueRef::decode_from(&encoded[..encoded.len() - 1]);
+        assert_eq!(true, decoded.is_err());
+    }
+
+    #[test]
+    fn encodes_and_decodes_a_tombstone() {
+        let key_value = KeyValue::tombstone(Vec::from(b"raft"));
+        let encoded = key_value.encode();
+
+        let decoded = KeyValue::decode_from(encoded).expect("Failed to decode the tombstone");
+        assert_eq!(true, decoded.is_tombstone());
+        assert_eq!(b"raft", &decoded.key[..]);
+        assert_eq!(true, decoded.value().is_empty());
+    }
+
+    #[test]
+    fn fails_to_decode_a_key_value_given_corrupted_value() {
+        let key_value = KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"));
+        let mut encoded = key_value.encode();
+        let last = encoded.len() - 1;
+        encoded[last] ^= 0xFF;
+
+        assert_eq!(true, KeyValue::decode_from(encoded).is_err());
+    }
+
+    #[test]
+    fn fails_to_decode_a_key_value_given_no_record_in_the_buffer() {
+        let buffer = [0u8; 32];
+        assert_eq!(true, KeyValueRef::decode_from(&buffer).is_err());
+    }
+
+    #[test]
+    fn iterates_over_encoded_key_values_until_the_first_invalid_record() {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus")).encode());
+        buffer.extend_from_slice(&KeyValue::tombstone(Vec::from(b"paxos")).encode());
+        buffer.extend_from_slice(&[0; 8]);
+
+        let mut key_value_refs = KeyRefs::new(&buffer);
+        let (position, first) = key_value_refs.next().unwrap();
+        assert_eq!(0, position);
+        assert_eq!(b"raft", first.key());
+
+        let (position, second) = key_value_refs.next().unwrap();
+        assert_eq!(first.encoded_size(), position);
+        assert_eq!(true, second.is_tombstone());
+
+        assert_eq!(true, key_value_refs.next().is_none());
+        assert_eq!(buffer.len() - 8, key_value_refs.position());
+    }
 }
\ No newline at end of file
@@ -1,68 +1,735 @@
-use std::io::Error;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
 
 use bytes::BytesMut;
 
-use crate::memory::key_value::KeyValue;
+use crate::memory::arena::{Arena, ArenaBacking};
+use crate::memory::compression::ValueCompression;
+use crate::memory::counter::{apply_delta, CounterEncoding, CounterError};
+use crate::memory::key_value::{KeyRef, KeyRefs, KeyValue, KeyValueRef};
 use crate::memory::index::{Index, IndexMarker};
 use crate::memory::options::LogOptions;
+use crate::memory::scan::{ScanPage, ScanRequest};
 use crate::memory::segment::Segment;
 
 pub(crate) struct Log {
     segments: Vec<Segment>,
     index: Index,
-    segment_tail: usize,
+    size_classes: Vec<SizeClass>,
+    free_segments: BTreeSet<usize>,
+    oldest_versions: Vec<u64>,
+    evicted_keys: Vec<Vec<u8>>,
+    arena: Arc<Arena>,
+    next_version: u64,
+    counter_encoding: CounterEncoding,
+    max_item_size: usize,
+    compression: ValueCompression,
+    compressed_values: u64,
+    uncompressed_value_bytes: u64,
+    compressed_value_bytes: u64,
+    undo_journal: Option<Vec<UndoEntry>>,
+    snapshot_active: bool,
+    write_ahead_log_position: Option<u64>,
+    rejected_appends: u64,
+    evictions: u64,
+    expirations: u64,
+}
+
+// Restores the log to where a transaction began, the journal undoes the index changes and in-place overwrites
+// while the segments of every size class are truncated back to the checkpointed tails.
+pub(crate) struct LogCheckpoint {
+    tails: Vec<(usize, usize)>,
+    next_version: u64,
+}
+
+// Items of similar sizes share segments, like the slabs of memcached. The segments are owned oldest first, the last
+// one is the tail taking the appends, and the pressure counts the appends that found no free segment.
+struct SizeClass {
+    max_item_size: usize,
+    segments: Vec<usize>,
+    pressure: u64,
+}
+
+enum UndoEntry {
+    Index(Vec<u8>, Option<IndexMarker>),
+    Bytes { segment_index: usize, segment_position: usize, bytes: Vec<u8> },
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum CompareAndSwapResult {
+    Swapped(u64),
+    VersionMismatch(u64),
+    KeyNotFound,
+    OutOfSpace,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum ConcatenateError {
+    KeyNotFound,
+    ItemTooLarge,
+    OutOfSpace,
+    Corrupted,
 }
 
 impl Log {
     pub(crate) fn new(options: LogOptions) -> Self {
+        let arena = Arena::new(options.arena_size(), options.huge_pages(), options.pre_fault());
+        Self::in_arena(Arc::new(arena), &options)
+    }
+
+    pub(crate) fn open(options: LogOptions) -> Result<Self, Error> {
+        let path = options
+            .backing_file()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "log options do not have a backing file"))?;
+
+        let arena = Arena::file_backed(path, options.arena_size())?;
+        let mut log = Self::in_arena(Arc::new(arena), &options);
+        log.recover();
+        Ok(log)
+    }
+
+    fn in_arena(arena: Arc<Arena>, options: &LogOptions) -> Self {
+        let segment_size = options.segment_size();
         Log {
-            segments: (1..=options.number_of_segments()).map(|_| Segment::new(options.segment_size())).collect(),
-            segment_tail: 0,
-            index: Index::new(),
+            segments: (0..options.number_of_segments())
+                .map(|segment_index| Segment::in_arena(arena.clone(), segment_index * segment_size, segment_size))
+                .collect(),
+            size_classes: options
+                .size_classes()
+                .into_iter()
+                .map(|max_item_size| SizeClass { max_item_size, segments: Vec::new(), pressure: 0 })
+                .collect(),
+            free_segments: (0..options.number_of_segments()).collect(),
+            oldest_versions: vec![u64::MAX; options.number_of_segments()],
+            evicted_keys: Vec::new(),
+            index: if options.ordered_index() { Index::ordered() } else { Index::new() },
+            arena,
+            next_version: 1,
+            counter_encoding: options.counter_encoding(),
+            max_item_size: options.max_item_size(),
+            compression: options.compression(),
+            compressed_values: 0,
+            uncompressed_value_bytes: 0,
+            compressed_value_bytes: 0,
+            undo_journal: None,
+            snapshot_active: false,
+            write_ahead_log_position: None,
+            rejected_appends: 0,
+            evictions: 0,
+            expirations: 0,
         }
     }
 
     pub(crate) fn try_append(&mut self, key_value: KeyValue) -> bool {
-        let encoded = key_value.encode();
-        let appended = self.try_append_to_segment(&encoded);
-        if let Some(segment_position) = appended {
+        self.try_append_expiring(key_value, None)
+    }
+
+    // Expiry deadlines are unix timestamps in milliseconds, kept in the index and written with the record so that
+    // recovery and snapshots restore them.
+    pub(crate) fn try_append_expiring(&mut self, key_value: KeyValue, expires_at: Option<u64>) -> bool {
+        self.try_append_versioned(key_value, expires_at).is_some()
+    }
+
+    pub(crate) fn try_compare_and_swap(
+        &mut self,
+        key_value: KeyValue,
+        expected_version: u64,
+        expires_at: Option<u64>,
+    ) -> CompareAndSwapResult {
+        match self.version_of(&key_value.key()) {
+            None => CompareAndSwapResult::KeyNotFound,
+            Some(version) if version != expected_version => CompareAndSwapResult::VersionMismatch(version),
+            Some(_) => self
+                .try_append_versioned(key_value, expires_at)
+                .map_or(CompareAndSwapResult::OutOfSpace, CompareAndSwapResult::Swapped),
+        }
+    }
+
+    pub(crate) fn try_increment(&mut self, key: &[u8], delta: i64) -> Result<i64, CounterError> {
+        let (counter, flags) = match self.try_get_ref(key) {
+            None => return Err(CounterError::KeyNotFound),
+            Some(Err(_)) => return Err(CounterError::NonNumericValue),
+            Some(Ok(key_value_ref)) => (self.counter_encoding.decode(key_value_ref.value())?, key_value_ref.flags()),
+        };
+        let counter = apply_delta(counter, delta)?;
+        let key_value = KeyValue::new(key.to_vec(), self.counter_encoding.encode(counter))
+            .with_flags(flags)
+            .with_version(self.next_version)
+            .with_expires_at(self.expires_at(key));
+
+        if !self.try_overwrite(&key_value) && self.try_append_versioned(key_value, self.expires_at(key)).is_none() {
+            return Err(CounterError::OutOfSpace);
+        }
+        Ok(counter)
+    }
+
+    pub(crate) fn try_append_to_value(&mut self, key: &[u8], suffix: &[u8]) -> Result<(), ConcatenateError> {
+        self.try_concatenate(key, |existing| [existing, suffix].concat())
+    }
+
+    pub(crate) fn try_prepend_to_value(&mut self, key: &[u8], prefix: &[u8]) -> Result<(), ConcatenateError> {
+        self.try_concatenate(key, |existing| [prefix, existing].concat())
+    }
+
+    pub(crate) fn contains_key(&self, key: &[u8]) -> bool {
+        self.index.get(key).is_some()
+    }
+
+    pub(crate) fn version_of(&self, key: &[u8]) -> Option<u64> {
+        self.index.get(key).map(|index_marker| index_marker.version)
+    }
+
+    pub(crate) fn expires_at(&self, key: &[u8]) -> Option<u64> {
+        self.index.get(key).and_then(|index_marker| index_marker.expires_at)
+    }
+
+    // The deadline of the record is overwritten in place with a new version, so a sliding expiration never copies
+    // the value while recovery and snapshots still restore the new deadline. A record written without a deadline,
+    // or one that can not be overwritten, is appended again with its value as stored.
+    pub(crate) fn try_touch(&mut self, key: &[u8], expires_at: Option<u64>) -> bool {
+        let Some(index_marker) = self.index.get(key).copied() else {
+            return false;
+        };
+        let version = self.next_version;
+        let record = self.segments[index_marker.segment_index]
+            .get(index_marker.segment_position, index_marker.key_value_size);
+        let Ok(key_ref) = KeyRef::decode_from(record) else {
+            return false;
+        };
+        let encoded = key_ref.encode_touched(version, expires_at);
+
+        self.journal_index(key);
+        if encoded.len() == index_marker.key_value_size && self.can_overwrite() {
+            self.journal_bytes(index_marker.segment_index, index_marker.segment_position, encoded.len());
+            self.segments[index_marker.segment_index].overwrite(index_marker.segment_position, &encoded);
+            self.next_version = version + 1;
+            if let Some(index_marker) = self.index.get_mut(key) {
+                index_marker.version = version;
+                index_marker.expires_at = expires_at;
+            }
+            return true;
+        }
+        let Some((segment_index, segment_position)) = self.try_append_to_segment(&encoded, version, true) else {
+            self.rejected_appends += 1;
+            return false;
+        };
+        self.next_version = version + 1;
+        self.index.insert(
+            key.to_vec(),
+            IndexMarker { expires_at, ..IndexMarker::new(segment_index, segment_position, encoded.len(), version) },
+        );
+        true
+    }
+
+    // Expired keys stay in the log until they are accessed, then they are deleted like an evicted key.
+    pub(crate) fn try_expire(&mut self, key: &[u8], now: u64) -> bool {
+        let due = self.expires_at(key).is_some_and(|expires_at| expires_at <= now);
+        if !due {
+            return false;
+        }
+        let expired = self.try_delete(key);
+        if expired {
+            self.expirations += 1;
+            self.evicted_keys.push(key.to_vec());
+        }
+        expired
+    }
+
+    // Appends a key moved from another log with the version it had there, so a version read before the move still
+    // compares equal. The versions this log assigns afterwards are above it.
+    pub(crate) fn try_append_migrated(&mut self, key_value: KeyValue, expires_at: Option<u64>) -> bool {
+        self.try_append_with_version(key_value, expires_at).is_some()
+    }
+
+    fn try_append_versioned(&mut self, key_value: KeyValue, expires_at: Option<u64>) -> Option<u64> {
+        let version = self.next_version;
+        self.try_append_with_version(key_value.with_version(version), expires_at)
+    }
+
+    fn try_append_with_version(&mut self, key_value: KeyValue, expires_at: Option<u64>) -> Option<u64> {
+        let key_value = key_value.with_expires_at(expires_at);
+        let encoded = key_value.encode_with(self.compression);
+        if !self.fits_in_an_item(&key_value, &encoded) {
+            self.rejected_appends += 1;
+            return None;
+        }
+        self.try_append_encoded(key_value, encoded)
+    }
+
+    fn try_append_encoded(&mut self, key_value: KeyValue, encoded: BytesMut) -> Option<u64> {
+        let version = key_value.version();
+        let appended = self.try_append_to_segment(&encoded, version, true);
+        if let Some((segment_index, segment_position)) = appended {
+            self.next_version = self.next_version.max(version + 1);
+            self.journal_index(&key_value.key());
             self.index.insert(
                 key_value.key(),
-                IndexMarker::new(self.segment_tail, segment_position, encoded.iter().len()),
+                IndexMarker {
+                    expires_at: key_value.expires_at(),
+                    ..IndexMarker::new(segment_index, segment_position, encoded.iter().len(), version)
+                },
             );
-            return true;
+            self.count_compression(&key_value, encoded.len());
+            return Some(version);
+        }
+        self.rejected_appends += 1;
+        return None;
+    }
+
+    fn try_concatenate<F>(&mut self, key: &[u8], concatenate: F) -> Result<(), ConcatenateError>
+        where F: FnOnce(&[u8]) -> Vec<u8> {
+        let (value, flags) = match self.try_get(key) {
+            None => return Err(ConcatenateError::KeyNotFound),
+            Some(Err(_)) => return Err(ConcatenateError::Corrupted),
+            Some(Ok(key_value)) => (concatenate(key_value.value()), key_value.flags()),
+        };
+        let key_value = KeyValue::new(key.to_vec(), value)
+            .with_flags(flags)
+            .with_version(self.next_version)
+            .with_expires_at(self.expires_at(key));
+        let encoded = key_value.encode_with(self.compression);
+        if !self.fits_in_an_item(&key_value, &encoded) {
+            return Err(ConcatenateError::ItemTooLarge);
+        }
+        self.try_append_encoded(key_value, encoded).map(|_| ()).ok_or(ConcatenateError::OutOfSpace)
+    }
+
+    fn fits_in_an_item(&self, key_value: &KeyValue, encoded: &BytesMut) -> bool {
+        key_value.value().len() <= u16::MAX as usize && encoded.len() <= self.max_item_size
+    }
+
+    fn count_compression(&mut self, key_value: &KeyValue, encoded_size: usize) {
+        let saved_bytes = key_value.encoded_size() - encoded_size;
+        if saved_bytes > 0 {
+            self.compressed_values += 1;
+            self.uncompressed_value_bytes += key_value.value().len() as u64;
+            self.compressed_value_bytes += (key_value.value().len() - saved_bytes) as u64;
+        }
+    }
+
+    fn try_overwrite(&mut self, key_value: &KeyValue) -> bool {
+        if !self.can_overwrite() {
+            return false;
+        }
+        let encoded = key_value.encode_with(self.compression);
+        let key = key_value.key();
+        let Some(index_marker) = self.index.get(&key).copied() else {
+            return false;
+        };
+        if index_marker.key_value_size != encoded.len() {
+            return false;
+        }
+        self.journal_index(&key);
+        self.journal_bytes(index_marker.segment_index, index_marker.segment_position, encoded.len());
+        self.segments[index_marker.segment_index].overwrite(index_marker.segment_position, &encoded);
+
+        let version = self.next_version();
+        if let Some(index_marker) = self.index.get_mut(&key) {
+            index_marker.version = version;
+        }
+        true
+    }
+
+    // A snapshot copies the segments up to their cutoffs and must not see later writes, a file-backed arena could be
+    // left with a torn record by a crash during the write. Both get the new record appended instead.
+    fn can_overwrite(&self) -> bool {
+        !self.snapshot_active && self.arena.backing() != ArenaBacking::File
+    }
+
+    fn next_version(&mut self) -> u64 {
+        let version = self.next_version;
+        self.next_version += 1;
+        version
+    }
+
+    pub(crate) fn try_delete(&mut self, key: &[u8]) -> bool {
+        if self.index.get(key).is_none() {
+            return false;
+        }
+        let version = self.next_version;
+        let encoded = KeyValue::tombstone(key.to_vec()).with_version(version).encode();
+        if self.try_append_to_segment(&encoded, version, true).is_none() {
+            return false;
+        }
+        self.next_version = version + 1;
+        self.journal_index(key);
+        self.index.remove(key);
+        true
+    }
+
+    pub(crate) fn begin_transaction(&mut self) -> LogCheckpoint {
+        assert!(self.undo_journal.is_none());
+        self.undo_journal = Some(Vec::new());
+        LogCheckpoint {
+            tails: self
+                .size_classes
+                .iter()
+                .map(|size_class| {
+                    let tail_length = size_class
+                        .segments
+                        .last()
+                        .map_or(0, |segment_index| self.segments[*segment_index].written().len());
+                    (size_class.segments.len(), tail_length)
+                })
+                .collect(),
+            next_version: self.next_version,
         }
-        return false;
+    }
+
+    pub(crate) fn commit_transaction(&mut self) {
+        self.undo_journal = None;
+    }
+
+    pub(crate) fn rollback_transaction(&mut self, checkpoint: LogCheckpoint) {
+        for undo_entry in self.undo_journal.take().unwrap_or_default().into_iter().rev() {
+            match undo_entry {
+                UndoEntry::Index(key, Some(index_marker)) => self.index.insert(key, index_marker),
+                UndoEntry::Index(key, None) => {
+                    self.index.remove(&key);
+                }
+                UndoEntry::Bytes { segment_index, segment_position, bytes } =>
+                    self.segments[segment_index].overwrite(segment_position, &bytes),
+            }
+        }
+        for (size_class, (claimed, tail_length)) in self.size_classes.iter_mut().zip(checkpoint.tails) {
+            for segment_index in size_class.segments.drain(claimed..) {
+                self.segments[segment_index].truncate(0);
+                self.oldest_versions[segment_index] = u64::MAX;
+                self.free_segments.insert(segment_index);
+            }
+            if let Some(segment_index) = size_class.segments.last() {
+                self.segments[*segment_index].truncate(tail_length);
+            }
+        }
+        self.next_version = checkpoint.next_version;
+    }
+
+    fn journal_index(&mut self, key: &[u8]) {
+        if let Some(undo_journal) = self.undo_journal.as_mut() {
+            undo_journal.push(UndoEntry::Index(key.to_vec(), self.index.get(key).copied()));
+        }
+    }
+
+    fn journal_bytes(&mut self, segment_index: usize, segment_position: usize, size: usize) {
+        if let Some(undo_journal) = self.undo_journal.as_mut() {
+            let bytes = self.segments[segment_index].get(segment_position, size).to_vec();
+            undo_journal.push(UndoEntry::Bytes { segment_index, segment_position, bytes });
+        }
+    }
+
+    // Set by the owner of an incremental snapshot for as long as the snapshot is being written.
+    pub(crate) fn set_snapshot_active(&mut self, snapshot_active: bool) {
+        self.snapshot_active = snapshot_active;
+    }
+
+    // The position of the write-ahead log up to which a restored log holds its writes, the write-ahead log is
+    // replayed from there. None for a log that does not know which writes it holds.
+    pub(crate) fn write_ahead_log_position(&self) -> Option<u64> {
+        self.write_ahead_log_position
+    }
+
+    pub(crate) fn set_write_ahead_log_position(&mut self, write_ahead_log_position: Option<u64>) {
+        self.write_ahead_log_position = write_ahead_log_position;
+    }
+
+    pub(crate) fn flush(&self) -> Result<(), Error> {
+        self.arena.flush()
     }
 
     pub(crate) fn try_get(&self, key: &[u8]) -> Option<Result<KeyValue, Error>> {
+        self.try_get_ref(key)
+            .map(|key_value_ref| key_value_ref.map(|key_value_ref| key_value_ref.to_key_value()))
+    }
+
+    pub(crate) fn try_get_ref(&self, key: &[u8]) -> Option<Result<KeyValueRef<'_>, Error>> {
         self.index
             .get(key)
             .map(|index_marker| self.
                 segments[index_marker.segment_index].
                 get(index_marker.segment_position, index_marker.key_value_size))
-            .map(|raw_value| BytesMut::from(raw_value))
-            .map(|bytes| KeyValue::decode_from(bytes))
+            .map(KeyValueRef::decode_from)
+    }
+
+    pub(crate) fn scan(&self, request: &ScanRequest) -> Option<ScanPage> {
+        self.index.scan(request)
+    }
+
+    pub(crate) fn scan_keyspace(&self, cursor: u64, count: usize) -> (Vec<Vec<u8>>, u64) {
+        self.index.scan_keys(cursor, count)
     }
 
-    fn try_append_to_segment(&mut self, encoded: &BytesMut) -> Option<usize> {
-        let appended = self.segments[self.segment_tail].try_append(&encoded);
-        if let Some(segment_position) = appended {
-            return Some(segment_position);
+    pub(crate) fn index_size(&self) -> usize {
+        self.index.len()
+    }
+
+    pub(crate) fn segment_bytes(&self) -> Vec<usize> {
+        self.segments.iter().map(|segment| segment.written().len()).collect()
+    }
+
+    // The arena is reserved up front, the log uses the bytes written to its segments. Deleted and overwritten items
+    // keep their bytes until the segment holding them is evicted.
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.segments.iter().map(|segment| segment.written().len()).sum::<usize>() + self.index.memory_usage()
+    }
+
+    // The live keys sharing a segment with the key, evicting the key frees that segment. None when the key is not
+    // stored or the segment can not be evicted.
+    pub(crate) fn keys_evicted_with(&self, key: &[u8]) -> Option<Vec<Vec<u8>>> {
+        if !self.can_evict() {
+            return None;
         }
-        if self.segment_tail == self.segments.len() - 1 {
+        self.index.get(key).map(|index_marker| self.live_keys_of(index_marker.segment_index))
+    }
+
+    // Empties the segment holding the key and evicts its live items, except the retained ones which are written again
+    // with their versions and deadlines. They and the tombstones left in the segment take no more room than the
+    // segment held, so they always fit back into the log.
+    pub(crate) fn try_evict(&mut self, key: &[u8], retained: &[Vec<u8>]) -> bool {
+        if !self.can_evict() {
+            return false;
+        }
+        let Some(segment_index) = self.index.get(key).map(|index_marker| index_marker.segment_index) else {
+            return false;
+        };
+        let Some(class) = self
+            .size_classes
+            .iter()
+            .position(|size_class| size_class.segments.contains(&segment_index))
+        else {
+            return false;
+        };
+        let retained: Vec<(Vec<u8>, Vec<u8>, IndexMarker)> = retained
+            .iter()
+            .filter(|retained_key| retained_key.as_slice() != key)
+            .filter_map(|retained_key| {
+                let index_marker = self.index.get(retained_key).filter(|marker| marker.segment_index == segment_index)?;
+                let encoded = self.segments[segment_index].get(index_marker.segment_position, index_marker.key_value_size);
+                Some((retained_key.clone(), encoded.to_vec(), *index_marker))
+            })
+            .collect();
+        self.size_classes[class].segments.retain(|claimed| *claimed != segment_index);
+        for (retained_key, _, _) in &retained {
+            self.index.remove(retained_key);
+        }
+        self.evict_segment(segment_index);
+        if self.segments[segment_index].is_empty() {
+            self.free_segments.insert(segment_index);
+        } else {
+            self.size_classes[class].segments.push(segment_index);
+        }
+        // Copied as stored, a compressed value is not decompressed to be written again.
+        for (retained_key, encoded, index_marker) in retained {
+            let appended = self.try_append_to_segment(&encoded, index_marker.version, false);
+            if let Some((segment_index, segment_position)) = appended {
+                self.index.insert(retained_key, IndexMarker { segment_index, segment_position, ..index_marker });
+            }
+        }
+        true
+    }
+
+    // Evicting a segment can not be undone by a transaction and would rewrite a segment a snapshot has yet to copy.
+    fn can_evict(&self) -> bool {
+        self.undo_journal.is_none() && !self.snapshot_active
+    }
+
+    // Keys evicted or expired since the last call, evictions happen on request or because a segment moved to
+    // another size class.
+    pub(crate) fn take_evicted_keys(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.evicted_keys)
+    }
+
+    pub(crate) fn size_class_segments(&self) -> Vec<usize> {
+        self.size_classes.iter().map(|size_class| size_class.segments.len()).collect()
+    }
+
+    pub(crate) fn compressed_values(&self) -> u64 {
+        self.compressed_values
+    }
+
+    pub(crate) fn uncompressed_value_bytes(&self) -> u64 {
+        self.uncompressed_value_bytes
+    }
+
+    pub(crate) fn compressed_value_bytes(&self) -> u64 {
+        self.compressed_value_bytes
+    }
+
+    pub(crate) fn rejected_appends(&self) -> u64 {
+        self.rejected_appends
+    }
+
+    pub(crate) fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    pub(crate) fn expirations(&self) -> u64 {
+        self.expirations
+    }
+
+    pub(crate) fn number_of_segments(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub(crate) fn segment(&self, segment_index: usize) -> &Segment {
+        &self.segments[segment_index]
+    }
+
+    // Segments are reused in any order and size classes append side by side, so the record with the highest version
+    // of a key wins wherever it is stored, and a tombstone deletes the older records of its key.
+    fn recover(&mut self) {
+        let mut latest: HashMap<Vec<u8>, (u64, Option<IndexMarker>)> = HashMap::new();
+        for segment_index in 0..self.segments.len() {
+            let mut key_refs = KeyRefs::new(self.segments[segment_index].contents());
+            let (mut first_item_size, mut first_tombstone_size) = (None, None);
+            let mut oldest_version = u64::MAX;
+            for (position, key_ref) in key_refs.by_ref() {
+                let version = key_ref.version();
+                oldest_version = oldest_version.min(version);
+                self.next_version = self.next_version.max(version + 1);
+                if key_ref.is_tombstone() {
+                    first_tombstone_size.get_or_insert(key_ref.encoded_size());
+                } else {
+                    first_item_size.get_or_insert(key_ref.encoded_size());
+                }
+                if latest.get(key_ref.key()).is_some_and(|(latest_version, _)| *latest_version > version) {
+                    continue;
+                }
+                let index_marker = (!key_ref.is_tombstone()).then(|| IndexMarker {
+                    expires_at: key_ref.expires_at(),
+                    ..IndexMarker::new(segment_index, position, key_ref.encoded_size(), version)
+                });
+                latest.insert(key_ref.key().to_vec(), (version, index_marker));
+            }
+            let length = key_refs.position();
+            // An evicted segment starts with the tombstones of its items, the items written after them decide the class.
+            if let Some(item_size) = first_item_size.or(first_tombstone_size) {
+                let class = self.class_of(item_size);
+                self.size_classes[class].segments.push(segment_index);
+                self.free_segments.remove(&segment_index);
+            }
+            self.oldest_versions[segment_index] = oldest_version;
+            self.segments[segment_index].restore_length(length);
+        }
+        for (key, (_, index_marker)) in latest {
+            if let Some(index_marker) = index_marker {
+                self.index.insert(key, index_marker);
+            }
+        }
+    }
+
+    fn class_of(&self, item_size: usize) -> usize {
+        self.size_classes
+            .iter()
+            .position(|size_class| item_size <= size_class.max_item_size)
+            .unwrap_or(self.size_classes.len() - 1)
+    }
+
+    fn try_append_to_segment(&mut self, encoded: &[u8], version: u64, rebalance: bool) -> Option<(usize, usize)> {
+        let class = self.class_of(encoded.len());
+        let tail = self.size_classes[class].segments.last().copied();
+        if let Some(appended) = tail.and_then(|segment_index| self.try_append_to(segment_index, encoded, version)) {
+            return Some(appended);
+        }
+        let segment_index = match self.free_segments.pop_first() {
+            Some(segment_index) => segment_index,
+            None if rebalance => self.rebalance_into(class)?,
+            None => return None,
+        };
+        self.size_classes[class].segments.push(segment_index);
+        self.try_append_to(segment_index, encoded, version)
+    }
+
+    fn try_append_to(&mut self, segment_index: usize, encoded: &[u8], version: u64) -> Option<(usize, usize)> {
+        let segment_position = self.segments[segment_index].try_append(encoded)?;
+        self.oldest_versions[segment_index] = self.oldest_versions[segment_index].min(version);
+        Some((segment_index, segment_position))
+    }
+
+    // Moves the oldest segment of the class under the least pressure to a class that ran out of segments, evicting
+    // the live items of that segment. Neither a transaction nor a snapshot in progress lets it evict.
+    fn rebalance_into(&mut self, class: usize) -> Option<usize> {
+        self.size_classes[class].pressure += 1;
+        if !self.can_evict() {
             return None;
         }
-        self.segment_tail += 1;
-        return self.segments[self.segment_tail].try_append(&encoded);
+        let pressure = self.size_classes[class].pressure;
+        let victim = (0..self.size_classes.len())
+            .filter(|victim| *victim != class)
+            .filter(|victim| self.size_classes[*victim].segments.len() > 1)
+            .filter(|victim| self.size_classes[*victim].pressure < pressure)
+            .min_by_key(|victim| {
+                let size_class = &self.size_classes[*victim];
+                (size_class.pressure, Reverse(size_class.segments.len()))
+            })?;
+        let segment_index = self.size_classes[victim].segments.remove(0);
+        self.evict_segment(segment_index);
+        for size_class in self.size_classes.iter_mut() {
+            size_class.pressure /= 2;
+        }
+        Some(segment_index)
+    }
+
+    fn live_keys_of(&self, segment_index: usize) -> Vec<Vec<u8>> {
+        KeyRefs::new(self.segments[segment_index].written())
+            .filter(|(segment_position, key_ref)| {
+                self.index.get(key_ref.key()).is_some_and(|index_marker| {
+                    index_marker.segment_index == segment_index && index_marker.segment_position == *segment_position
+                })
+            })
+            .map(|(_, key_ref)| key_ref.key().to_vec())
+            .collect()
+    }
+
+    // Older records of the evicted keys in other segments would come back on recovery or from a snapshot, so the
+    // segment is left holding a tombstone for each of them. A tombstone takes no more room than the item it replaces
+    // and the tombstones of deleted keys are kept the same way, so they always fit. A tombstone is dropped once no
+    // other segment holds a record older than it.
+    fn evict_segment(&mut self, segment_index: usize) {
+        let evicted_keys = self.live_keys_of(segment_index);
+        let oldest_elsewhere = (0..self.segments.len())
+            .filter(|other| *other != segment_index)
+            .map(|other| self.oldest_versions[other])
+            .min()
+            .unwrap_or(u64::MAX);
+        let tombstones: Vec<(Vec<u8>, u64)> = KeyRefs::new(self.segments[segment_index].written())
+            .filter(|(_, key_ref)| key_ref.is_tombstone() && self.index.get(key_ref.key()).is_none())
+            .map(|(_, key_ref)| (key_ref.key().to_vec(), key_ref.version()))
+            .chain(evicted_keys.iter().filter_map(|key| self.version_of(key).map(|version| (key.clone(), version))))
+            .filter(|(_, version)| *version > oldest_elsewhere)
+            .collect();
+        self.segments[segment_index].truncate(0);
+        self.oldest_versions[segment_index] = u64::MAX;
+        for key in &evicted_keys {
+            self.index.remove(key);
+        }
+        for (key, version) in tombstones {
+            let tombstone = KeyValue::tombstone(key).with_version(version).encode();
+            assert!(self.try_append_to(segment_index, &tombstone, version).is_some());
+        }
+        self.evictions += evicted_keys.len() as u64;
+        self.evicted_keys.extend(evicted_keys);
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::memory::compression::{CompressionAlgorithm, ValueCompression};
+    use crate::memory::counter::{CounterEncoding, CounterError};
     use crate::memory::key_value::KeyValue;
-    use crate::memory::log::Log;
+    use crate::memory::log::{CompareAndSwapResult, ConcatenateError, Log};
     use crate::memory::options::LogOptions;
+    use crate::memory::scan::ScanRequest;
+
+    fn backing_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("memcore-log-{}-{}.data", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
 
     #[test]
     fn should_append_to_the_log() {
@@ -94,4 +761,672 @@ mod tests {
         let key_value = log.try_get(b"raft").unwrap().unwrap();
         assert_eq!(b"consensus", key_value.value());
     }
+
+    #[test]
+    fn get_reference_from_log() {
+        let log_size_bytes = 32;
+        let segment_size_bytes = 32;
+
+        let mut log = Log::new(LogOptions::new(log_size_bytes, segment_size_bytes));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+
+        let key_value_ref = log.try_get_ref(b"raft").unwrap().unwrap();
+        assert_eq!(b"raft", key_value_ref.key());
+        assert_eq!(b"consensus", key_value_ref.value());
+    }
+
+    #[test]
+    fn get_reference_from_log_for_a_non_existing_key() {
+        let log_size_bytes = 32;
+        let segment_size_bytes = 32;
+
+        let log = Log::new(LogOptions::new(log_size_bytes, segment_size_bytes));
+        assert_eq!(true, log.try_get_ref(b"raft").is_none());
+    }
+
+    #[test]
+    fn get_from_log_spanning_multiple_segments_of_the_arena() {
+        let log_size_bytes = 64;
+        let segment_size_bytes = 32;
+
+        let mut log = Log::new(LogOptions::new(log_size_bytes, segment_size_bytes).with_pre_fault());
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus"))));
+
+        let key_value = log.try_get(b"paxos").unwrap().unwrap();
+        assert_eq!(b"consensus", key_value.value());
+    }
+
+    #[test]
+    fn delete_from_log() {
+        let log_size_bytes = 64;
+        let segment_size_bytes = 64;
+
+        let mut log = Log::new(LogOptions::new(log_size_bytes, segment_size_bytes));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_delete(b"raft"));
+
+        assert_eq!(true, log.try_get(b"raft").is_none());
+    }
+
+    #[test]
+    fn delete_a_non_existing_key_from_log() {
+        let log_size_bytes = 64;
+        let segment_size_bytes = 64;
+
+        let mut log = Log::new(LogOptions::new(log_size_bytes, segment_size_bytes));
+        assert_eq!(false, log.try_delete(b"raft"));
+    }
+
+    #[test]
+    fn fail_to_open_log_without_backing_file() {
+        let log = Log::open(LogOptions::new(64, 32));
+        assert_eq!(true, log.is_err());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn warm_restart_from_backing_file() {
+        let path = backing_file("warm-restart");
+        {
+            let mut log = Log::open(LogOptions::new(160, 32).with_backing_file(path.clone())).unwrap();
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus"))));
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"leader"))));
+            assert_eq!(true, log.try_delete(b"paxos"));
+            log.flush().unwrap();
+        }
+
+        let mut log = Log::open(LogOptions::new(160, 32).with_backing_file(path.clone())).unwrap();
+        assert_eq!(b"leader", log.try_get(b"raft").unwrap().unwrap().value());
+        assert_eq!(true, log.try_get(b"paxos").is_none());
+
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"zab"), Vec::from(b"atomic"))));
+        assert_eq!(b"atomic", log.try_get(b"zab").unwrap().unwrap().value());
+        assert_eq!(b"leader", log.try_get(b"raft").unwrap().unwrap().value());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn warm_restart_ignores_a_torn_record() {
+        let path = backing_file("torn-record");
+        {
+            let mut log = Log::open(LogOptions::new(64, 64).with_backing_file(path.clone())).unwrap();
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus"))));
+            log.flush().unwrap();
+        }
+        let mut contents = fs::read(&path).unwrap();
+        contents[40] ^= 0xFF;
+        fs::write(&path, contents).unwrap();
+
+        let log = Log::open(LogOptions::new(64, 64).with_backing_file(path.clone())).unwrap();
+        assert_eq!(b"consensus", log.try_get(b"raft").unwrap().unwrap().value());
+        assert_eq!(true, log.try_get(b"paxos").is_none());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn versions_increase_monotonically_with_every_append() {
+        let mut log = Log::new(LogOptions::new(128, 64));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"leader"))));
+
+        assert_eq!(Some(3), log.version_of(b"raft"));
+        assert_eq!(Some(2), log.version_of(b"paxos"));
+        assert_eq!(None, log.version_of(b"zab"));
+    }
+
+    #[test]
+    fn compare_and_swap_given_matching_version() {
+        let mut log = Log::new(LogOptions::new(128, 64));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+
+        let result = log.try_compare_and_swap(KeyValue::new(Vec::from(b"raft"), Vec::from(b"leader")), 1, None);
+        assert_eq!(CompareAndSwapResult::Swapped(2), result);
+        assert_eq!(b"leader", log.try_get(b"raft").unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn compare_and_swap_given_version_mismatch() {
+        let mut log = Log::new(LogOptions::new(128, 64));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"leader"))));
+
+        let result = log.try_compare_and_swap(KeyValue::new(Vec::from(b"raft"), Vec::from(b"follower")), 1, None);
+        assert_eq!(CompareAndSwapResult::VersionMismatch(2), result);
+        assert_eq!(b"leader", log.try_get(b"raft").unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn compare_and_swap_given_non_existing_key() {
+        let mut log = Log::new(LogOptions::new(128, 64));
+
+        let result = log.try_compare_and_swap(KeyValue::new(Vec::from(b"raft"), Vec::from(b"leader")), 1, None);
+        assert_eq!(CompareAndSwapResult::KeyNotFound, result);
+    }
+
+    #[test]
+    fn compare_and_swap_given_no_space() {
+        let mut log = Log::new(LogOptions::new(32, 32));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+
+        let result = log.try_compare_and_swap(KeyValue::new(Vec::from(b"raft"), Vec::from(b"leader")), 1, None);
+        assert_eq!(CompareAndSwapResult::OutOfSpace, result);
+    }
+
+    #[test]
+    fn keep_the_version_of_a_migrated_key() {
+        let mut log = Log::new(LogOptions::new(256, 128));
+        let key_value = KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus")).with_version(42);
+        assert_eq!(true, log.try_append_migrated(key_value, Some(100)));
+        assert_eq!(Some(42), log.version_of(b"raft"));
+        assert_eq!(Some(100), log.expires_at(b"raft"));
+
+        let result = log.try_compare_and_swap(KeyValue::new(Vec::from(b"raft"), Vec::from(b"leader")), 42, None);
+        assert_eq!(CompareAndSwapResult::Swapped(43), result);
+        let key_value = KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus")).with_version(7);
+        assert_eq!(true, log.try_append_migrated(key_value, None));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"zab"), Vec::from(b"atomic"))));
+        assert_eq!(Some(44), log.version_of(b"zab"));
+    }
+
+    #[test]
+    fn increment_a_decimal_counter_in_place() {
+        let mut log = Log::new(LogOptions::new(32, 32));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"hits"), Vec::from(b"10"))));
+
+        assert_eq!(Ok(15), log.try_increment(b"hits", 5));
+        assert_eq!(Ok(11), log.try_increment(b"hits", -4));
+        assert_eq!(b"11", log.try_get(b"hits").unwrap().unwrap().value());
+        assert_eq!(Some(3), log.version_of(b"hits"));
+    }
+
+    #[test]
+    fn increment_a_decimal_counter_by_appending_given_the_width_grows() {
+        let mut log = Log::new(LogOptions::new(64, 64));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"hits"), Vec::from(b"99"))));
+
+        assert_eq!(Ok(100), log.try_increment(b"hits", 1));
+        assert_eq!(b"100", log.try_get(b"hits").unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn increment_a_little_endian_counter_in_place() {
+        let mut log = Log::new(LogOptions::new(32, 32).with_counter_encoding(CounterEncoding::LittleEndian));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"hits"), 99i64.to_le_bytes().to_vec())));
+
+        assert_eq!(Ok(1099), log.try_increment(b"hits", 1000));
+        assert_eq!(1099i64.to_le_bytes(), log.try_get(b"hits").unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn increment_a_counter_by_appending_while_a_snapshot_is_active() {
+        let mut log = Log::new(LogOptions::new(64, 64));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"hits"), Vec::from(b"10"))));
+        log.set_snapshot_active(true);
+
+        assert_eq!(Ok(15), log.try_increment(b"hits", 5));
+        assert_eq!(vec![48], log.segment_bytes());
+        assert_eq!(b"15", log.try_get(b"hits").unwrap().unwrap().value());
+
+        log.set_snapshot_active(false);
+        assert_eq!(Ok(16), log.try_increment(b"hits", 1));
+        assert_eq!(vec![48], log.segment_bytes());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn increment_a_counter_by_appending_on_a_file_backed_log() {
+        let path = backing_file("counter");
+        {
+            let mut log = Log::open(LogOptions::new(64, 64).with_backing_file(path.clone())).unwrap();
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"hits"), Vec::from(b"10"))));
+
+            assert_eq!(Ok(15), log.try_increment(b"hits", 5));
+            assert_eq!(vec![48], log.segment_bytes());
+            log.flush().unwrap();
+        }
+
+        let log = Log::open(LogOptions::new(64, 64).with_backing_file(path.clone())).unwrap();
+        assert_eq!(b"15", log.try_get(b"hits").unwrap().unwrap().value());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn fail_to_increment_a_counter() {
+        let mut log = Log::new(LogOptions::new(128, 64));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"hits"), i64::MAX.to_string().into_bytes())));
+
+        assert_eq!(Err(CounterError::KeyNotFound), log.try_increment(b"paxos", 1));
+        assert_eq!(Err(CounterError::NonNumericValue), log.try_increment(b"raft", 1));
+        assert_eq!(Err(CounterError::Overflow), log.try_increment(b"hits", 1));
+    }
+
+    #[test]
+    fn fail_to_increment_a_counter_given_no_space() {
+        let mut log = Log::new(LogOptions::new(24, 24));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"hits"), Vec::from(b"9"))));
+
+        assert_eq!(Err(CounterError::OutOfSpace), log.try_increment(b"hits", 1));
+        assert_eq!(b"9", log.try_get(b"hits").unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn should_not_append_an_item_larger_than_the_max_item_size() {
+        let mut log = Log::new(LogOptions::new(64, 64).with_max_item_size(24));
+        assert_eq!(false, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"ok"))));
+    }
+
+    #[test]
+    fn append_and_prepend_to_an_existing_value() {
+        let mut log = Log::new(LogOptions::new(128, 64));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"events"), Vec::from(b"b"))));
+
+        assert_eq!(Ok(()), log.try_append_to_value(b"events", b"c"));
+        assert_eq!(Ok(()), log.try_prepend_to_value(b"events", b"a"));
+        assert_eq!(b"abc", log.try_get(b"events").unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn fail_to_append_to_a_non_existing_value() {
+        let mut log = Log::new(LogOptions::new(64, 64));
+        assert_eq!(Err(ConcatenateError::KeyNotFound), log.try_append_to_value(b"events", b"c"));
+        assert_eq!(Err(ConcatenateError::KeyNotFound), log.try_prepend_to_value(b"events", b"a"));
+    }
+
+    #[test]
+    fn fail_to_append_to_a_value_given_the_result_exceeds_the_max_item_size() {
+        let mut log = Log::new(LogOptions::new(128, 64).with_max_item_size(28));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"events"), Vec::from(b"abcd"))));
+
+        assert_eq!(Err(ConcatenateError::ItemTooLarge), log.try_append_to_value(b"events", b"e"));
+        assert_eq!(b"abcd", log.try_get(b"events").unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn fail_to_append_to_a_value_given_no_space() {
+        let mut log = Log::new(LogOptions::new(32, 32));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"events"), Vec::from(b"abcd"))));
+
+        assert_eq!(Err(ConcatenateError::OutOfSpace), log.try_append_to_value(b"events", b"e"));
+    }
+
+    #[test]
+    fn rollback_a_transaction() {
+        let mut log = Log::new(LogOptions::new(256, 64));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"counter"), Vec::from(b"10"))));
+
+        let checkpoint = log.begin_transaction();
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"leader"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus"))));
+        assert_eq!(Ok(11), log.try_increment(b"counter", 1));
+        assert_eq!(true, log.try_delete(b"raft"));
+        log.rollback_transaction(checkpoint);
+
+        assert_eq!(b"consensus", log.try_get(b"raft").unwrap().unwrap().value());
+        assert_eq!(b"10", log.try_get(b"counter").unwrap().unwrap().value());
+        assert_eq!(true, log.try_get(b"paxos").is_none());
+        assert_eq!(Some(1), log.version_of(b"raft"));
+        assert_eq!(Some(2), log.version_of(b"counter"));
+    }
+
+    #[test]
+    fn rollback_reclaims_the_appended_space() {
+        let mut log = Log::new(LogOptions::new(64, 32));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+
+        let checkpoint = log.begin_transaction();
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus"))));
+        log.rollback_transaction(checkpoint);
+
+        assert_eq!(true, log.segment(1).is_empty());
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus"))));
+    }
+
+    #[test]
+    fn commit_a_transaction() {
+        let mut log = Log::new(LogOptions::new(64, 64));
+        log.begin_transaction();
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+        log.commit_transaction();
+
+        assert_eq!(b"consensus", log.try_get(b"raft").unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn scan_an_ordered_log() {
+        let mut log = Log::new(LogOptions::new(128, 128).with_ordered_index());
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"session:2"), Vec::from(b"b"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"session:1"), Vec::from(b"a"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"tenant:1"), Vec::from(b"c"))));
+
+        let page = log.scan(&ScanRequest::new(10).with_prefix(Vec::from(b"session:"))).unwrap();
+        assert_eq!(vec![Vec::from(b"session:1"), Vec::from(b"session:2")], page.keys);
+    }
+
+    #[test]
+    fn do_not_scan_a_log_without_an_ordered_index() {
+        let log = Log::new(LogOptions::new(64, 64));
+        assert_eq!(true, log.scan(&ScanRequest::new(10)).is_none());
+    }
+
+    #[test]
+    fn count_the_rejected_appends() {
+        let mut log = Log::new(LogOptions::new(32, 32));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+        assert_eq!(false, log.try_append(KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus"))));
+
+        assert_eq!(1, log.rejected_appends());
+        assert_eq!(1, log.index_size());
+        assert_eq!(vec![31], log.segment_bytes());
+    }
+
+    #[test]
+    fn evict_a_key_and_count_the_eviction() {
+        let mut log = Log::new(LogOptions::new(64, 64));
+        let empty = log.memory_usage();
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.memory_usage() > empty);
+
+        assert_eq!(Some(vec![Vec::from(b"raft")]), log.keys_evicted_with(b"raft"));
+        assert_eq!(true, log.try_evict(b"raft", &[]));
+        assert_eq!(false, log.try_evict(b"raft", &[]));
+        assert_eq!(1, log.evictions());
+        assert_eq!(empty, log.memory_usage());
+        assert_eq!(true, log.try_get(b"raft").is_none());
+    }
+
+    #[test]
+    fn evict_a_segment_and_keep_the_retained_keys() {
+        let mut log = Log::new(LogOptions::new(256, 128));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append_expiring(KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus")), Some(7)));
+        let version = log.version_of(b"paxos");
+
+        assert_eq!(Some(vec![Vec::from(b"raft"), Vec::from(b"paxos")]), log.keys_evicted_with(b"raft"));
+        assert_eq!(true, log.try_evict(b"raft", &[Vec::from(b"paxos")]));
+        assert_eq!(1, log.evictions());
+        assert_eq!(vec![Vec::from(b"raft")], log.take_evicted_keys());
+        assert_eq!(true, log.try_get(b"raft").is_none());
+        assert_eq!(b"consensus", log.try_get(b"paxos").unwrap().unwrap().value());
+        assert_eq!(version, log.version_of(b"paxos"));
+        assert_eq!(Some(7), log.expires_at(b"paxos"));
+        assert_eq!(vec![40, 0], log.segment_bytes());
+    }
+
+    #[test]
+    fn do_not_evict_a_segment_while_a_snapshot_is_active() {
+        let mut log = Log::new(LogOptions::new(64, 64));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+        log.set_snapshot_active(true);
+
+        assert_eq!(None, log.keys_evicted_with(b"raft"));
+        assert_eq!(false, log.try_evict(b"raft", &[]));
+        assert_eq!(true, log.try_get(b"raft").is_some());
+    }
+
+    #[test]
+    fn items_of_different_size_classes_go_to_different_segments() {
+        let mut log = Log::new(LogOptions::new(192, 64).with_size_classes(vec![32]));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), vec![1; 40])));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"zab"), Vec::from(b"consensus"))));
+
+        assert_eq!(vec![1, 1], log.size_class_segments());
+        assert_eq!(vec![61, 63, 0], log.segment_bytes());
+        assert_eq!(vec![1; 40], log.try_get(b"paxos").unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn move_a_segment_to_the_size_class_under_eviction_pressure() {
+        let mut log = Log::new(LogOptions::new(192, 64).with_size_classes(vec![32]));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"ra00"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), vec![1; 40])));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"multi"), vec![2; 40])));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"ra01"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"ra02"), Vec::from(b"consensus"))));
+
+        assert_eq!(vec![2, 1], log.size_class_segments());
+        assert_eq!(1, log.evictions());
+        assert_eq!(vec![Vec::from(b"paxos")], log.take_evicted_keys());
+        assert_eq!(true, log.try_get(b"paxos").is_none());
+        assert_eq!(vec![2; 40], log.try_get(b"multi").unwrap().unwrap().value());
+        assert_eq!(b"consensus", log.try_get(b"ra02").unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn keep_the_last_segment_of_a_size_class() {
+        let mut log = Log::new(LogOptions::new(128, 64).with_size_classes(vec![32]));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"ra00"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), vec![1; 40])));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"ra01"), Vec::from(b"consensus"))));
+        assert_eq!(false, log.try_append(KeyValue::new(Vec::from(b"ra02"), Vec::from(b"consensus"))));
+
+        assert_eq!(0, log.evictions());
+        assert_eq!(vec![1; 40], log.try_get(b"paxos").unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn rollback_returns_the_claimed_segments_of_every_size_class() {
+        let mut log = Log::new(LogOptions::new(192, 64).with_size_classes(vec![32]));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+
+        let checkpoint = log.begin_transaction();
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"zab"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), vec![1; 40])));
+        log.rollback_transaction(checkpoint);
+
+        assert_eq!(vec![1, 0], log.size_class_segments());
+        assert_eq!(vec![31, 0, 0], log.segment_bytes());
+        assert_eq!(true, log.try_get(b"paxos").is_none());
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), vec![1; 40])));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"multi"), vec![2; 40])));
+    }
+
+    #[test]
+    fn recover_the_segments_of_every_size_class() {
+        let path = backing_file("size-classes");
+        {
+            let mut log = Log::open(LogOptions::new(192, 64).with_size_classes(vec![32]).with_backing_file(path.clone())).unwrap();
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), vec![1; 40])));
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+            log.flush().unwrap();
+        }
+
+        let mut log = Log::open(LogOptions::new(192, 64).with_size_classes(vec![32]).with_backing_file(path.clone())).unwrap();
+        assert_eq!(vec![1, 1], log.size_class_segments());
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"zab"), Vec::from(b"consensus"))));
+        assert_eq!(vec![63, 61, 0], log.segment_bytes());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn recover_the_latest_version_of_a_key_written_to_an_earlier_segment() {
+        let path = backing_file("versions");
+        let options = || LogOptions::new(192, 64).with_size_classes(vec![32]).with_backing_file(path.clone());
+        {
+            let mut log = Log::open(options()).unwrap();
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), vec![1; 40])));
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"leader"))));
+            log.flush().unwrap();
+        }
+
+        let mut log = Log::open(options()).unwrap();
+        assert_eq!(b"leader", log.try_get(b"raft").unwrap().unwrap().value());
+        assert_eq!(Some(3), log.version_of(b"raft"));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus"))));
+        assert_eq!(Some(4), log.version_of(b"paxos"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn keep_a_key_evicted_with_its_segment_deleted_on_recovery() {
+        let path = backing_file("evicted");
+        let options = || LogOptions::new(192, 64).with_size_classes(vec![32]).with_backing_file(path.clone());
+        {
+            let mut log = Log::open(options()).unwrap();
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus"))));
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), vec![1; 40])));
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"multi"), vec![2; 40])));
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"ra01"), Vec::from(b"consensus"))));
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"ra02"), Vec::from(b"consensus"))));
+            assert_eq!(vec![Vec::from(b"paxos")], log.take_evicted_keys());
+            log.flush().unwrap();
+        }
+
+        let log = Log::open(options()).unwrap();
+        assert_eq!(true, log.try_get(b"paxos").is_none());
+        assert_eq!(b"consensus", log.try_get(b"ra02").unwrap().unwrap().value());
+        assert_eq!(vec![2; 40], log.try_get(b"multi").unwrap().unwrap().value());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn do_not_move_a_segment_between_size_classes_while_a_snapshot_is_active() {
+        let mut log = Log::new(LogOptions::new(192, 64).with_size_classes(vec![32]));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"ra00"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), vec![1; 40])));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"multi"), vec![2; 40])));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"ra01"), Vec::from(b"consensus"))));
+        log.set_snapshot_active(true);
+
+        assert_eq!(false, log.try_append(KeyValue::new(Vec::from(b"ra02"), Vec::from(b"consensus"))));
+        assert_eq!(0, log.evictions());
+        assert_eq!(vec![1; 40], log.try_get(b"paxos").unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn recover_compressed_values() {
+        let path = backing_file("compressed");
+        let value = "{\"consensus\":\"raft\"}".repeat(20).into_bytes();
+        let options = || LogOptions::new(512, 512)
+            .with_compression(ValueCompression::new(CompressionAlgorithm::Zstd, 64))
+            .with_backing_file(path.clone());
+        {
+            let mut log = Log::open(options()).unwrap();
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), value.clone())));
+            assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus"))));
+            assert_eq!(1, log.compressed_values());
+            assert_eq!(value.len() as u64, log.uncompressed_value_bytes());
+            assert_eq!(true, log.segment_bytes()[0] < value.len());
+            log.flush().unwrap();
+        }
+
+        let log = Log::open(options()).unwrap();
+        assert_eq!(value, log.try_get(b"raft").unwrap().unwrap().value());
+        assert_eq!(b"consensus", log.try_get(b"paxos").unwrap().unwrap().value());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn touch_a_key_without_writing_to_the_segments() {
+        let mut log = Log::new(LogOptions::new(128, 128));
+        assert_eq!(true, log.try_append_expiring(KeyValue::new(Vec::from(b"session"), Vec::from(b"alice")), Some(100)));
+        let segment_bytes = log.segment_bytes();
+
+        assert_eq!(true, log.try_touch(b"session", Some(200)));
+        assert_eq!(false, log.try_touch(b"raft", Some(200)));
+        assert_eq!(segment_bytes, log.segment_bytes());
+        assert_eq!(Some(200), log.expires_at(b"session"));
+        assert_eq!(Some(200), log.try_get(b"session").unwrap().unwrap().expires_at());
+        assert_eq!(Some(2), log.version_of(b"session"));
+
+        assert_eq!(Ok(()), log.try_append_to_value(b"session", b"!"));
+        assert_eq!(Some(200), log.expires_at(b"session"));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"session"), Vec::from(b"bob"))));
+        assert_eq!(None, log.expires_at(b"session"));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn recover_the_expiry_of_a_key() {
+        let path = backing_file("expiry");
+        {
+            let mut log = Log::open(LogOptions::new(128, 128).with_backing_file(path.clone())).unwrap();
+            assert_eq!(true, log.try_append_expiring(KeyValue::new(Vec::from(b"session"), Vec::from(b"alice")), Some(100)));
+            assert_eq!(true, log.try_append_expiring(KeyValue::new(Vec::from(b"hits"), Vec::from(b"10")), Some(200)));
+            assert_eq!(Ok(11), log.try_increment(b"hits", 1));
+            log.flush().unwrap();
+        }
+
+        let mut log = Log::open(LogOptions::new(128, 128).with_backing_file(path.clone())).unwrap();
+        assert_eq!(Some(100), log.expires_at(b"session"));
+        assert_eq!(Some(200), log.expires_at(b"hits"));
+        assert_eq!(true, log.try_expire(b"session", 100));
+        assert_eq!(b"11", log.try_get(b"hits").unwrap().unwrap().value());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn expire_a_key_once_its_deadline_passed() {
+        let mut log = Log::new(LogOptions::new(128, 128));
+        assert_eq!(true, log.try_append_expiring(KeyValue::new(Vec::from(b"session"), Vec::from(b"alice")), Some(100)));
+
+        assert_eq!(false, log.try_expire(b"session", 99));
+        assert_eq!(true, log.try_expire(b"session", 100));
+        assert_eq!(true, log.try_get(b"session").is_none());
+        assert_eq!(1, log.expirations());
+        assert_eq!(vec![Vec::from(b"session")], log.take_evicted_keys());
+    }
+
+    #[test]
+    fn append_a_touched_key_written_without_a_deadline() {
+        let mut log = Log::new(LogOptions::new(128, 128));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"session"), Vec::from(b"alice"))));
+
+        assert_eq!(true, log.try_touch(b"session", Some(200)));
+        assert_eq!(vec![30 + 38], log.segment_bytes());
+        let key_value = log.try_get(b"session").unwrap().unwrap();
+        assert_eq!((b"alice".as_slice(), Some(200), 2), (key_value.value(), key_value.expires_at(), key_value.version()));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn recover_the_expiry_of_a_touched_key() {
+        let path = backing_file("touched-expiry");
+        {
+            let mut log = Log::open(LogOptions::new(128, 128).with_backing_file(path.clone())).unwrap();
+            assert_eq!(true, log.try_append_expiring(KeyValue::new(Vec::from(b"session"), Vec::from(b"alice")), Some(100)));
+            assert_eq!(true, log.try_touch(b"session", Some(300)));
+            log.flush().unwrap();
+        }
+
+        let log = Log::open(LogOptions::new(128, 128).with_backing_file(path.clone())).unwrap();
+        assert_eq!(Some(300), log.expires_at(b"session"));
+        assert_eq!(b"alice", log.try_get(b"session").unwrap().unwrap().value());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rollback_a_touch() {
+        let mut log = Log::new(LogOptions::new(128, 128));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"session"), Vec::from(b"alice"))));
+        assert_eq!(true, log.try_append_expiring(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus")), Some(100)));
+
+        let checkpoint = log.begin_transaction();
+        assert_eq!(true, log.try_touch(b"session", Some(100)));
+        assert_eq!(true, log.try_touch(b"raft", Some(200)));
+        log.rollback_transaction(checkpoint);
+        assert_eq!(None, log.expires_at(b"session"));
+        assert_eq!(Some(100), log.expires_at(b"raft"));
+        assert_eq!(Some(100), log.try_get(b"raft").unwrap().unwrap().expires_at());
+    }
 }
\ No newline at end of file
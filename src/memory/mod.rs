@@ -1,5 +1,11 @@
+pub(crate) mod arena;
+pub(crate) mod budget;
+pub(crate) mod compression;
+pub(crate) mod counter;
 pub(crate) mod segment;
 pub(crate) mod options;
 pub(crate) mod log;
 pub(crate) mod index;
-pub(crate) mod key_value;
\ No newline at end of file
+pub(crate) mod key_value;
+pub(crate) mod snapshot;
+pub(crate) mod scan;
\ No newline at end of file
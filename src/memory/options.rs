@@ -1,6 +1,19 @@
+use std::path::{Path, PathBuf};
+
+use crate::memory::compression::ValueCompression;
+use crate::memory::counter::CounterEncoding;
+
 pub(crate) struct LogOptions {
     log_size_bytes: usize,
     segment_size_bytes: usize,
+    huge_pages: bool,
+    pre_fault: bool,
+    backing_file: Option<PathBuf>,
+    counter_encoding: CounterEncoding,
+    max_item_size_bytes: usize,
+    ordered_index: bool,
+    size_classes: Vec<usize>,
+    compression: ValueCompression,
 }
 
 impl LogOptions {
@@ -9,9 +22,61 @@ impl LogOptions {
         LogOptions {
             log_size_bytes,
             segment_size_bytes,
+            huge_pages: false,
+            pre_fault: false,
+            backing_file: None,
+            counter_encoding: CounterEncoding::Decimal,
+            max_item_size_bytes: segment_size_bytes,
+            ordered_index: false,
+            size_classes: Vec::new(),
+            compression: ValueCompression::none(),
         }
     }
 
+    pub(crate) fn with_huge_pages(mut self) -> Self {
+        self.huge_pages = true;
+        self
+    }
+
+    pub(crate) fn with_pre_fault(mut self) -> Self {
+        self.pre_fault = true;
+        self
+    }
+
+    pub(crate) fn with_backing_file(mut self, path: PathBuf) -> Self {
+        self.backing_file = Some(path);
+        self
+    }
+
+    pub(crate) fn with_counter_encoding(mut self, counter_encoding: CounterEncoding) -> Self {
+        self.counter_encoding = counter_encoding;
+        self
+    }
+
+    pub(crate) fn with_max_item_size(mut self, max_item_size_bytes: usize) -> Self {
+        assert!(max_item_size_bytes <= self.segment_size_bytes);
+        self.max_item_size_bytes = max_item_size_bytes;
+        self
+    }
+
+    pub(crate) fn with_ordered_index(mut self) -> Self {
+        self.ordered_index = true;
+        self
+    }
+
+    // Upper bounds of the encoded item sizes of each class, an implicit last class holds the items up to the max item size.
+    pub(crate) fn with_size_classes(mut self, mut size_classes: Vec<usize>) -> Self {
+        size_classes.sort_unstable();
+        size_classes.dedup();
+        self.size_classes = size_classes;
+        self
+    }
+
+    pub(crate) fn with_compression(mut self, compression: ValueCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
     pub(crate) fn number_of_segments(&self) -> usize {
         if self.log_size_bytes % self.segment_size_bytes != 0 {
             return (self.log_size_bytes / self.segment_size_bytes) + 1;
@@ -22,10 +87,57 @@ impl LogOptions {
     pub(crate) fn segment_size(&self) -> usize {
         self.segment_size_bytes
     }
+
+    pub(crate) fn arena_size(&self) -> usize {
+        self.number_of_segments() * self.segment_size_bytes
+    }
+
+    pub(crate) fn huge_pages(&self) -> bool {
+        self.huge_pages
+    }
+
+    pub(crate) fn pre_fault(&self) -> bool {
+        self.pre_fault
+    }
+
+    pub(crate) fn backing_file(&self) -> Option<&Path> {
+        self.backing_file.as_deref()
+    }
+
+    pub(crate) fn counter_encoding(&self) -> CounterEncoding {
+        self.counter_encoding
+    }
+
+    pub(crate) fn max_item_size(&self) -> usize {
+        self.max_item_size_bytes
+    }
+
+    pub(crate) fn ordered_index(&self) -> bool {
+        self.ordered_index
+    }
+
+    pub(crate) fn compression(&self) -> ValueCompression {
+        self.compression
+    }
+
+    pub(crate) fn size_classes(&self) -> Vec<usize> {
+        let mut size_classes: Vec<usize> = self
+            .size_classes
+            .iter()
+            .copied()
+            .filter(|max_item_size| *max_item_size < self.max_item_size_bytes)
+            .collect();
+        size_classes.push(self.max_item_size_bytes);
+        size_classes
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::memory::compression::{CompressionAlgorithm, ValueCompression};
+    use crate::memory::counter::CounterEncoding;
     use crate::memory::options::LogOptions;
 
     #[test]
@@ -39,4 +151,59 @@ mod tests {
         let log_options = LogOptions::new(50, 3);
         assert_eq!(17, log_options.number_of_segments());
     }
+
+    #[test]
+    fn arena_size_covers_all_the_segments() {
+        let log_options = LogOptions::new(50, 3);
+        assert_eq!(51, log_options.arena_size());
+    }
+
+    #[test]
+    fn arena_options() {
+        let log_options = LogOptions::new(100, 10).with_huge_pages().with_pre_fault();
+        assert_eq!(true, log_options.huge_pages());
+        assert_eq!(true, log_options.pre_fault());
+    }
+
+    #[test]
+    fn backing_file() {
+        let log_options = LogOptions::new(100, 10).with_backing_file(PathBuf::from("memcore.data"));
+        assert_eq!(Some(Path::new("memcore.data")), log_options.backing_file());
+    }
+
+    #[test]
+    fn counter_encoding() {
+        assert_eq!(CounterEncoding::Decimal, LogOptions::new(100, 10).counter_encoding());
+
+        let log_options = LogOptions::new(100, 10).with_counter_encoding(CounterEncoding::LittleEndian);
+        assert_eq!(CounterEncoding::LittleEndian, log_options.counter_encoding());
+    }
+
+    #[test]
+    fn max_item_size() {
+        assert_eq!(10, LogOptions::new(100, 10).max_item_size());
+        assert_eq!(8, LogOptions::new(100, 10).with_max_item_size(8).max_item_size());
+    }
+
+    #[test]
+    fn compression() {
+        assert_eq!(ValueCompression::none(), LogOptions::new(100, 10).compression());
+
+        let compression = ValueCompression::new(CompressionAlgorithm::Lz4, 1024);
+        assert_eq!(compression, LogOptions::new(100, 10).with_compression(compression).compression());
+    }
+
+    #[test]
+    fn size_classes_end_with_the_max_item_size() {
+        assert_eq!(vec![10], LogOptions::new(100, 10).size_classes());
+
+        let log_options = LogOptions::new(100, 10).with_max_item_size(8).with_size_classes(vec![12, 4, 2, 4, 8]);
+        assert_eq!(vec![2, 4, 8], log_options.size_classes());
+    }
+
+    #[test]
+    #[should_panic]
+    fn max_item_size_can_not_exceed_the_segment_size() {
+        let _ = LogOptions::new(100, 10).with_max_item_size(11);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,249 @@
+use std::io::{Error, Read};
+use std::ops::Bound;
+
+use bytes::{BufMut, BytesMut};
+
+pub(crate) const MAX_SCAN_PAGE_SIZE: usize = 1024;
+
+// A keyspace cursor keeps the shard being scanned above the position cursor of its index, which never reaches
+// these bits.
+const KEYSPACE_CURSOR_SHARD_SHIFT: u32 = 48;
+
+// Keys matching the prefix within [start, end), the cursor is the last key of the previous page.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct ScanRequest {
+    prefix: Vec<u8>,
+    start: Option<Vec<u8>>,
+    end: Option<Vec<u8>>,
+    cursor: Option<Vec<u8>>,
+    limit: usize,
+}
+
+#[derive(PartialEq, Debug)]
+pub(crate) struct ScanPage {
+    pub(crate) keys: Vec<Vec<u8>>,
+    pub(crate) cursor: Option<Vec<u8>>,
+}
+
+impl ScanRequest {
+    pub(crate) fn new(limit: usize) -> Self {
+        ScanRequest {
+            prefix: Vec::new(),
+            start: None,
+            end: None,
+            cursor: None,
+            limit: limit.clamp(1, MAX_SCAN_PAGE_SIZE),
+        }
+    }
+
+    pub(crate) fn with_prefix(mut self, prefix: Vec<u8>) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    pub(crate) fn with_range(mut self, start: Option<Vec<u8>>, end: Option<Vec<u8>>) -> Self {
+        self.start = start;
+        self.end = end;
+        self
+    }
+
+    pub(crate) fn with_cursor(mut self, cursor: Option<Vec<u8>>) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub(crate) fn lower_bound(&self) -> Bound<Vec<u8>> {
+        let start = match &self.start {
+            Some(start) if *start > self.prefix => start.clone(),
+            _ => self.prefix.clone(),
+        };
+        match &self.cursor {
+            Some(cursor) if *cursor >= start => Bound::Excluded(cursor.clone()),
+            _ => Bound::Included(start),
+        }
+    }
+
+    // Keys are visited in order from the lower bound, so the first key outside the prefix or past the end
+    // finishes the scan.
+    pub(crate) fn may_continue_with(&self, key: &[u8]) -> bool {
+        key.starts_with(&self.prefix) && self.end.as_ref().is_none_or(|end| key < end.as_slice())
+    }
+
+    pub(crate) fn encode_to(&self, buffer: &mut BytesMut) {
+        put_bytes(buffer, Some(&self.prefix));
+        put_bytes(buffer, self.start.as_deref());
+        put_bytes(buffer, self.end.as_deref());
+        put_bytes(buffer, self.cursor.as_deref());
+        buffer.put_u32_le(self.limit as u32);
+    }
+
+    pub(crate) fn decode_from(reader: &mut impl Read) -> Result<Self, Error> {
+        let prefix = read_bytes(reader)?.unwrap_or_default();
+        let start = read_bytes(reader)?;
+        let end = read_bytes(reader)?;
+        let cursor = read_bytes(reader)?;
+        let mut limit = [0; 4];
+        reader.read_exact(&mut limit)?;
+
+        Ok(ScanRequest::new(u32::from_le_bytes(limit) as usize)
+            .with_prefix(prefix)
+            .with_range(start, end)
+            .with_cursor(cursor))
+    }
+}
+
+impl ScanPage {
+    pub(crate) fn from_keys(mut keys: Vec<Vec<u8>>, limit: usize) -> Self {
+        if keys.len() <= limit {
+            return ScanPage { keys, cursor: None };
+        }
+        keys.truncate(limit);
+        let cursor = keys.last().cloned();
+        ScanPage { keys, cursor }
+    }
+
+    // A shard with more keys has only returned keys up to its cursor, so the merged page can not go past the
+    // smallest such cursor without skipping the keys that shard has not returned yet.
+    pub(crate) fn merge(pages: Vec<ScanPage>, limit: usize) -> Self {
+        let bound = pages.iter().filter_map(|page| page.cursor.clone()).min();
+        let mut keys: Vec<Vec<u8>> = pages
+            .into_iter()
+            .flat_map(|page| page.keys)
+            .filter(|key| bound.as_ref().is_none_or(|bound| key <= bound))
+            .collect();
+        keys.sort();
+
+        if keys.len() > limit {
+            return ScanPage::from_keys(keys, limit);
+        }
+        ScanPage { keys, cursor: bound }
+    }
+}
+
+pub(crate) fn keyspace_cursor(shard: usize, position: u64) -> u64 {
+    ((shard as u64) << KEYSPACE_CURSOR_SHARD_SHIFT) | position
+}
+
+pub(crate) fn keyspace_cursor_shard(cursor: u64) -> usize {
+    (cursor >> KEYSPACE_CURSOR_SHARD_SHIFT) as usize
+}
+
+pub(crate) fn keyspace_cursor_position(cursor: u64) -> u64 {
+    cursor & ((1 << KEYSPACE_CURSOR_SHARD_SHIFT) - 1)
+}
+
+fn put_bytes(buffer: &mut BytesMut, bytes: Option<&[u8]>) {
+    match bytes {
+        Some(bytes) => {
+            buffer.put_u8(1);
+            buffer.put_u16_le(bytes.len() as u16);
+            buffer.put_slice(bytes);
+        }
+        None => buffer.put_u8(0),
+    }
+}
+
+fn read_bytes(reader: &mut impl Read) -> Result<Option<Vec<u8>>, Error> {
+    let mut present = [0; 1];
+    reader.read_exact(&mut present)?;
+    if present[0] == 0 {
+        return Ok(None);
+    }
+    let mut length = [0; 2];
+    reader.read_exact(&mut length)?;
+
+    let mut bytes = vec![0; u16::from_le_bytes(length) as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(Some(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use bytes::{Buf, BytesMut};
+
+    use crate::memory::scan::{
+        keyspace_cursor, keyspace_cursor_position, keyspace_cursor_shard, ScanPage, ScanRequest, MAX_SCAN_PAGE_SIZE,
+    };
+
+    fn keys(keys: &[&str]) -> Vec<Vec<u8>> {
+        keys.iter().map(|key| key.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn clamps_the_page_size() {
+        assert_eq!(1, ScanRequest::new(0).limit());
+        assert_eq!(MAX_SCAN_PAGE_SIZE, ScanRequest::new(usize::MAX).limit());
+    }
+
+    #[test]
+    fn lower_bound_starts_at_the_prefix_or_the_cursor() {
+        let request = ScanRequest::new(10).with_prefix(Vec::from(b"session:"));
+        assert_eq!(Bound::Included(Vec::from(b"session:")), request.lower_bound());
+
+        let request = request.with_cursor(Some(Vec::from(b"session:42")));
+        assert_eq!(Bound::Excluded(Vec::from(b"session:42")), request.lower_bound());
+    }
+
+    #[test]
+    fn continues_with_keys_within_the_prefix_and_the_end() {
+        let request = ScanRequest::new(10).with_prefix(Vec::from(b"session:")).with_range(None, Some(Vec::from(b"session:5")));
+        assert_eq!(true, request.may_continue_with(b"session:42"));
+        assert_eq!(false, request.may_continue_with(b"session:50"));
+        assert_eq!(false, request.may_continue_with(b"sessions"));
+    }
+
+    #[test]
+    fn encodes_and_decodes_a_scan_request() {
+        let request = ScanRequest::new(10)
+            .with_prefix(Vec::from(b"session:"))
+            .with_range(Some(Vec::from(b"session:1")), None)
+            .with_cursor(Some(Vec::from(b"session:3")));
+
+        let mut buffer = BytesMut::new();
+        request.encode_to(&mut buffer);
+        assert_eq!(request, ScanRequest::decode_from(&mut buffer.reader()).unwrap());
+    }
+
+    #[test]
+    fn page_has_a_cursor_given_more_keys() {
+        let page = ScanPage::from_keys(keys(&["a", "b", "c"]), 2);
+        assert_eq!(keys(&["a", "b"]), page.keys);
+        assert_eq!(Some(Vec::from(b"b")), page.cursor);
+
+        assert_eq!(None, ScanPage::from_keys(keys(&["a", "b"]), 2).cursor);
+    }
+
+    #[test]
+    fn merges_pages_in_key_order() {
+        let merged = ScanPage::merge(vec![
+            ScanPage { keys: keys(&["b", "d"]), cursor: None },
+            ScanPage { keys: keys(&["a", "c"]), cursor: None },
+        ], 3);
+        assert_eq!(keys(&["a", "b", "c"]), merged.keys);
+        assert_eq!(Some(Vec::from(b"c")), merged.cursor);
+    }
+
+    #[test]
+    fn merge_stops_at_the_smallest_cursor_of_an_unfinished_page() {
+        let merged = ScanPage::merge(vec![
+            ScanPage { keys: keys(&["a", "b"]), cursor: Some(Vec::from(b"b")) },
+            ScanPage { keys: keys(&["c"]), cursor: None },
+        ], 4);
+        assert_eq!(keys(&["a", "b"]), merged.keys);
+        assert_eq!(Some(Vec::from(b"b")), merged.cursor);
+    }
+
+    #[test]
+    fn keyspace_cursor_carries_the_shard_and_the_position() {
+        let cursor = keyspace_cursor(3, 42);
+        assert_eq!(3, keyspace_cursor_shard(cursor));
+        assert_eq!(42, keyspace_cursor_position(cursor));
+        assert_eq!(0, keyspace_cursor(0, 0));
+    }
+}
\ No newline at end of file
@@ -1,36 +1,77 @@
+use std::sync::Arc;
+
+use crate::memory::arena::Arena;
+
 pub(crate) struct Segment {
-    buffer: Vec<u8>,
+    arena: Arc<Arena>,
+    offset: usize,
+    length: usize,
     available_capacity: usize,
 }
 
 impl Segment {
     pub(crate) fn new(capacity: usize) -> Self {
         assert!(capacity > 0);
+        Self::in_arena(Arc::new(Arena::heap(capacity, false)), 0, capacity)
+    }
+
+    pub(crate) fn in_arena(arena: Arc<Arena>, offset: usize, capacity: usize) -> Self {
+        assert!(capacity > 0);
+        assert!(offset + capacity <= arena.size());
         Segment {
-            buffer: Vec::with_capacity(capacity),
+            arena,
+            offset,
+            length: 0,
             available_capacity: capacity,
         }
     }
 
     pub(crate) fn try_append(&mut self, slice: &[u8]) -> Option<usize> {
         if self.available_capacity >= slice.len() {
-            let index = self.buffer.len();
-            self.buffer.extend_from_slice(slice);
+            let index = self.length;
+            unsafe { self.arena.write(self.offset + index, slice) };
+            self.length += slice.len();
             self.available_capacity -= slice.len();
             return Some(index);
         }
         return None;
     }
 
+    pub(crate) fn overwrite(&mut self, index: usize, slice: &[u8]) {
+        assert!(self.length >= index + slice.len());
+        unsafe { self.arena.write(self.offset + index, slice) };
+    }
+
     pub(crate) fn get(&self, index: usize, size: usize) -> &[u8] {
         assert!(size > 0);
-        assert!(self.buffer.len() >= (index + size - 1));
+        assert!(self.length >= index + size);
+
+        return self.arena.slice(self.offset + index, size);
+    }
 
-        return &self.buffer[index..index + size];
+    pub(crate) fn written(&self) -> &[u8] {
+        self.arena.slice(self.offset, self.length)
+    }
+
+    pub(crate) fn contents(&self) -> &[u8] {
+        self.arena.slice(self.offset, self.length + self.available_capacity)
+    }
+
+    pub(crate) fn restore_length(&mut self, length: usize) {
+        let capacity = self.length + self.available_capacity;
+        assert!(length <= capacity);
+        self.length = length;
+        self.available_capacity = capacity - length;
+    }
+
+    pub(crate) fn truncate(&mut self, length: usize) {
+        assert!(length <= self.length);
+        unsafe { self.arena.write(self.offset + length, &vec![0; self.length - length]) };
+        self.restore_length(length);
     }
 
     pub(crate) fn is_empty(&self) -> bool {
-        self.available_capacity == self.buffer.capacity()
+        self.length == 0
     }
 
     pub(crate) fn is_full(&self) -> bool {
@@ -40,6 +81,9 @@ impl Segment {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
+    use crate::memory::arena::Arena;
     use crate::memory::segment::Segment;
 
     #[test]
@@ -150,4 +194,67 @@ mod tests {
 
         let _ = segment.get(0, 9);
     }
+
+    #[test]
+    fn should_append_to_segments_carved_from_the_same_arena() {
+        let arena = Arc::new(Arena::new(32, false, false));
+        let mut first = Segment::in_arena(arena.clone(), 0, 16);
+        let mut second = Segment::in_arena(arena, 16, 16);
+
+        assert_eq!(Some(0), first.try_append(b"thread-per-core"));
+        assert_eq!(Some(0), second.try_append(b"memcore"));
+
+        assert_eq!(b"thread-per-core", first.get(0, 15));
+        assert_eq!(b"memcore", second.get(0, 7));
+    }
+
+    #[test]
+    #[should_panic]
+    fn should_panic_given_segment_beyond_the_arena() {
+        let arena = Arc::new(Arena::new(32, false, false));
+        let _ = Segment::in_arena(arena, 24, 16);
+    }
+
+    #[test]
+    fn should_restore_the_length_of_a_segment() {
+        let arena = Arc::new(Arena::new(16, false, false));
+        unsafe { arena.write(0, b"memcore") };
+
+        let mut segment = Segment::in_arena(arena, 0, 16);
+        segment.restore_length(7);
+
+        assert_eq!(b"memcore", segment.get(0, 7));
+        assert_eq!(Some(7), segment.try_append(b"raft"));
+        assert_eq!(b"memcoreraft", segment.written());
+    }
+
+    #[test]
+    fn should_overwrite_in_segment() {
+        let mut segment = Segment::new(16);
+        assert_eq!(true, segment.try_append(b"memcore").is_some());
+
+        segment.overwrite(3, b"CORE");
+        assert_eq!(b"memCORE", segment.get(0, 7));
+    }
+
+    #[test]
+    #[should_panic]
+    fn should_panic_given_overwrite_beyond_the_written_length() {
+        let mut segment = Segment::new(16);
+        assert_eq!(true, segment.try_append(b"memcore").is_some());
+
+        segment.overwrite(5, b"CORE");
+    }
+
+    #[test]
+    fn truncate_clears_the_discarded_bytes() {
+        let mut segment = Segment::new(32);
+        assert_eq!(Some(0), segment.try_append(b"thread"));
+        assert_eq!(Some(6), segment.try_append(b"per-core"));
+
+        segment.truncate(6);
+        assert_eq!(b"thread", segment.written());
+        assert_eq!(true, segment.contents()[6..14].iter().all(|byte| *byte == 0));
+        assert_eq!(Some(6), segment.try_append(b"shard"));
+    }
 }
\ No newline at end of file
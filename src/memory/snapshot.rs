@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::Path;
+
+use crate::memory::key_value::{KeyRefs, KeyValue};
+use crate::memory::log::Log;
+use crate::memory::options::LogOptions;
+
+// magic | version (u16) | write-ahead log position (u64) | segment count (u32), followed by
+// length (u32) | checksum (u32) | bytes per segment
+const SNAPSHOT_MAGIC: &[u8; 4] = b"MCSN";
+const SNAPSHOT_VERSION: u16 = 2;
+const NO_WRITE_AHEAD_LOG: u64 = u64::MAX;
+
+type LatestRecords = HashMap<Vec<u8>, KeyValue>;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct SnapshotProgress {
+    pub(crate) segments_written: usize,
+    pub(crate) segments_total: usize,
+    pub(crate) bytes_written: usize,
+    pub(crate) bytes_total: usize,
+}
+
+pub(crate) struct Snapshot {
+    writer: BufWriter<File>,
+    segment_cutoffs: Vec<usize>,
+    progress: SnapshotProgress,
+}
+
+impl SnapshotProgress {
+    pub(crate) fn is_complete(&self) -> bool {
+        self.segments_written == self.segments_total
+    }
+}
+
+impl Snapshot {
+    // The position is where the write-ahead log of the shard ended when the snapshot began, None without one.
+    pub(crate) fn begin(log: &Log, path: &Path, write_ahead_log_position: Option<u64>) -> Result<Self, Error> {
+        let mut segment_cutoffs: Vec<usize> = (0..log.number_of_segments())
+            .map(|segment_index| log.segment(segment_index).written().len())
+            .collect();
+        // Segments are claimed in any order, an empty one can sit between two written ones.
+        while segment_cutoffs.last() == Some(&0) {
+            segment_cutoffs.pop();
+        }
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(SNAPSHOT_MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        writer.write_all(&write_ahead_log_position.unwrap_or(NO_WRITE_AHEAD_LOG).to_le_bytes())?;
+        writer.write_all(&(segment_cutoffs.len() as u32).to_le_bytes())?;
+
+        let progress = SnapshotProgress {
+            segments_written: 0,
+            segments_total: segment_cutoffs.len(),
+            bytes_written: 0,
+            bytes_total: segment_cutoffs.iter().sum(),
+        };
+        let mut snapshot = Snapshot { writer, segment_cutoffs, progress };
+        if snapshot.progress.is_complete() {
+            snapshot.complete()?;
+        }
+        Ok(snapshot)
+    }
+
+    pub(crate) fn step(&mut self, log: &Log) -> Result<SnapshotProgress, Error> {
+        if self.progress.is_complete() {
+            return Ok(self.progress);
+        }
+        let segment_index = self.progress.segments_written;
+        let cutoff = self.segment_cutoffs[segment_index];
+        let bytes = &log.segment(segment_index).written()[..cutoff];
+
+        self.writer.write_all(&(cutoff as u32).to_le_bytes())?;
+        self.writer.write_all(&crc32fast::hash(bytes).to_le_bytes())?;
+        self.writer.write_all(bytes)?;
+
+        self.progress.segments_written += 1;
+        self.progress.bytes_written += cutoff;
+        if self.progress.is_complete() {
+            self.complete()?;
+        }
+        Ok(self.progress)
+    }
+
+    pub(crate) fn progress(&self) -> SnapshotProgress {
+        self.progress
+    }
+
+    // The segments hold the records of a key in no particular order, the one with the highest version is restored
+    // with its expiry deadline unless it is a tombstone. Restored records are appended oldest first.
+    pub(crate) fn restore(path: &Path, options: LogOptions) -> Result<Log, Error> {
+        let (latest, write_ahead_log_position) = Self::read_latest(path)?;
+        let mut log = Log::new(options);
+        log.set_write_ahead_log_position(write_ahead_log_position);
+        restore_into(&mut log, latest.into_values().collect())?;
+        Ok(log)
+    }
+
+    // Restores a node from the files the Snapshot command wrote for each of its shards, into one log per options
+    // given. A key goes to the shard owning it on the restoring node, which is not the shard that wrote it once the
+    // number of shards or the slots of a shard changed. The write-ahead log positions are only kept while every
+    // key stays on the shard that wrote it, the write-ahead logs of the shards no longer match their logs otherwise.
+    pub(crate) fn restore_node(
+        directory: &Path,
+        name: &[u8],
+        options: Vec<LogOptions>,
+        shard_of: impl Fn(&[u8]) -> usize,
+    ) -> Result<Vec<Log>, Error> {
+        let mut key_values: Vec<Vec<KeyValue>> = options.iter().map(|_| Vec::new()).collect();
+        let mut write_ahead_log_positions = vec![None; options.len()];
+        let mut moved = false;
+        let mut shard = 0;
+        loop {
+            let file_name = String::from_utf8(shard_file_name(name, shard))
+                .map_err(|_| Error::new(ErrorKind::InvalidInput, "snapshot name is not valid UTF-8"))?;
+            let path = directory.join(file_name);
+            if !path.exists() {
+                break;
+            }
+            let (latest, write_ahead_log_position) = Self::read_latest(&path)?;
+            if let Some(position) = write_ahead_log_positions.get_mut(shard) {
+                *position = write_ahead_log_position;
+            }
+            for (key, key_value) in latest {
+                let owner = shard_of(&key);
+                moved |= owner != shard;
+                key_values[owner].push(key_value);
+            }
+            shard += 1;
+        }
+        if shard == 0 {
+            return Err(Error::new(ErrorKind::NotFound, "no snapshot of a shard found"));
+        }
+        moved |= shard != options.len();
+
+        let mut logs = Vec::with_capacity(options.len());
+        let shards = options.into_iter().zip(key_values).zip(write_ahead_log_positions);
+        for ((options, key_values), write_ahead_log_position) in shards {
+            let mut log = Log::new(options);
+            log.set_write_ahead_log_position(if moved { None } else { write_ahead_log_position });
+            restore_into(&mut log, key_values)?;
+            logs.push(log);
+        }
+        Ok(logs)
+    }
+
+    // The latest record of every key and the write-ahead log position of the snapshot file.
+    fn read_latest(path: &Path) -> Result<(LatestRecords, Option<u64>), Error> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "file is not a memcore snapshot"));
+        }
+        let version = u16::from_le_bytes(read_array(&mut reader)?);
+        if version != SNAPSHOT_VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, format!("unsupported snapshot version {}", version)));
+        }
+        let write_ahead_log_position = Some(u64::from_le_bytes(read_array(&mut reader)?))
+            .filter(|position| *position != NO_WRITE_AHEAD_LOG);
+
+        let mut latest = LatestRecords::new();
+        let segment_count = u32::from_le_bytes(read_array(&mut reader)?);
+        for _ in 0..segment_count {
+            let length = u32::from_le_bytes(read_array(&mut reader)?) as usize;
+            let checksum = u32::from_le_bytes(read_array(&mut reader)?);
+
+            // Read up to the length rather than allocating it up front, a corrupted length must not allocate more than
+            // the file holds.
+            let mut bytes = Vec::new();
+            if (&mut reader).take(length as u64).read_to_end(&mut bytes)? < length {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "snapshot segment shorter than its length"));
+            }
+            if crc32fast::hash(&bytes) != checksum {
+                return Err(Error::new(ErrorKind::InvalidData, "snapshot segment is corrupted"));
+            }
+            for (_, key_ref) in KeyRefs::new(&bytes) {
+                let newer = latest.get(key_ref.key()).is_none_or(|key_value| key_value.version() <= key_ref.version());
+                if newer {
+                    latest.insert(key_ref.key().to_vec(), key_ref.to_key_value_ref()?.to_key_value());
+                }
+            }
+        }
+
+        Ok((latest, write_ahead_log_position))
+    }
+
+    fn complete(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()
+    }
+}
+
+// The Snapshot command of a node writes one file per shard, named after the snapshot and the shard.
+pub(crate) fn shard_file_name(name: &[u8], shard: usize) -> Vec<u8> {
+    [name, format!(".shard-{}", shard).as_bytes()].concat()
+}
+
+fn restore_into(log: &mut Log, key_values: Vec<KeyValue>) -> Result<(), Error> {
+    let mut key_values: Vec<KeyValue> = key_values.into_iter().filter(|key_value| !key_value.is_tombstone()).collect();
+    key_values.sort_by_key(KeyValue::version);
+    for key_value in key_values {
+        let expires_at = key_value.expires_at();
+        if !log.try_append_expiring(key_value, expires_at) {
+            return Err(Error::other("log does not have enough space to restore the snapshot"));
+        }
+    }
+    Ok(())
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> Result<[u8; N], Error> {
+    let mut buffer = [0; N];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::path::PathBuf;
+
+    use crate::memory::key_value::KeyValue;
+    use crate::memory::log::Log;
+    use crate::memory::options::LogOptions;
+    use crate::memory::snapshot::Snapshot;
+
+    fn snapshot_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("memcore-snapshot-{}-{}.snap", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn snapshot_and_restore_a_log() {
+        let path = snapshot_file("restore");
+        let mut log = Log::new(LogOptions::new(128, 32));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_delete(b"paxos"));
+
+        let mut snapshot = Snapshot::begin(&log, &path, None).unwrap();
+        while !snapshot.step(&log).unwrap().is_complete() {}
+
+        let restored = Snapshot::restore(&path, LogOptions::new(256, 64)).unwrap();
+        assert_eq!(b"consensus", restored.try_get(b"raft").unwrap().unwrap().value());
+        assert_eq!(true, restored.try_get(b"paxos").is_none());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn snapshot_ignores_writes_after_the_cutoff() {
+        let path = snapshot_file("cutoff");
+        let mut log = Log::new(LogOptions::new(128, 64));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+
+        let mut snapshot = Snapshot::begin(&log, &path, None).unwrap();
+        assert_eq!(1, snapshot.progress().segments_total);
+        assert_eq!(31, snapshot.progress().bytes_total);
+
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus"))));
+        let progress = snapshot.step(&log).unwrap();
+        assert_eq!(true, progress.is_complete());
+        assert_eq!(31, progress.bytes_written);
+
+        let restored = Snapshot::restore(&path, LogOptions::new(128, 64)).unwrap();
+        assert_eq!(true, restored.try_get(b"raft").is_some());
+        assert_eq!(true, restored.try_get(b"paxos").is_none());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn restore_the_latest_version_of_a_key_written_to_an_earlier_segment() {
+        let path = snapshot_file("versions");
+        let mut log = Log::new(LogOptions::new(192, 64).with_size_classes(vec![32]));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), vec![1; 40])));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"leader"))));
+
+        let mut snapshot = Snapshot::begin(&log, &path, None).unwrap();
+        while !snapshot.step(&log).unwrap().is_complete() {}
+
+        let restored = Snapshot::restore(&path, LogOptions::new(192, 64)).unwrap();
+        assert_eq!(b"leader", restored.try_get(b"raft").unwrap().unwrap().value());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn snapshot_the_segments_after_an_empty_one() {
+        let path = snapshot_file("gaps");
+        let mut log = Log::new(LogOptions::new(192, 64).with_size_classes(vec![32]));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"paxos"), vec![1; 40])));
+        assert_eq!(true, log.try_evict(b"raft", &[]));
+        assert_eq!(vec![0, 63, 0], log.segment_bytes());
+
+        let mut snapshot = Snapshot::begin(&log, &path, None).unwrap();
+        while !snapshot.step(&log).unwrap().is_complete() {}
+
+        let restored = Snapshot::restore(&path, LogOptions::new(192, 64)).unwrap();
+        assert_eq!(vec![1; 40], restored.try_get(b"paxos").unwrap().unwrap().value());
+        assert_eq!(true, restored.try_get(b"raft").is_none());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn restore_the_expiry_of_a_key() {
+        let path = snapshot_file("expiry");
+        let mut log = Log::new(LogOptions::new(128, 64));
+        assert_eq!(true, log.try_append_expiring(KeyValue::new(Vec::from(b"session"), Vec::from(b"alice")), Some(100)));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+
+        let mut snapshot = Snapshot::begin(&log, &path, None).unwrap();
+        while !snapshot.step(&log).unwrap().is_complete() {}
+
+        let restored = Snapshot::restore(&path, LogOptions::new(128, 64)).unwrap();
+        assert_eq!(Some(100), restored.expires_at(b"session"));
+        assert_eq!(None, restored.expires_at(b"raft"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn restore_the_expiry_of_a_touched_key() {
+        let path = snapshot_file("touched-expiry");
+        let mut log = Log::new(LogOptions::new(256, 128));
+        assert_eq!(true, log.try_append_expiring(KeyValue::new(Vec::from(b"session"), Vec::from(b"alice")), Some(100)));
+        assert_eq!(true, log.try_touch(b"session", Some(200)));
+
+        let mut snapshot = Snapshot::begin(&log, &path, None).unwrap();
+        log.set_snapshot_active(true);
+        assert_eq!(true, log.try_touch(b"session", Some(300)));
+        while !snapshot.step(&log).unwrap().is_complete() {}
+        log.set_snapshot_active(false);
+        assert_eq!(Some(200), Snapshot::restore(&path, LogOptions::new(256, 128)).unwrap().expires_at(b"session"));
+
+        let mut snapshot = Snapshot::begin(&log, &path, None).unwrap();
+        while !snapshot.step(&log).unwrap().is_complete() {}
+        let restored = Snapshot::restore(&path, LogOptions::new(256, 128)).unwrap();
+        assert_eq!(Some(300), restored.expires_at(b"session"));
+        assert_eq!(b"alice", restored.try_get(b"session").unwrap().unwrap().value());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn restore_the_write_ahead_log_position_of_a_snapshot() {
+        let path = snapshot_file("write-ahead-log-position");
+        let mut log = Log::new(LogOptions::new(128, 64));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+
+        let mut snapshot = Snapshot::begin(&log, &path, Some(42)).unwrap();
+        while !snapshot.step(&log).unwrap().is_complete() {}
+        assert_eq!(Some(42), Snapshot::restore(&path, LogOptions::new(128, 64)).unwrap().write_ahead_log_position());
+
+        Snapshot::begin(&Log::new(LogOptions::new(128, 64)), &path, None).unwrap();
+        assert_eq!(None, Snapshot::restore(&path, LogOptions::new(128, 64)).unwrap().write_ahead_log_position());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn snapshot_an_empty_log() {
+        let path = snapshot_file("empty");
+        let log = Log::new(LogOptions::new(64, 32));
+
+        let snapshot = Snapshot::begin(&log, &path, None).unwrap();
+        assert_eq!(true, snapshot.progress().is_complete());
+
+        let restored = Snapshot::restore(&path, LogOptions::new(64, 32)).unwrap();
+        assert_eq!(true, restored.try_get(b"raft").is_none());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn fail_to_restore_a_corrupted_snapshot() {
+        let path = snapshot_file("corrupted");
+        let mut log = Log::new(LogOptions::new(64, 64));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+
+        let mut snapshot = Snapshot::begin(&log, &path, None).unwrap();
+        snapshot.step(&log).unwrap();
+
+        let mut contents = fs::read(&path).unwrap();
+        let last = contents.len() - 1;
+        contents[last] ^= 0xFF;
+        fs::write(&path, contents).unwrap();
+
+        assert_eq!(true, Snapshot::restore(&path, LogOptions::new(64, 64)).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn fail_to_restore_a_snapshot_given_a_corrupted_segment_length() {
+        let path = snapshot_file("corrupted-length");
+        let mut log = Log::new(LogOptions::new(64, 64));
+        assert_eq!(true, log.try_append(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus"))));
+
+        let mut snapshot = Snapshot::begin(&log, &path, None).unwrap();
+        snapshot.step(&log).unwrap();
+
+        let mut contents = fs::read(&path).unwrap();
+        contents[18..22].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, contents).unwrap();
+
+        assert_eq!(ErrorKind::UnexpectedEof, Snapshot::restore(&path, LogOptions::new(64, 64)).err().unwrap().kind());
+        fs::remove_file(&path).unwrap();
+    }
+}
\ No newline at end of file
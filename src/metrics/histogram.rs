@@ -0,0 +1,99 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Log-linear buckets in the style of HDR histograms, every power of two is split into 2^SUB_BUCKET_BITS
+// buckets which keeps the relative error of a recorded value under 1 / 2^SUB_BUCKET_BITS.
+const SUB_BUCKET_BITS: u32 = 3;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+const NUMBER_OF_BUCKETS: usize = (64 - SUB_BUCKET_BITS as usize + 1) * SUB_BUCKETS;
+
+// Only the owning core records, so counts are bumped with a load and a store instead of a locked
+// read-modify-write and a scrape from another thread reads whatever has been published so far.
+pub(crate) struct AtomicHistogram {
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl AtomicHistogram {
+    pub(crate) fn new() -> Self {
+        AtomicHistogram {
+            buckets: (0..NUMBER_OF_BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum_nanos: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn record(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        increment(&self.buckets[bucket_of(nanos)], 1);
+        increment(&self.count, 1);
+        increment(&self.sum_nanos, nanos);
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn sum(&self) -> Duration {
+        Duration::from_nanos(self.sum_nanos.load(Ordering::Relaxed))
+    }
+
+    // Number of recorded values below 2^exponent nanoseconds, a power of two always starts a bucket.
+    pub(crate) fn count_below(&self, exponent: u32) -> u64 {
+        let upper = if exponent >= 64 { NUMBER_OF_BUCKETS } else { bucket_of(1 << exponent) };
+        self.buckets[..upper].iter().map(|bucket| bucket.load(Ordering::Relaxed)).sum()
+    }
+}
+
+fn increment(counter: &AtomicU64, delta: u64) {
+    counter.store(counter.load(Ordering::Relaxed).wrapping_add(delta), Ordering::Relaxed);
+}
+
+fn bucket_of(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+    let exponent = 63 - value.leading_zeros();
+    let shift = exponent - SUB_BUCKET_BITS;
+    let sub_bucket = ((value >> shift) as usize) & (SUB_BUCKETS - 1);
+    (shift as usize + 1) * SUB_BUCKETS + sub_bucket
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::metrics::histogram::{bucket_of, AtomicHistogram, NUMBER_OF_BUCKETS};
+
+    #[test]
+    fn buckets_grow_monotonically() {
+        let mut previous = 0;
+        for value in (0..20).map(|exponent| 1u64 << exponent).flat_map(|value| [value, value + value / 3]) {
+            let bucket = bucket_of(value);
+            assert_eq!(true, bucket >= previous);
+            previous = bucket;
+        }
+        assert_eq!(NUMBER_OF_BUCKETS - 1, bucket_of(u64::MAX));
+    }
+
+    #[test]
+    fn a_power_of_two_starts_a_bucket() {
+        assert_eq!(bucket_of(1023) + 1, bucket_of(1024));
+        assert_eq!(bucket_of(1024), bucket_of(1024 + 127));
+    }
+
+    #[test]
+    fn records_durations() {
+        let histogram = AtomicHistogram::new();
+        histogram.record(Duration::from_nanos(500));
+        histogram.record(Duration::from_micros(3));
+        histogram.record(Duration::from_millis(2));
+
+        assert_eq!(3, histogram.count());
+        assert_eq!(Duration::from_nanos(2_003_500), histogram.sum());
+        assert_eq!(1, histogram.count_below(10));
+        assert_eq!(2, histogram.count_below(12));
+        assert_eq!(3, histogram.count_below(64));
+    }
+}
\ No newline at end of file
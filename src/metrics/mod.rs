@@ -0,0 +1,3 @@
+pub(crate) mod histogram;
+pub(crate) mod registry;
+pub(crate) mod server;
\ No newline at end of file
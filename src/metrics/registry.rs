@@ -0,0 +1,166 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::executor::command::CommandType;
+use crate::metrics::histogram::AtomicHistogram;
+
+// Bucket boundaries exported to Prometheus, 2^10 ns (~1µs) to 2^34 ns (~17s).
+const EXPORTED_EXPONENTS: std::ops::RangeInclusive<u32> = 10..=34;
+
+pub(crate) struct ShardMetrics {
+    shard: usize,
+    latencies: Vec<AtomicHistogram>,
+    hottest_key_accesses: AtomicU64,
+    key_accesses: AtomicU64,
+}
+
+pub(crate) struct MetricsRegistry {
+    shards: Vec<Arc<ShardMetrics>>,
+}
+
+impl ShardMetrics {
+    pub(crate) fn new(shard: usize) -> Self {
+        ShardMetrics {
+            shard,
+            latencies: CommandType::ALL.iter().map(|_| AtomicHistogram::new()).collect(),
+            hottest_key_accesses: AtomicU64::new(0),
+            key_accesses: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn record(&self, command_type: CommandType, duration: Duration) {
+        self.latency_of(command_type).record(duration);
+    }
+
+    pub(crate) fn latency_of(&self, command_type: CommandType) -> &AtomicHistogram {
+        &self.latencies[command_type as usize - 1]
+    }
+
+    // A hottest key taking a large share of the accesses of its shard points at skew a key hash can not spread.
+    pub(crate) fn record_key_accesses(&self, hottest: u64, total: u64) {
+        self.hottest_key_accesses.store(hottest, Ordering::Relaxed);
+        self.key_accesses.store(total, Ordering::Relaxed);
+    }
+
+    pub(crate) fn hottest_key_accesses(&self) -> u64 {
+        self.hottest_key_accesses.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn key_accesses(&self) -> u64 {
+        self.key_accesses.load(Ordering::Relaxed)
+    }
+}
+
+impl MetricsRegistry {
+    pub(crate) fn new(shards: Vec<Arc<ShardMetrics>>) -> Self {
+        MetricsRegistry { shards }
+    }
+
+    // Renders the Prometheus text exposition format, only command types a shard has executed are exported.
+    pub(crate) fn render(&self) -> String {
+        let mut output = String::new();
+        output.push_str("# HELP memcore_commands_total Commands executed by a shard.\n");
+        output.push_str("# TYPE memcore_commands_total counter\n");
+        for (shard, command_type, histogram) in self.recorded() {
+            let _ = writeln!(output, "memcore_commands_total{{{}}} {}", labels(shard, command_type), histogram.count());
+        }
+
+        output.push_str("# HELP memcore_command_duration_seconds Latency of commands executed by a shard.\n");
+        output.push_str("# TYPE memcore_command_duration_seconds histogram\n");
+        for (shard, command_type, histogram) in self.recorded() {
+            let labels = labels(shard, command_type);
+            for exponent in EXPORTED_EXPONENTS {
+                let upper_bound = (1u64 << exponent) as f64 / 1e9;
+                let _ = writeln!(
+                    output,
+                    "memcore_command_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, upper_bound, histogram.count_below(exponent),
+                );
+            }
+            let _ = writeln!(output, "memcore_command_duration_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, histogram.count());
+            let _ = writeln!(output, "memcore_command_duration_seconds_sum{{{}}} {}", labels, histogram.sum().as_secs_f64());
+            let _ = writeln!(output, "memcore_command_duration_seconds_count{{{}}} {}", labels, histogram.count());
+        }
+
+        output.push_str("# HELP memcore_key_accesses_total Key accesses seen by a shard.\n");
+        output.push_str("# TYPE memcore_key_accesses_total counter\n");
+        for metrics in &self.shards {
+            let _ = writeln!(output, "memcore_key_accesses_total{{shard=\"{}\"}} {}", metrics.shard, metrics.key_accesses());
+        }
+        output.push_str("# HELP memcore_hottest_key_accesses Accesses of the most accessed key of a shard.\n");
+        output.push_str("# TYPE memcore_hottest_key_accesses gauge\n");
+        for metrics in &self.shards {
+            let _ = writeln!(
+                output,
+                "memcore_hottest_key_accesses{{shard=\"{}\"}} {}",
+                metrics.shard, metrics.hottest_key_accesses(),
+            );
+        }
+        output
+    }
+
+    fn recorded(&self) -> impl Iterator<Item = (usize, CommandType, &AtomicHistogram)> {
+        self.shards.iter().flat_map(|metrics| {
+            CommandType::ALL
+                .iter()
+                .map(|command_type| (metrics.shard, *command_type, metrics.latency_of(*command_type)))
+                .filter(|(_, _, histogram)| histogram.count() > 0)
+        })
+    }
+}
+
+fn labels(shard: usize, command_type: CommandType) -> String {
+    format!("shard=\"{}\",command=\"{}\"", shard, command_type.name())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::executor::command::CommandType;
+    use crate::metrics::registry::{MetricsRegistry, ShardMetrics};
+
+    #[test]
+    fn renders_the_recorded_commands_per_shard() {
+        let first = Arc::new(ShardMetrics::new(0));
+        let second = Arc::new(ShardMetrics::new(1));
+        first.record(CommandType::Get, Duration::from_micros(5));
+        first.record(CommandType::Get, Duration::from_millis(1));
+        second.record(CommandType::Put, Duration::from_micros(20));
+
+        let output = MetricsRegistry::new(vec![first, second]).render();
+        assert_eq!(true, output.contains("memcore_commands_total{shard=\"0\",command=\"get\"} 2\n"));
+        assert_eq!(true, output.contains("memcore_commands_total{shard=\"1\",command=\"put\"} 1\n"));
+        assert_eq!(true, output.contains("memcore_command_duration_seconds_bucket{shard=\"0\",command=\"get\",le=\"0.000008192\"} 1\n"));
+        assert_eq!(true, output.contains("memcore_command_duration_seconds_bucket{shard=\"0\",command=\"get\",le=\"+Inf\"} 2\n"));
+        assert_eq!(true, output.contains("memcore_command_duration_seconds_count{shard=\"1\",command=\"put\"} 1\n"));
+        assert_eq!(false, output.contains("command=\"delete\""));
+    }
+
+    #[test]
+    fn renders_the_key_accesses_per_shard() {
+        let metrics = Arc::new(ShardMetrics::new(2));
+        metrics.record_key_accesses(40, 100);
+
+        let output = MetricsRegistry::new(vec![metrics]).render();
+        assert_eq!(true, output.contains("memcore_key_accesses_total{shard=\"2\"} 100\n"));
+        assert_eq!(true, output.contains("memcore_hottest_key_accesses{shard=\"2\"} 40\n"));
+    }
+
+    #[test]
+    fn merges_recordings_from_other_threads_at_render_time() {
+        let metrics = Arc::new(ShardMetrics::new(0));
+        let recorder = metrics.clone();
+        std::thread::spawn(move || {
+            for _ in 0..100 {
+                recorder.record(CommandType::Get, Duration::from_micros(1));
+            }
+        }).join().unwrap();
+
+        let output = MetricsRegistry::new(vec![metrics]).render();
+        assert_eq!(true, output.contains("memcore_commands_total{shard=\"0\",command=\"get\"} 100\n"));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,116 @@
+use std::io::{BufRead, BufReader, Error, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::metrics::registry::MetricsRegistry;
+
+// A scraper that connects and never sends its request line must not hold the server.
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Runs on its own thread, away from the shard cores, and reads their metrics only when scraped.
+pub(crate) struct MetricsServer {
+    listener: TcpListener,
+    registry: Arc<MetricsRegistry>,
+    read_timeout: Duration,
+}
+
+impl MetricsServer {
+    pub(crate) fn bind(address: impl ToSocketAddrs, registry: Arc<MetricsRegistry>) -> Result<Self, Error> {
+        Ok(MetricsServer { listener: TcpListener::bind(address)?, registry, read_timeout: DEFAULT_READ_TIMEOUT })
+    }
+
+    pub(crate) fn with_read_timeout(mut self, read_timeout: Duration) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    pub(crate) fn local_address(&self) -> Result<SocketAddr, Error> {
+        self.listener.local_addr()
+    }
+
+    pub(crate) fn serve(&self) {
+        for stream in self.listener.incoming().flatten() {
+            let _ = self.handle(stream);
+        }
+    }
+
+    pub(crate) fn handle(&self, mut stream: TcpStream) -> Result<(), Error> {
+        stream.set_read_timeout(Some(self.read_timeout))?;
+        let mut request_line = String::new();
+        BufReader::new(&stream).read_line(&mut request_line)?;
+
+        let mut parts = request_line.split_whitespace();
+        let response = match (parts.next(), parts.next()) {
+            (Some("GET"), Some("/metrics")) => {
+                let body = self.registry.render();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body,
+                )
+            }
+            _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        };
+        stream.write_all(response.as_bytes())?;
+        stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::executor::command::CommandType;
+    use crate::metrics::registry::{MetricsRegistry, ShardMetrics};
+    use crate::metrics::server::MetricsServer;
+
+    fn request(server: MetricsServer, path: &str) -> String {
+        let address = server.local_address().unwrap();
+        let handler = thread::spawn(move || {
+            let (stream, _) = server.listener.accept().unwrap();
+            server.handle(stream).unwrap();
+        });
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        handler.join().unwrap();
+        response
+    }
+
+    #[test]
+    fn serves_the_metrics() {
+        let metrics = Arc::new(ShardMetrics::new(0));
+        metrics.record(CommandType::Get, Duration::from_micros(5));
+        let server = MetricsServer::bind("127.0.0.1:0", Arc::new(MetricsRegistry::new(vec![metrics]))).unwrap();
+
+        let response = request(server, "/metrics");
+        assert_eq!(true, response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(true, response.contains("memcore_commands_total{shard=\"0\",command=\"get\"} 1\n"));
+    }
+
+    #[test]
+    fn does_not_serve_other_paths() {
+        let server = MetricsServer::bind("127.0.0.1:0", Arc::new(MetricsRegistry::new(Vec::new()))).unwrap();
+
+        let response = request(server, "/");
+        assert_eq!(true, response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn gives_up_on_a_client_that_sends_no_request() {
+        let server = MetricsServer::bind("127.0.0.1:0", Arc::new(MetricsRegistry::new(Vec::new())))
+            .unwrap()
+            .with_read_timeout(Duration::from_millis(50));
+        let _stream = TcpStream::connect(server.local_address().unwrap()).unwrap();
+
+        let (stream, _) = server.listener.accept().unwrap();
+        assert_eq!(true, server.handle(stream).is_err());
+    }
+}
\ No newline at end of file
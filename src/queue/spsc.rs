@@ -1,107 +1,278 @@
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use crossbeam_utils::CachePadded;
 
 pub(crate) struct SPSCQueue<T> {
     head: CachePadded<AtomicUsize>,
     tail: CachePadded<AtomicUsize>,
-    elements: Vec<T>,
+    length: CachePadded<AtomicUsize>,
+    elements: Box<[UnsafeCell<MaybeUninit<T>>]>,
 }
 
+// The only handle allowed to push, it is not Clone so a queue never has a second producer.
+pub(crate) struct Producer<T> {
+    queue: Arc<SPSCQueue<T>>,
+}
+
+// The only handle allowed to pop, reading the front borrows it so the element can not be popped while referenced.
+pub(crate) struct Consumer<T> {
+    queue: Arc<SPSCQueue<T>>,
+}
+
+// A single producer owns the tail and a single consumer owns the head, the shared length
+// publishes the slots between them. The queue is only reachable through one Producer and one Consumer.
+unsafe impl<T: Send> Send for SPSCQueue<T> {}
+unsafe impl<T: Send> Sync for SPSCQueue<T> {}
+
 impl<T> SPSCQueue<T> {
     pub(crate) fn new(capacity: usize) -> Self {
+        assert!(capacity > 0);
         SPSCQueue {
             head: CachePadded::new(AtomicUsize::new(0)),
             tail: CachePadded::new(AtomicUsize::new(0)),
-            elements: Vec::with_capacity(capacity),
+            length: CachePadded::new(AtomicUsize::new(0)),
+            elements: (0..capacity).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect(),
         }
     }
 
-    pub(crate) fn is_empty(&self) -> bool {
-        self.head.load(Ordering::Acquire) == self.tail.load(Ordering::Acquire)
+    pub(crate) fn split(self) -> (Producer<T>, Consumer<T>) {
+        let queue = Arc::new(self);
+        (Producer { queue: queue.clone() }, Consumer { queue })
     }
 
-    pub(crate) fn try_enqueue(&mut self, element: T) -> bool {
-        let tail = self.tail.load(Ordering::Relaxed);
-        let mut next_tail = tail + 1;
-        if next_tail == self.elements.capacity() + 1 {
-            next_tail = 0;
-        }
-        if next_tail == self.head.load(Ordering::Acquire) {
-            return false;
+    fn is_empty(&self) -> bool {
+        self.length.load(Ordering::Acquire) == 0
+    }
+
+    fn len(&self) -> usize {
+        self.length.load(Ordering::Acquire)
+    }
+
+    fn try_push(&self, element: T) -> Result<(), T> {
+        if self.length.load(Ordering::Acquire) == self.elements.len() {
+            return Err(element);
         }
-        self.elements.insert(tail, element);
-        self.tail.store(next_tail, Ordering::Release);
-        return true;
+        let tail = self.tail.load(Ordering::Relaxed);
+        unsafe { (*self.elements[tail].get()).write(element) };
+        self.tail.store(self.next(tail), Ordering::Relaxed);
+        self.length.fetch_add(1, Ordering::Release);
+        Ok(())
     }
 
-    pub(crate) fn try_get_front(&self) -> Option<&T> {
+    fn try_get_front(&self) -> Option<&T> {
+        if self.length.load(Ordering::Acquire) == 0 {
+            return None;
+        }
         let head = self.head.load(Ordering::Relaxed);
-        if self.tail.load(Ordering::Acquire) == head {
+        Some(unsafe { (*self.elements[head].get()).assume_init_ref() })
+    }
+
+    fn try_dequeue(&self) -> Option<T> {
+        if self.length.load(Ordering::Acquire) == 0 {
             return None;
         }
-        return Some(&self.elements[head]);
+        let head = self.head.load(Ordering::Relaxed);
+        let element = unsafe { (*self.elements[head].get()).assume_init_read() };
+        self.advance_head(head);
+        Some(element)
     }
 
-    pub(crate) fn pop(&self) {
+    fn pop(&self) {
+        if self.length.load(Ordering::Acquire) == 0 {
+            return;
+        }
         let head = self.head.load(Ordering::Relaxed);
-        let mut next_head = head + 1;
-        if next_head == self.elements.capacity() {
-            next_head = 0;
+        unsafe { (*self.elements[head].get()).assume_init_drop() };
+        self.advance_head(head);
+    }
+
+    fn advance_head(&self, head: usize) {
+        self.head.store(self.next(head), Ordering::Relaxed);
+        self.length.fetch_sub(1, Ordering::Release);
+    }
+
+    fn next(&self, index: usize) -> usize {
+        if index + 1 == self.elements.len() {
+            return 0;
         }
-        self.head.store(next_head, Ordering::Release);
+        index + 1
+    }
+}
+
+impl<T> Drop for SPSCQueue<T> {
+    fn drop(&mut self) {
+        while self.try_dequeue().is_some() {}
+    }
+}
+
+impl<T> Producer<T> {
+    pub(crate) fn try_enqueue(&mut self, element: T) -> bool {
+        self.queue.try_push(element).is_ok()
+    }
+
+    pub(crate) fn try_push(&mut self, element: T) -> Result<(), T> {
+        self.queue.try_push(element)
+    }
+}
+
+impl<T> Consumer<T> {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub(crate) fn try_get_front(&self) -> Option<&T> {
+        self.queue.try_get_front()
+    }
+
+    // The elements from the front, the producer only writes past them so they stay valid while borrowed.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &T> {
+        let head = self.queue.head.load(Ordering::Relaxed);
+        let capacity = self.queue.elements.len();
+        (0..self.queue.len())
+            .map(move |offset| unsafe { (*self.queue.elements[(head + offset) % capacity].get()).assume_init_ref() })
+    }
+
+    pub(crate) fn try_dequeue(&mut self) -> Option<T> {
+        self.queue.try_dequeue()
+    }
+
+    pub(crate) fn pop(&mut self) {
+        self.queue.pop()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::thread;
+
     use crate::queue::spsc::SPSCQueue;
 
     #[test]
     fn is_empty_queue() {
-        let queue: SPSCQueue<usize> = SPSCQueue::new(2);
-        assert_eq!(true, queue.is_empty());
+        let (_, consumer) = SPSCQueue::<usize>::new(2).split();
+        assert_eq!(true, consumer.is_empty());
     }
 
     #[test]
     fn try_enqueue_and_get_front() {
-        let mut queue = SPSCQueue::new(2);
-        assert_eq!(true, queue.try_enqueue(10));
-        assert_eq!(true, queue.try_enqueue(20));
+        let (mut producer, mut consumer) = SPSCQueue::new(2).split();
+        assert_eq!(true, producer.try_enqueue(10));
+        assert_eq!(true, producer.try_enqueue(20));
 
-        assert_eq!(&10, queue.try_get_front().unwrap());
-        queue.pop();
+        assert_eq!(&10, consumer.try_get_front().unwrap());
+        consumer.pop();
 
-        assert_eq!(&20, queue.try_get_front().unwrap());
-        queue.pop();
+        assert_eq!(&20, consumer.try_get_front().unwrap());
+        consumer.pop();
     }
 
     #[test]
     fn can_not_enqueue_in_a_full_queue() {
-        let mut queue = SPSCQueue::new(2);
-        assert_eq!(true, queue.try_enqueue(10));
-        assert_eq!(true, queue.try_enqueue(20));
-        assert_eq!(false, queue.try_enqueue(30));
+        let (mut producer, _consumer) = SPSCQueue::new(2).split();
+        assert_eq!(true, producer.try_enqueue(10));
+        assert_eq!(true, producer.try_enqueue(20));
+        assert_eq!(false, producer.try_enqueue(30));
     }
 
     #[test]
     fn can_not_get_front_from_an_empty_queue() {
-        let queue: SPSCQueue<usize> = SPSCQueue::new(2);
+        let (_, consumer) = SPSCQueue::<usize>::new(2).split();
 
-        assert_eq!(None, queue.try_get_front());
+        assert_eq!(None, consumer.try_get_front());
     }
 
     #[test]
     fn pop_in_a_queue() {
-        let mut queue = SPSCQueue::new(2);
-        assert_eq!(true, queue.try_enqueue(10));
-        assert_eq!(true, queue.try_enqueue(20));
+        let (mut producer, mut consumer) = SPSCQueue::new(2).split();
+        assert_eq!(true, producer.try_enqueue(10));
+        assert_eq!(true, producer.try_enqueue(20));
 
-        queue.pop();
-        assert_eq!(1, queue.head.load(Ordering::SeqCst));
+        consumer.pop();
+        assert_eq!(1, consumer.queue.head.load(Ordering::SeqCst));
+
+        consumer.pop();
+        assert_eq!(0, consumer.queue.head.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn enqueue_after_wrapping_around() {
+        let (mut producer, mut consumer) = SPSCQueue::new(2).split();
+        assert_eq!(true, producer.try_enqueue(10));
+        assert_eq!(true, producer.try_enqueue(20));
+        assert_eq!(Some(10), consumer.try_dequeue());
+
+        assert_eq!(true, producer.try_enqueue(30));
+        assert_eq!(2, consumer.len());
+        assert_eq!(Some(20), consumer.try_dequeue());
+        assert_eq!(Some(30), consumer.try_dequeue());
+        assert_eq!(None, consumer.try_dequeue());
+    }
 
-        queue.pop();
-        assert_eq!(0, queue.head.load(Ordering::SeqCst));
+    #[test]
+    fn iterate_from_the_front_after_wrapping_around() {
+        let (mut producer, mut consumer) = SPSCQueue::new(2).split();
+        assert_eq!(true, producer.try_enqueue(10));
+        assert_eq!(true, producer.try_enqueue(20));
+        consumer.pop();
+        assert_eq!(true, producer.try_enqueue(30));
+
+        assert_eq!(vec![&20, &30], consumer.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn returns_the_element_given_a_full_queue() {
+        let (mut producer, _consumer) = SPSCQueue::new(1).split();
+        assert_eq!(Ok(()), producer.try_push(10));
+        assert_eq!(Err(20), producer.try_push(20));
+    }
+
+    #[test]
+    fn drops_the_remaining_elements() {
+        let element = Arc::new(10);
+        {
+            let (mut producer, _consumer) = SPSCQueue::new(2).split();
+            assert_eq!(true, producer.try_enqueue(element.clone()));
+        }
+        assert_eq!(1, Arc::strong_count(&element));
+    }
+
+    #[test]
+    fn drops_the_remaining_elements_once_both_handles_are_dropped() {
+        let element = Arc::new(10);
+        let (mut producer, consumer) = SPSCQueue::new(2).split();
+        assert_eq!(true, producer.try_enqueue(element.clone()));
+
+        drop(consumer);
+        assert_eq!(2, Arc::strong_count(&element));
+        drop(producer);
+        assert_eq!(1, Arc::strong_count(&element));
+    }
+
+    #[test]
+    fn transfers_elements_across_threads() {
+        let (mut producer, mut consumer) = SPSCQueue::new(16).split();
+
+        let producer = thread::spawn(move || {
+            for element in 0..1000 {
+                while !producer.try_enqueue(element) {}
+            }
+        });
+        let mut expected = 0;
+        while expected < 1000 {
+            if let Some(element) = consumer.try_dequeue() {
+                assert_eq!(expected, element);
+                expected += 1;
+            }
+        }
+        producer.join().unwrap();
     }
 }
\ No newline at end of file
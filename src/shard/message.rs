@@ -0,0 +1,60 @@
+use std::mem::size_of;
+
+use crate::executor::command::{Command, CommandResponse};
+use crate::memory::key_value::KeyValue;
+use crate::queue::spsc::{Consumer, Producer};
+
+pub(crate) type RequestId = u64;
+
+pub(crate) enum ShardMessage {
+    Request {
+        origin: usize,
+        request_id: RequestId,
+        commands: Vec<(usize, Command)>,
+    },
+    Response {
+        request_id: RequestId,
+        responses: Vec<(usize, CommandResponse)>,
+    },
+    Replicate {
+        key_value: KeyValue,
+    },
+    Invalidate {
+        key: Vec<u8>,
+    },
+    SlotsMigrating {
+        slots: Vec<usize>,
+    },
+    MigratedEntries {
+        entries: Vec<(KeyValue, Option<u64>)>,
+    },
+    SlotsAssigned {
+        slots: Vec<usize>,
+        owner: usize,
+    },
+    ShardAdded {
+        shard: usize,
+        producer: Producer<ShardMessage>,
+        consumer: Consumer<ShardMessage>,
+    },
+}
+
+impl ShardMessage {
+    // The bytes a queued message holds, the message itself and the keys and values it carries on the heap.
+    pub(crate) fn memory_usage(&self) -> usize {
+        let payload_bytes = match self {
+            ShardMessage::Request { commands, .. } =>
+                commands.iter().map(|(_, command)| command.payload_bytes()).sum(),
+            ShardMessage::Response { responses, .. } =>
+                responses.iter().map(|(_, response)| response.payload_bytes()).sum(),
+            ShardMessage::Replicate { key_value } => key_value.key().len() + key_value.value().len(),
+            ShardMessage::Invalidate { key } => key.len(),
+            ShardMessage::SlotsMigrating { slots } | ShardMessage::SlotsAssigned { slots, .. } =>
+                slots.len() * size_of::<usize>(),
+            ShardMessage::MigratedEntries { entries } =>
+                entries.iter().map(|(key_value, _)| key_value.key().len() + key_value.value().len()).sum(),
+            ShardMessage::ShardAdded { .. } => 0,
+        };
+        size_of::<ShardMessage>() + payload_bytes
+    }
+}
\ No newline at end of file
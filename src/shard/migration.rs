@@ -0,0 +1,78 @@
+use std::collections::HashSet;
+
+// Keys visited per poll while a shard moves slots away, small enough not to stall the requests it serves.
+pub(crate) const MIGRATION_BATCH_SIZE: usize = 64;
+
+// The source keeps serving a migrating slot for the keys it still holds and redirects the rest to the target,
+// a key is deleted from the source once it has been sent, so it always lives on exactly one of the two.
+pub(crate) struct Migration {
+    slots: HashSet<usize>,
+    target: usize,
+    cursor: Option<u64>,
+    retries: Vec<Vec<u8>>,
+}
+
+impl Migration {
+    pub(crate) fn new(slots: HashSet<usize>, target: usize) -> Self {
+        Migration { slots, target, cursor: Some(0), retries: Vec::new() }
+    }
+
+    pub(crate) fn target(&self) -> usize {
+        self.target
+    }
+
+    pub(crate) fn covers(&self, slot: usize) -> bool {
+        self.slots.contains(&slot)
+    }
+
+    pub(crate) fn slots(&self) -> Vec<usize> {
+        let mut slots: Vec<usize> = self.slots.iter().copied().collect();
+        slots.sort();
+        slots
+    }
+
+    // The keyspace cursor to continue from, none once the scan of the index has wrapped around.
+    pub(crate) fn cursor(&self) -> Option<u64> {
+        self.cursor
+    }
+
+    pub(crate) fn advance(&mut self, cursor: u64) {
+        self.cursor = (cursor != 0).then_some(cursor);
+    }
+
+    // Keys that could not be deleted from the source are sent again, with their latest value.
+    pub(crate) fn retry(&mut self, keys: Vec<Vec<u8>>) {
+        self.retries = keys;
+    }
+
+    pub(crate) fn take_retries(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.retries)
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.cursor.is_none() && self.retries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::shard::migration::Migration;
+
+    #[test]
+    fn finishes_once_the_scan_wraps_around_without_retries() {
+        let mut migration = Migration::new(HashSet::from([3, 1]), 2);
+        assert_eq!(vec![1, 3], migration.slots());
+        assert_eq!(Some(0), migration.cursor());
+
+        migration.advance(42);
+        assert_eq!(Some(42), migration.cursor());
+        migration.advance(0);
+        migration.retry(vec![Vec::from(b"raft")]);
+        assert_eq!(false, migration.is_finished());
+
+        assert_eq!(vec![Vec::from(b"raft")], migration.take_retries());
+        assert_eq!(true, migration.is_finished());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,6 @@
+pub(crate) mod message;
+pub(crate) mod migration;
+pub(crate) mod pipeline;
+pub(crate) mod replication;
+pub(crate) mod router;
+pub(crate) mod worker;
\ No newline at end of file
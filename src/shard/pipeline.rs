@@ -0,0 +1,196 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::executor::command::{Command, CommandResponse};
+use crate::shard::message::RequestId;
+use crate::shard::worker::Shard;
+
+// Responses complete in any order across shards but leave the connection in the order requests arrived. Every request
+// waiting on another shard is found by its id, its sequence is its position counted from the first request drained.
+pub(crate) struct Pipeline {
+    max_in_flight: usize,
+    in_flight: VecDeque<Option<CommandResponse>>,
+    sequences: HashMap<RequestId, u64>,
+    drained: u64,
+}
+
+impl Pipeline {
+    pub(crate) fn new(max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0);
+        Pipeline {
+            max_in_flight,
+            in_flight: VecDeque::with_capacity(max_in_flight),
+            sequences: HashMap::with_capacity(max_in_flight),
+            drained: 0,
+        }
+    }
+
+    pub(crate) fn try_submit(&mut self, shard: &mut Shard, command: Command) -> Result<RequestId, Box<Command>> {
+        if self.is_full() {
+            return Err(Box::new(command));
+        }
+        let request_id = shard.next_request_id();
+        let response = shard.submit(request_id, command);
+        if response.is_none() {
+            self.sequences.insert(request_id, self.drained + self.in_flight.len() as u64);
+        }
+        self.in_flight.push_back(response);
+        Ok(request_id)
+    }
+
+    pub(crate) fn complete(&mut self, request_id: RequestId, response: CommandResponse) -> Result<(), CommandResponse> {
+        match self.sequences.remove(&request_id) {
+            Some(sequence) => {
+                self.in_flight[(sequence - self.drained) as usize] = Some(response);
+                Ok(())
+            }
+            None => Err(response),
+        }
+    }
+
+    pub(crate) fn drain_ready(&mut self) -> Vec<CommandResponse> {
+        let mut ready = Vec::new();
+        while self.in_flight.front().is_some_and(|response| response.is_some()) {
+            ready.push(self.in_flight.pop_front().unwrap().unwrap());
+            self.drained += 1;
+        }
+        ready
+    }
+
+    pub(crate) fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.in_flight.len() >= self.max_in_flight
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::executor::command::Command;
+    use crate::executor::command_executor::CommandExecutor;
+    use crate::memory::log::Log;
+    use crate::memory::options::LogOptions;
+    use crate::shard::pipeline::Pipeline;
+    use crate::shard::router::ShardRouter;
+    use crate::shard::worker::Shard;
+
+    fn shards(number_of_shards: usize) -> Vec<Shard> {
+        let executors = (0..number_of_shards)
+            .map(|_| CommandExecutor::new(Log::new(LogOptions::new(1024, 256))))
+            .collect();
+        Shard::all(executors, 4)
+    }
+
+    fn key_owned_by(router: &ShardRouter, shard: usize) -> Vec<u8> {
+        (0..).map(|index| format!("key-{}", index).into_bytes()).find(|key| router.shard_of(key) == shard).unwrap()
+    }
+
+    #[test]
+    fn responses_leave_in_request_order() {
+        let mut shards = shards(2);
+        let router = ShardRouter::new(2);
+        let remote = key_owned_by(&router, 1);
+        let local = key_owned_by(&router, 0);
+
+        let mut pipeline = Pipeline::new(4);
+        pipeline.try_submit(&mut shards[0], Command::put(remote.clone(), Vec::from(b"remote"))).unwrap();
+        pipeline.try_submit(&mut shards[0], Command::put(local.clone(), Vec::from(b"local"))).unwrap();
+        pipeline.try_submit(&mut shards[0], Command::get(local)).unwrap();
+
+        assert_eq!(true, pipeline.drain_ready().is_empty());
+
+        let mut responses = Vec::new();
+        while !pipeline.is_empty() {
+            shards[1].poll();
+            for (request_id, response) in shards[0].poll() {
+                assert_eq!(true, pipeline.complete(request_id, response).is_ok());
+            }
+            responses.extend(pipeline.drain_ready());
+        }
+        assert_eq!(3, responses.len());
+        let mut responses = responses.into_iter();
+        assert_eq!(true, responses.next().unwrap().put_response());
+        assert_eq!(true, responses.next().unwrap().put_response());
+        assert_eq!(b"local", responses.next().unwrap().get_response().unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn rejects_commands_beyond_the_in_flight_cap() {
+        let mut shards = shards(2);
+        let router = ShardRouter::new(2);
+        let remote = key_owned_by(&router, 1);
+
+        let mut pipeline = Pipeline::new(1);
+        assert_eq!(true, pipeline.try_submit(&mut shards[0], Command::get(remote.clone())).is_ok());
+        assert_eq!(true, pipeline.is_full());
+
+        let rejected = pipeline.try_submit(&mut shards[0], Command::get(remote)).unwrap_err();
+        assert_eq!(1, pipeline.in_flight());
+        assert_eq!(true, rejected.value.is_none());
+    }
+
+    #[test]
+    fn frees_in_flight_slots_once_responses_are_drained() {
+        let mut shards = shards(1);
+        let mut pipeline = Pipeline::new(1);
+
+        pipeline.try_submit(&mut shards[0], Command::put(Vec::from(b"raft"), Vec::from(b"consensus"))).unwrap();
+        assert_eq!(true, pipeline.is_full());
+        assert_eq!(1, pipeline.drain_ready().len());
+        assert_eq!(false, pipeline.is_full());
+        assert_eq!(true, pipeline.try_submit(&mut shards[0], Command::get(Vec::from(b"raft"))).is_ok());
+    }
+
+    #[test]
+    fn does_not_complete_requests_of_other_connections() {
+        let mut shards = shards(2);
+        let router = ShardRouter::new(2);
+        let remote = key_owned_by(&router, 1);
+
+        let mut pipeline = Pipeline::new(2);
+        let mut other = Pipeline::new(2);
+        pipeline.try_submit(&mut shards[0], Command::get(remote)).unwrap();
+
+        shards[1].poll();
+        let (request_id, response) = shards[0].poll().pop().unwrap();
+        let Err(response) = other.complete(request_id, response) else {
+            panic!("completed a request of another connection");
+        };
+        assert_eq!(true, pipeline.complete(request_id, response).is_ok());
+        assert_eq!(1, pipeline.drain_ready().len());
+    }
+
+    #[test]
+    fn completes_requests_out_of_order_after_earlier_ones_were_drained() {
+        let mut shards = shards(2);
+        let router = ShardRouter::new(2);
+        let remote = key_owned_by(&router, 1);
+        let local = key_owned_by(&router, 0);
+
+        let mut pipeline = Pipeline::new(4);
+        pipeline.try_submit(&mut shards[0], Command::put(local, Vec::from(b"consensus"))).unwrap();
+        assert_eq!(1, pipeline.drain_ready().len());
+
+        let first = pipeline.try_submit(&mut shards[0], Command::get(remote.clone())).unwrap();
+        let second = pipeline.try_submit(&mut shards[0], Command::get(remote)).unwrap();
+        shards[1].poll();
+        let mut completed = shards[0].poll();
+        completed.sort_by_key(|(request_id, _)| *request_id);
+
+        let (request_id, response) = completed.pop().unwrap();
+        assert_eq!(second, request_id);
+        assert_eq!(true, pipeline.complete(request_id, response).is_ok());
+        assert_eq!(true, pipeline.drain_ready().is_empty());
+
+        let (request_id, response) = completed.pop().unwrap();
+        assert_eq!(first, request_id);
+        assert_eq!(true, pipeline.complete(request_id, response).is_ok());
+        assert_eq!(2, pipeline.drain_ready().len());
+        assert_eq!(true, pipeline.is_empty());
+    }
+}
\ No newline at end of file
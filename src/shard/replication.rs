@@ -0,0 +1,184 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::memory::key_value::KeyValue;
+
+// Hot entries owned by other shards are copied into a local cache so Gets for them are answered on the
+// receiving core. An owner invalidates every copy it handed out through the same queue it replicated them on,
+// so a copy is stale at most until the invalidation has been polled. A full cache drops its least recently used
+// copy for a new one without telling the owner, which keeps invalidating the shard and sends the key again once the
+// shard forwards a Get for it after the resend interval.
+pub(crate) struct HotKeyReplication {
+    min_accesses: u64,
+    capacity: usize,
+    resend_interval: Duration,
+    replicas: HashMap<Vec<u8>, Replica>,
+    recency: BTreeMap<u64, Vec<u8>>,
+    clock: u64,
+    replicated_to: HashMap<Vec<u8>, Vec<(usize, Instant)>>,
+    replica_bytes: usize,
+}
+
+struct Replica {
+    key_value: KeyValue,
+    last_used: u64,
+}
+
+impl HotKeyReplication {
+    pub(crate) fn new(min_accesses: u64, capacity: usize, resend_interval: Duration) -> Self {
+        HotKeyReplication {
+            min_accesses,
+            capacity,
+            resend_interval,
+            replicas: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            replicated_to: HashMap::new(),
+            replica_bytes: 0,
+        }
+    }
+
+    pub(crate) fn should_replicate(&self, accesses: u64) -> bool {
+        accesses >= self.min_accesses
+    }
+
+    // Returns whether the shard needs a copy of the key, it does unless one was sent within the resend interval. A
+    // shard forwarding a Get for a key it was sent no longer holds the copy, or has not received it yet.
+    pub(crate) fn replicated(&mut self, key: &[u8], shard: usize) -> bool {
+        let shards = self.replicated_to.entry(key.to_vec()).or_default();
+        match shards.iter_mut().find(|(replicated_to, _)| *replicated_to == shard) {
+            Some((_, sent_at)) if sent_at.elapsed() < self.resend_interval => false,
+            Some((_, sent_at)) => {
+                *sent_at = Instant::now();
+                true
+            }
+            None => {
+                shards.push((shard, Instant::now()));
+                true
+            }
+        }
+    }
+
+    pub(crate) fn invalidated(&mut self, key: &[u8]) -> Vec<usize> {
+        if self.replicated_to.is_empty() {
+            return Vec::new();
+        }
+        self.replicated_to.remove(key).unwrap_or_default().into_iter().map(|(shard, _)| shard).collect()
+    }
+
+    pub(crate) fn has_replicated(&self) -> bool {
+        !self.replicated_to.is_empty()
+    }
+
+    pub(crate) fn store(&mut self, key_value: KeyValue) {
+        if self.capacity == 0 {
+            return;
+        }
+        let key = key_value.key();
+        self.evict(&key);
+        if self.replicas.len() == self.capacity {
+            if let Some((_, coldest)) = self.recency.pop_first() {
+                self.evict(&coldest);
+            }
+        }
+        let last_used = self.tick();
+        self.replica_bytes += key.len() + key_value.value().len();
+        self.recency.insert(last_used, key.clone());
+        self.replicas.insert(key, Replica { key_value, last_used });
+    }
+
+    pub(crate) fn get(&mut self, key: &[u8]) -> Option<&KeyValue> {
+        let last_used = self.tick();
+        let replica = self.replicas.get_mut(key)?;
+        self.recency.remove(&replica.last_used);
+        self.recency.insert(last_used, key.to_vec());
+        replica.last_used = last_used;
+        Some(&replica.key_value)
+    }
+
+    // Reads a copy without marking it as used, get does that.
+    pub(crate) fn peek(&self, key: &[u8]) -> Option<&KeyValue> {
+        self.replicas.get(key).map(|replica| &replica.key_value)
+    }
+
+    pub(crate) fn evict(&mut self, key: &[u8]) {
+        if let Some(replica) = self.replicas.remove(key) {
+            self.recency.remove(&replica.last_used);
+            self.replica_bytes -= key.len() + replica.key_value.value().len();
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.replica_bytes
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.replicas.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::memory::key_value::KeyValue;
+    use crate::shard::replication::HotKeyReplication;
+
+    #[test]
+    fn replicates_a_key_to_a_shard_once() {
+        let mut replication = HotKeyReplication::new(4, 8, Duration::from_secs(60));
+        assert_eq!(false, replication.should_replicate(3));
+        assert_eq!(true, replication.should_replicate(4));
+
+        assert_eq!(true, replication.replicated(b"raft", 1));
+        assert_eq!(false, replication.replicated(b"raft", 1));
+        assert_eq!(true, replication.replicated(b"raft", 2));
+        assert_eq!(vec![1, 2], replication.invalidated(b"raft"));
+        assert_eq!(true, replication.invalidated(b"raft").is_empty());
+    }
+
+    #[test]
+    fn replicates_a_key_to_a_shard_again_after_the_resend_interval() {
+        let mut replication = HotKeyReplication::new(4, 8, Duration::ZERO);
+        assert_eq!(true, replication.replicated(b"raft", 1));
+        assert_eq!(true, replication.replicated(b"raft", 1));
+        assert_eq!(vec![1], replication.invalidated(b"raft"));
+    }
+
+    #[test]
+    fn stores_replicas_up_to_the_capacity() {
+        let mut replication = HotKeyReplication::new(4, 1, Duration::from_secs(60));
+        replication.store(KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus")));
+        replication.store(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus")));
+        replication.store(KeyValue::new(Vec::from(b"raft"), Vec::from(b"leader")).with_flags(3));
+
+        assert_eq!(1, replication.len());
+        assert_eq!(b"leader", replication.get(b"raft").unwrap().value());
+        assert_eq!(3, replication.get(b"raft").unwrap().flags());
+        assert_eq!(10, replication.memory_usage());
+
+        replication.evict(b"raft");
+        assert_eq!(true, replication.get(b"raft").is_none());
+        assert_eq!(0, replication.memory_usage());
+    }
+
+    #[test]
+    fn replaces_the_least_recently_used_replica_given_a_full_cache() {
+        let mut replication = HotKeyReplication::new(4, 2, Duration::from_secs(60));
+        replication.store(KeyValue::new(Vec::from(b"raft"), Vec::from(b"consensus")));
+        replication.store(KeyValue::new(Vec::from(b"paxos"), Vec::from(b"consensus")));
+        assert_eq!(true, replication.get(b"raft").is_some());
+
+        replication.store(KeyValue::new(Vec::from(b"zab"), Vec::from(b"atomic")));
+        assert_eq!(2, replication.len());
+        assert_eq!(true, replication.get(b"paxos").is_none());
+        assert_eq!(true, replication.get(b"raft").is_some());
+        assert_eq!(b"atomic", replication.get(b"zab").unwrap().value());
+        assert_eq!(22, replication.memory_usage());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+
+use crate::memory::key_value::hash_of_key;
+
+pub(crate) const NUMBER_OF_SLOTS: usize = 16384;
+
+// Keys hash to a fixed number of slots and slots are assigned to shards, so changing the shards that own slots
+// only moves the keys of the reassigned slots.
+#[derive(Clone)]
+pub(crate) struct ShardRouter {
+    number_of_shards: usize,
+    slots: Vec<u16>,
+}
+
+impl ShardRouter {
+    pub(crate) fn new(number_of_shards: usize) -> Self {
+        assert!(number_of_shards > 0 && number_of_shards <= NUMBER_OF_SLOTS);
+        let slots = (0..NUMBER_OF_SLOTS).map(|slot| (slot * number_of_shards / NUMBER_OF_SLOTS) as u16).collect();
+        ShardRouter { number_of_shards, slots }
+    }
+
+    pub(crate) fn shard_of(&self, key: &[u8]) -> usize {
+        self.owner_of_slot(slot_of(key))
+    }
+
+    pub(crate) fn owner_of_slot(&self, slot: usize) -> usize {
+        self.slots[slot] as usize
+    }
+
+    pub(crate) fn assign(&mut self, slot: usize, shard: usize) {
+        assert!(shard < self.number_of_shards);
+        self.slots[slot] = shard as u16;
+    }
+
+    // An added shard owns no slots until they are assigned to it.
+    pub(crate) fn add_shard(&mut self, shard: usize) {
+        assert!(shard < NUMBER_OF_SLOTS);
+        self.number_of_shards = self.number_of_shards.max(shard + 1);
+    }
+
+    pub(crate) fn slots_of(&self, shard: usize) -> Vec<usize> {
+        (0..NUMBER_OF_SLOTS).filter(|slot| self.owner_of_slot(*slot) == shard).collect()
+    }
+
+    pub(crate) fn number_of_shards(&self) -> usize {
+        self.number_of_shards
+    }
+
+    // Plans the fewest slot moves that spread all slots evenly over the given shards, as (from, to, slots).
+    pub(crate) fn rebalance(&self, shards: &[usize]) -> Vec<(usize, usize, Vec<usize>)> {
+        assert!(!shards.is_empty());
+        let target_of = |position: usize| {
+            NUMBER_OF_SLOTS / shards.len() + usize::from(position < NUMBER_OF_SLOTS % shards.len())
+        };
+
+        let mut surplus = Vec::new();
+        for shard in 0..self.number_of_shards {
+            let owned = self.slots_of(shard);
+            let keep = shards.iter().position(|active| *active == shard).map_or(0, target_of);
+            surplus.extend(owned.into_iter().skip(keep).map(|slot| (shard, slot)));
+        }
+
+        let mut moves: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+        let mut surplus = surplus.into_iter();
+        for (position, shard) in shards.iter().enumerate() {
+            let owned = self.slots_of(*shard).len();
+            for (from, slot) in surplus.by_ref().take(target_of(position).saturating_sub(owned)) {
+                moves.entry((from, *shard)).or_default().push(slot);
+            }
+        }
+        moves.into_iter().map(|((from, to), slots)| (from, to, slots)).collect()
+    }
+}
+
+pub(crate) fn slot_of(key: &[u8]) -> usize {
+    (hash_of_key(routing_key(key)) % NUMBER_OF_SLOTS as u64) as usize
+}
+
+// Keys sharing a non-empty `{...}` hash tag route by the tag alone, so `{user:42}:cart` and `{user:42}:session`
+// are co-located.
+pub(crate) fn routing_key(key: &[u8]) -> &[u8] {
+    let Some(open) = key.iter().position(|byte| *byte == b'{') else {
+        return key;
+    };
+    match key[open + 1..].iter().position(|byte| *byte == b'}') {
+        Some(length) if length > 0 => &key[open + 1..open + 1 + length],
+        _ => key,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shard::router::{routing_key, slot_of, ShardRouter, NUMBER_OF_SLOTS};
+
+    #[test]
+    fn routes_a_key_to_the_same_shard() {
+        let router = ShardRouter::new(4);
+        assert_eq!(router.shard_of(b"raft"), router.shard_of(b"raft"));
+    }
+
+    #[test]
+    fn routes_keys_within_the_number_of_shards() {
+        let router = ShardRouter::new(3);
+        for key in [b"raft".as_slice(), b"paxos", b"zab", b"viewstamped", b"chain"] {
+            assert!(router.shard_of(key) < 3);
+        }
+    }
+
+    #[test]
+    fn routes_all_keys_to_the_only_shard() {
+        let router = ShardRouter::new(1);
+        assert_eq!(0, router.shard_of(b"raft"));
+        assert_eq!(0, router.shard_of(b"paxos"));
+    }
+
+    #[test]
+    fn routes_by_the_hash_tag() {
+        assert_eq!(b"user:42", routing_key(b"{user:42}:cart"));
+        assert_eq!(b"user:42", routing_key(b"session:{user:42}"));
+        assert_eq!(b"a", routing_key(b"{a}{b}"));
+    }
+
+    #[test]
+    fn routes_by_the_whole_key_without_a_hash_tag() {
+        assert_eq!(b"user:42", routing_key(b"user:42"));
+        assert_eq!(b"{}user:42", routing_key(b"{}user:42"));
+        assert_eq!(b"{user:42", routing_key(b"{user:42"));
+        assert_eq!(b"}user{", routing_key(b"}user{"));
+    }
+
+    #[test]
+    fn co_locates_keys_sharing_a_hash_tag() {
+        let router = ShardRouter::new(16);
+        let shard = router.shard_of(b"{user:42}:cart");
+        for key in [b"{user:42}:session".as_slice(), b"{user:42}:profile", b"orders:{user:42}"] {
+            assert_eq!(shard, router.shard_of(key));
+        }
+    }
+
+    #[test]
+    fn assigns_slots_evenly_to_shards() {
+        let router = ShardRouter::new(3);
+        assert_eq!(5462, router.slots_of(0).len());
+        assert_eq!(5461, router.slots_of(1).len());
+        assert_eq!(5461, router.slots_of(2).len());
+    }
+
+    #[test]
+    fn routes_a_key_to_the_owner_of_its_slot() {
+        let mut router = ShardRouter::new(2);
+        let slot = slot_of(b"raft");
+        assert!(slot < NUMBER_OF_SLOTS);
+
+        router.assign(slot, 1 - router.shard_of(b"raft"));
+        assert_eq!(router.owner_of_slot(slot), router.shard_of(b"raft"));
+    }
+
+    #[test]
+    fn rebalances_the_slots_of_a_removed_shard() {
+        let router = ShardRouter::new(3);
+        let moves = router.rebalance(&[0, 1]);
+
+        assert_eq!(true, moves.iter().all(|(from, to, _)| *from == 2 && *to != 2));
+        assert_eq!(5461, moves.iter().map(|(_, _, slots)| slots.len()).sum::<usize>());
+    }
+
+    #[test]
+    fn rebalances_slots_onto_an_added_shard() {
+        let mut router = ShardRouter::new(3);
+        for (_, to, slots) in router.rebalance(&[0, 1]) {
+            for slot in slots {
+                router.assign(slot, to);
+            }
+        }
+        assert_eq!(0, router.slots_of(2).len());
+
+        let moves = router.rebalance(&[0, 1, 2]);
+        assert_eq!(true, moves.iter().all(|(from, to, _)| *from != 2 && *to == 2));
+        for (_, to, slots) in moves {
+            for slot in slots {
+                router.assign(slot, to);
+            }
+        }
+        assert_eq!(vec![5462, 5461, 5461], (0..3).map(|shard| router.slots_of(shard).len()).collect::<Vec<_>>());
+    }
+}
\ No newline at end of file
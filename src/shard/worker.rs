@@ -0,0 +1,1406 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::executor::command::{Command, CommandResponse, CommandType, TransactionError};
+use crate::executor::command_executor::CommandExecutor;
+use crate::executor::{hot_keys, slow_log};
+use crate::executor::stats::StatsReport;
+use crate::memory::key_value::{KeyValue, KeyValueRef};
+use crate::memory::scan::{keyspace_cursor, keyspace_cursor_shard, ScanPage};
+use crate::memory::snapshot::shard_file_name;
+use crate::metrics::registry::ShardMetrics;
+use crate::queue::spsc::{Consumer, Producer, SPSCQueue};
+use crate::shard::message::{RequestId, ShardMessage};
+use crate::shard::migration::{Migration, MIGRATION_BATCH_SIZE};
+use crate::shard::replication::HotKeyReplication;
+use crate::shard::router::{slot_of, ShardRouter};
+
+// Segments of a snapshot in progress written per poll, on top of the one written per executed command.
+const SNAPSHOT_SEGMENTS_PER_POLL: usize = 4;
+
+pub(crate) struct Shard {
+    id: usize,
+    executor: CommandExecutor,
+    router: ShardRouter,
+    inbound: Vec<(usize, Consumer<ShardMessage>)>,
+    outbound: Vec<Option<Producer<ShardMessage>>>,
+    outbox: VecDeque<(usize, ShardMessage)>,
+    pending: HashMap<RequestId, PendingRequest>,
+    completed: Vec<(RequestId, CommandResponse)>,
+    next_request_id: RequestId,
+    metrics: Arc<ShardMetrics>,
+    replication: Option<HotKeyReplication>,
+    migrations: VecDeque<Migration>,
+    importing: HashMap<usize, usize>,
+}
+
+struct PendingRequest {
+    kind: PendingKind,
+    responses: Vec<Option<CommandResponse>>,
+    outstanding: usize,
+}
+
+enum PendingKind {
+    Single,
+    MultiKey,
+    Scan(usize),
+    Stats,
+    SlowLog(usize),
+    HotKeys(usize),
+    Snapshot,
+    ScanKeyspace { shard: usize, number_of_shards: usize },
+}
+
+impl Shard {
+    pub(crate) fn all(executors: Vec<CommandExecutor>, queue_capacity: usize) -> Vec<Shard> {
+        let number_of_shards = executors.len();
+        let router = ShardRouter::new(number_of_shards);
+        let mut shards: Vec<Shard> = executors
+            .into_iter()
+            .enumerate()
+            .map(|(id, executor)| Shard::new(id, executor, router.clone()))
+            .collect();
+        for source in 0..number_of_shards {
+            for target in (0..number_of_shards).filter(|target| *target != source) {
+                let (producer, consumer) = SPSCQueue::new(queue_capacity).split();
+                shards[source].outbound[target] = Some(producer);
+                shards[target].inbound.push((source, consumer));
+            }
+        }
+        shards
+    }
+
+    fn new(id: usize, executor: CommandExecutor, router: ShardRouter) -> Self {
+        Shard {
+            id,
+            executor,
+            outbound: (0..router.number_of_shards()).map(|_| None).collect(),
+            router,
+            inbound: Vec::new(),
+            outbox: VecDeque::new(),
+            pending: HashMap::new(),
+            completed: Vec::new(),
+            next_request_id: 0,
+            metrics: Arc::new(ShardMetrics::new(id)),
+            replication: None,
+            migrations: VecDeque::new(),
+            importing: HashMap::new(),
+        }
+    }
+
+    // Adds a shard that owns no slots yet, this shard connects it to the others by handing each of them the ends of
+    // their queues to the new shard. Slots are moved to it with migrate_slots, and the returned shard is polled like
+    // any other. Shards are never removed, a shard is retired by migrating all of its slots away, after which it only
+    // answers fan-out commands from an empty log.
+    pub(crate) fn add_shard(&mut self, executor: CommandExecutor, queue_capacity: usize) -> Shard {
+        let id = self.router.number_of_shards();
+        self.router.add_shard(id);
+        let mut shard = Shard::new(id, executor, self.router.clone());
+        for other in 0..id {
+            let (other_to_shard, shard_from_other) = SPSCQueue::new(queue_capacity).split();
+            let (shard_to_other, other_from_shard) = SPSCQueue::new(queue_capacity).split();
+            shard.connect(other, shard_to_other, shard_from_other);
+            if other == self.id {
+                self.connect(id, other_to_shard, other_from_shard);
+            } else {
+                let message = ShardMessage::ShardAdded { shard: id, producer: other_to_shard, consumer: other_from_shard };
+                self.send(other, message);
+            }
+        }
+        shard
+    }
+
+    // Gets of keys accessed at least min_accesses times on their owner are answered by the shards that asked
+    // for them from a local copy, until the owner invalidates it. A copy dropped by a full cache is sent again for a
+    // Get forwarded after the resend interval.
+    pub(crate) fn with_hot_key_replication(
+        mut self,
+        min_accesses: u64,
+        capacity: usize,
+        resend_interval: Duration,
+    ) -> Self {
+        self.replication = Some(HotKeyReplication::new(min_accesses, capacity, resend_interval));
+        self
+    }
+
+    pub(crate) fn id(&self) -> usize {
+        self.id
+    }
+
+    pub(crate) fn metrics(&self) -> Arc<ShardMetrics> {
+        self.metrics.clone()
+    }
+
+    pub(crate) fn next_request_id(&mut self) -> RequestId {
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        request_id
+    }
+
+    pub(crate) fn submit(&mut self, request_id: RequestId, command: Command) -> Option<CommandResponse> {
+        if command.command_type.is_mutating() {
+            self.evict_replicas(&command);
+        }
+        if command.command_type.is_multi_key() {
+            return self.submit_multi_key(request_id, command);
+        }
+        if matches!(
+            command.command_type,
+            CommandType::Scan | CommandType::Stats | CommandType::SlowLog | CommandType::HotKeys | CommandType::Snapshot
+        ) {
+            return self.submit_to_all(request_id, command);
+        }
+        if command.command_type == CommandType::ScanKeyspace {
+            return self.submit_scan_keyspace(request_id, command);
+        }
+        if command.command_type == CommandType::ShardOf {
+            return Some(CommandResponse::ShardOf(self.router.shard_of(&command.key)));
+        }
+        let owner = match self.route(&command, None) {
+            Ok(owner) => owner,
+            Err(error) => return Some(CommandResponse::Transaction(Err(error))),
+        };
+        if owner == self.id {
+            return Some(self.execute(command));
+        }
+        if command.command_type == CommandType::Get {
+            if let Some(key_value) = self.replication.as_mut().and_then(|replication| replication.get(&command.key)) {
+                return Some(CommandResponse::Get(Some(Ok(key_value.clone()))));
+            }
+        }
+        self.pending.insert(request_id, PendingRequest::new(PendingKind::Single, 1));
+        self.send(owner, ShardMessage::Request { origin: self.id, request_id, commands: vec![(0, command)] });
+        None
+    }
+
+    // The Get of the network layer, a key owned by this shard or replicated to it is answered with a view of the
+    // segment or the replica holding the value, which is written out from there without a copy. A Get for a key of
+    // another shard is forwarded like submit does, None is returned and its owned response arrives through poll.
+    pub(crate) fn submit_get(
+        &mut self,
+        request_id: RequestId,
+        key: Vec<u8>,
+    ) -> Option<Option<Result<KeyValueRef<'_>, Error>>> {
+        let owner = self.destination_of(&key, None);
+        if owner == self.id {
+            let started_at = Instant::now();
+            let response = self.executor.execute_get_ref(&key);
+            self.metrics.record(CommandType::Get, started_at.elapsed());
+            return Some(response);
+        }
+        if self.replication.as_mut().is_some_and(|replication| replication.get(&key).is_some()) {
+            let replica = self.replication.as_ref().and_then(|replication| replication.peek(&key));
+            return Some(replica.map(|key_value| Ok(key_value.as_key_value_ref())));
+        }
+        self.pending.insert(request_id, PendingRequest::new(PendingKind::Single, 1));
+        self.send(owner, ShardMessage::Request { origin: self.id, request_id, commands: vec![(0, Command::get(key))] });
+        None
+    }
+
+    pub(crate) fn poll(&mut self) -> Vec<(RequestId, CommandResponse)> {
+        self.flush_outbox();
+        for position in 0..self.inbound.len() {
+            let source = self.inbound[position].0;
+            while let Some(message) = self.inbound[position].1.try_dequeue() {
+                match message {
+                    ShardMessage::Request { origin, request_id, commands } =>
+                        self.serve(origin, source, request_id, commands),
+                    ShardMessage::Response { request_id, responses } => self.gather(request_id, responses),
+                    ShardMessage::Replicate { key_value } => {
+                        if let Some(replication) = self.replication.as_mut() {
+                            replication.store(key_value);
+                        }
+                    }
+                    ShardMessage::Invalidate { key } => {
+                        if let Some(replication) = self.replication.as_mut() {
+                            replication.evict(&key);
+                        }
+                    }
+                    ShardMessage::SlotsMigrating { slots } =>
+                        self.importing.extend(slots.into_iter().map(|slot| (slot, source))),
+                    ShardMessage::MigratedEntries { entries } => {
+                        // A rejected put shows up in the rejected appends of the target. The keys keep their versions
+                        // so that a version read from the source still compares equal.
+                        for (key_value, expires_at) in entries {
+                            let command = Command::put(key_value.key(), key_value.value().to_vec())
+                                .with_flags(key_value.flags())
+                                .with_version(key_value.version());
+                            self.execute(Command { expires_at, ..command });
+                        }
+                    }
+                    ShardMessage::SlotsAssigned { slots, owner } => {
+                        // The owner may be a shard whose ShardAdded is still queued on another source.
+                        self.router.add_shard(owner);
+                        for slot in slots {
+                            self.router.assign(slot, owner);
+                            self.importing.remove(&slot);
+                        }
+                    }
+                    ShardMessage::ShardAdded { shard, producer, consumer } => self.connect(shard, producer, consumer),
+                }
+            }
+        }
+        self.migrate();
+        self.executor.advance_snapshot(SNAPSHOT_SEGMENTS_PER_POLL);
+        self.flush_outbox();
+        // A failed sync is retried on the next poll, the records stay in the page cache until then.
+        let _ = self.executor.sync_write_ahead_log();
+        std::mem::take(&mut self.completed)
+    }
+
+    // Moves the slots to the target shard in the background, one migration at a time. Slots this shard does not
+    // own or already migrates are left out.
+    pub(crate) fn migrate_slots(&mut self, slots: Vec<usize>, target: usize) {
+        assert!(target < self.router.number_of_shards());
+        let slots: HashSet<usize> = slots
+            .into_iter()
+            .filter(|slot| self.router.owner_of_slot(*slot) == self.id)
+            .filter(|slot| self.migrations.iter().all(|migration| !migration.covers(*slot)))
+            .collect();
+        if target == self.id || slots.is_empty() {
+            return;
+        }
+        self.migrations.push_back(Migration::new(slots, target));
+        if self.migrations.len() == 1 {
+            self.begin_migration();
+        }
+    }
+
+    pub(crate) fn is_migrating(&self) -> bool {
+        !self.migrations.is_empty()
+    }
+
+    pub(crate) fn router(&self) -> &ShardRouter {
+        &self.router
+    }
+
+    fn connect(&mut self, shard: usize, producer: Producer<ShardMessage>, consumer: Consumer<ShardMessage>) {
+        self.router.add_shard(shard);
+        if self.outbound.len() <= shard {
+            self.outbound.resize_with(shard + 1, || None);
+        }
+        self.outbound[shard] = Some(producer);
+        self.inbound.push((shard, consumer));
+    }
+
+    fn submit_multi_key(&mut self, request_id: RequestId, command: Command) -> Option<CommandResponse> {
+        let total = command.batch.len();
+        let mut commands_by_shard: HashMap<usize, Vec<(usize, Command)>> = HashMap::new();
+        for (position, command) in command.batch.into_iter().enumerate() {
+            commands_by_shard.entry(self.destination_of(&command.key, None)).or_default().push((position, command));
+        }
+
+        let mut pending = PendingRequest::new(PendingKind::MultiKey, total);
+        for (shard, commands) in commands_by_shard {
+            if shard == self.id {
+                for (position, command) in commands {
+                    pending.responses[position] = Some(self.execute(command));
+                    pending.outstanding -= 1;
+                }
+            } else {
+                self.send(shard, ShardMessage::Request { origin: self.id, request_id, commands });
+            }
+        }
+        if pending.outstanding == 0 {
+            return Some(pending.into_response());
+        }
+        self.pending.insert(request_id, pending);
+        None
+    }
+
+    // Every shard answers from its own state, the responses are merged once all of them arrive. Each shard writes
+    // its part of a snapshot to a file of its own.
+    fn submit_to_all(&mut self, request_id: RequestId, command: Command) -> Option<CommandResponse> {
+        let kind = match command.command_type {
+            CommandType::Scan => PendingKind::Scan(command.scan.as_ref().map_or(1, |request| request.limit())),
+            CommandType::SlowLog => PendingKind::SlowLog(command.count.unwrap_or(0) as usize),
+            CommandType::HotKeys => PendingKind::HotKeys(command.count.unwrap_or(0) as usize),
+            CommandType::Snapshot => PendingKind::Snapshot,
+            _ => PendingKind::Stats,
+        };
+        let number_of_shards = self.router.number_of_shards();
+        let mut pending = PendingRequest::new(kind, number_of_shards);
+        for shard in 0..number_of_shards {
+            let mut command = command.clone();
+            if command.command_type == CommandType::Snapshot {
+                command.key = shard_file_name(&command.key, shard);
+            }
+            if shard == self.id {
+                pending.responses[shard] = Some(self.execute(command));
+                pending.outstanding -= 1;
+            } else {
+                self.send(shard, ShardMessage::Request { origin: self.id, request_id, commands: vec![(shard, command)] });
+            }
+        }
+        if pending.outstanding == 0 {
+            return Some(pending.into_response());
+        }
+        self.pending.insert(request_id, pending);
+        None
+    }
+
+    // Shards are walked one after another, each to the end of its own index before the cursor moves on.
+    fn submit_scan_keyspace(&mut self, request_id: RequestId, command: Command) -> Option<CommandResponse> {
+        let shard = keyspace_cursor_shard(command.cursor.unwrap_or(0));
+        let number_of_shards = self.router.number_of_shards();
+        if shard >= number_of_shards {
+            return Some(CommandResponse::ScanKeyspace(Vec::new(), 0));
+        }
+        if shard == self.id {
+            return Some(continue_scan_keyspace(self.execute(command), shard, number_of_shards));
+        }
+        self.pending.insert(request_id, PendingRequest::new(PendingKind::ScanKeyspace { shard, number_of_shards }, 1));
+        self.send(shard, ShardMessage::Request { origin: self.id, request_id, commands: vec![(0, command)] });
+        None
+    }
+
+    pub(crate) fn queue_depth(&self) -> usize {
+        self.inbound.iter().map(|(_, queue)| queue.len()).sum::<usize>() + self.outbox.len()
+    }
+
+    // Only a Get that hit a hot key is replicated, and only to a shard that does not hold a copy yet.
+    fn replicate(&mut self, origin: usize, key: Vec<u8>, response: &CommandResponse) {
+        let Some(replication) = self.replication.as_mut() else {
+            return;
+        };
+        let CommandResponse::Get(Some(Ok(key_value))) = response else {
+            return;
+        };
+        // A copy does not know the deadline of the key, it could answer after the key expired.
+        if origin == self.id || self.executor.expires_at(&key).is_some() {
+            return;
+        }
+        if !replication.should_replicate(self.executor.hot_keys().accesses_of(&key)) || !replication.replicated(&key, origin) {
+            return;
+        }
+        self.send(origin, ShardMessage::Replicate { key_value: key_value.clone() });
+    }
+
+    // The write may not have reached the owner by the next Get, a stale copy must not answer it.
+    fn evict_replicas(&mut self, command: &Command) {
+        if let Some(replication) = self.replication.as_mut() {
+            for command in command.batch.iter().chain(Some(command)) {
+                replication.evict(&command.key);
+            }
+        }
+    }
+
+    fn invalidate(&mut self, command: &Command) {
+        let Some(replication) = self.replication.as_mut() else {
+            return;
+        };
+        if !command.command_type.is_mutating() || !replication.has_replicated() {
+            return;
+        }
+        let mut invalidations = Vec::new();
+        for command in command.batch.iter().chain(Some(command)) {
+            for shard in replication.invalidated(&command.key) {
+                invalidations.push((shard, command.key.clone()));
+            }
+        }
+        for (shard, key) in invalidations {
+            self.send(shard, ShardMessage::Invalidate { key });
+        }
+    }
+
+    // Messages waiting in the queues, with the keys and values they carry, and the replicas of other shards' keys
+    // count against the memory budget.
+    fn buffer_bytes(&self) -> usize {
+        self.inbound.iter().flat_map(|(_, queue)| queue.iter()).map(ShardMessage::memory_usage).sum::<usize>() +
+            self.outbox.iter().map(|(_, message)| message.memory_usage()).sum::<usize>() +
+            self.replication.as_ref().map_or(0, HotKeyReplication::memory_usage)
+    }
+
+    fn invalidate_evicted(&mut self) {
+        let Some(replication) = self.replication.as_mut() else {
+            return;
+        };
+        let mut invalidations = Vec::new();
+        for key in self.executor.evicted_keys() {
+            for shard in replication.invalidated(key) {
+                invalidations.push((shard, key.clone()));
+            }
+        }
+        for (shard, key) in invalidations {
+            self.send(shard, ShardMessage::Invalidate { key });
+        }
+    }
+
+    fn execute(&mut self, command: Command) -> CommandResponse {
+        self.invalidate(&command);
+        if command.command_type.allocates() && self.executor.has_memory_budget() {
+            self.executor.set_buffer_bytes(self.buffer_bytes());
+        }
+        let command_type = command.command_type;
+        let started_at = Instant::now();
+        let response = self.executor.execute(command);
+        self.metrics.record(command_type, started_at.elapsed());
+        self.invalidate_evicted();
+        self.metrics.record_key_accesses(self.executor.hot_keys().hottest(), self.executor.hot_keys().total());
+
+        match response {
+            CommandResponse::Stats(report) => {
+                let mut shards = report.shards;
+                for stats in shards.iter_mut() {
+                    stats.queue_depth = self.queue_depth();
+                }
+                CommandResponse::Stats(Box::new(StatsReport::of(shards)))
+            }
+            CommandResponse::SlowLog(mut entries) => {
+                for entry in entries.iter_mut() {
+                    entry.shard = self.id;
+                }
+                CommandResponse::SlowLog(entries)
+            }
+            CommandResponse::HotKeys(mut hot_keys) => {
+                for hot_key in hot_keys.iter_mut() {
+                    hot_key.shard = self.id;
+                }
+                CommandResponse::HotKeys(hot_keys)
+            }
+            response => response,
+        }
+    }
+
+    // Commands received from another shard are executed here or redirected once more, the response goes straight
+    // to the origin either way. The sender is the shard whose queue the commands arrived on.
+    fn serve(&mut self, origin: usize, sender: usize, request_id: RequestId, commands: Vec<(usize, Command)>) {
+        let mut responses = Vec::new();
+        let mut redirects: HashMap<usize, Vec<(usize, Command)>> = HashMap::new();
+        for (position, command) in commands {
+            match self.route(&command, Some(sender)) {
+                Ok(shard) if shard != self.id => redirects.entry(shard).or_default().push((position, command)),
+                Ok(_) => {
+                    let key = (command.command_type == CommandType::Get).then(|| command.key.clone());
+                    let response = self.execute(command);
+                    if let Some(key) = key {
+                        self.replicate(origin, key, &response);
+                    }
+                    responses.push((position, response));
+                }
+                Err(error) => responses.push((position, CommandResponse::Transaction(Err(error)))),
+            }
+        }
+        for (shard, commands) in redirects {
+            self.send(shard, ShardMessage::Request { origin, request_id, commands });
+        }
+        if responses.is_empty() {
+            return;
+        }
+        if origin == self.id {
+            self.gather(request_id, responses);
+        } else {
+            self.send(origin, ShardMessage::Response { request_id, responses });
+        }
+    }
+
+    fn route(&self, command: &Command, sender: Option<usize>) -> Result<usize, TransactionError> {
+        match command.command_type {
+            CommandType::Transaction => {
+                let mut destinations = command.batch.iter().map(|command| self.destination_of(&command.key, sender));
+                let destination = destinations.next().unwrap_or(self.id);
+                if destinations.all(|other| other == destination) {
+                    return Ok(destination);
+                }
+                let mut owners = command.batch.iter().map(|command| self.router.shard_of(&command.key));
+                let owner = owners.next().unwrap_or(self.id);
+                if owners.all(|other| other == owner) {
+                    return Err(TransactionError::Migrating);
+                }
+                Err(TransactionError::CrossShard)
+            }
+            command_type if command_type.accesses_key() => Ok(self.destination_of(&command.key, sender)),
+            _ => Ok(self.id),
+        }
+    }
+
+    // A slot being imported is only served for requests redirected by its source, which has already checked
+    // that the key moved and sends them behind the migrated entries. Any other shard that already learned the new
+    // owner is sent back to the source, so its write can not be overwritten by an entry still queued from the
+    // source. A slot being migrated away is only served for the keys that have not moved yet.
+    fn destination_of(&self, key: &[u8], sender: Option<usize>) -> usize {
+        let slot = slot_of(key);
+        if sender.is_some() && self.importing.get(&slot).copied() == sender {
+            return self.id;
+        }
+        let owner = self.router.owner_of_slot(slot);
+        if owner != self.id {
+            return owner;
+        }
+        match self.migrations.front() {
+            Some(migration) if migration.covers(slot) && !self.executor.contains_key(key) =>
+                migration.target(),
+            _ => self.id,
+        }
+    }
+
+    fn begin_migration(&mut self) {
+        if let Some(migration) = self.migrations.front() {
+            let (target, slots) = (migration.target(), migration.slots());
+            self.send(target, ShardMessage::SlotsMigrating { slots });
+        }
+    }
+
+    fn migrate(&mut self) {
+        let Some(migration) = self.migrations.front_mut() else {
+            return;
+        };
+        let mut keys = migration.take_retries();
+        if let Some(cursor) = migration.cursor() {
+            let (scanned, cursor) = self.executor.scan_keys(cursor, MIGRATION_BATCH_SIZE);
+            keys.extend(scanned.into_iter().filter(|key| migration.covers(slot_of(key))));
+            migration.advance(cursor);
+        }
+        let target = migration.target();
+
+        let entries: Vec<(KeyValue, Option<u64>)> = keys
+            .into_iter()
+            .filter_map(|key| {
+                let key_value = self.executor.get_ref(&key)?.ok()?.to_key_value();
+                Some((key_value, self.executor.expires_at(&key)))
+            })
+            .collect();
+        let moved: Vec<Vec<u8>> = entries.iter().map(|(key_value, _)| key_value.key()).collect();
+        if !entries.is_empty() {
+            self.send(target, ShardMessage::MigratedEntries { entries });
+        }
+        let mut retries = Vec::new();
+        for key in moved {
+            if !self.execute(Command::delete(key.clone())).delete_response() {
+                retries.push(key);
+            }
+        }
+
+        let migration = self.migrations.front_mut().unwrap();
+        migration.retry(retries);
+        if !migration.is_finished() {
+            return;
+        }
+        let slots = self.migrations.pop_front().unwrap().slots();
+        for slot in &slots {
+            self.router.assign(*slot, target);
+        }
+        for shard in 0..self.router.number_of_shards() {
+            if shard != self.id {
+                self.send(shard, ShardMessage::SlotsAssigned { slots: slots.clone(), owner: target });
+            }
+        }
+        self.begin_migration();
+    }
+
+    fn gather(&mut self, request_id: RequestId, responses: Vec<(usize, CommandResponse)>) {
+        let Some(pending) = self.pending.get_mut(&request_id) else {
+            return;
+        };
+        for (position, response) in responses {
+            pending.responses[position] = Some(response);
+            pending.outstanding -= 1;
+        }
+        if pending.outstanding == 0 {
+            let pending = self.pending.remove(&request_id).unwrap();
+            self.completed.push((request_id, pending.into_response()));
+        }
+    }
+
+    fn send(&mut self, target: usize, message: ShardMessage) {
+        self.outbox.push_back((target, message));
+        self.flush_outbox();
+    }
+
+    fn flush_outbox(&mut self) {
+        while let Some((target, message)) = self.outbox.pop_front() {
+            // Not connected to an added shard yet, its ShardAdded is still queued on another source.
+            let Some(queue) = self.outbound.get_mut(target).and_then(Option::as_mut) else {
+                self.outbox.push_front((target, message));
+                return;
+            };
+            if let Err(message) = queue.try_push(message) {
+                self.outbox.push_front((target, message));
+                return;
+            }
+        }
+    }
+}
+
+impl PendingRequest {
+    fn new(kind: PendingKind, outstanding: usize) -> Self {
+        PendingRequest { kind, responses: (0..outstanding).map(|_| None).collect(), outstanding }
+    }
+
+    fn into_response(self) -> CommandResponse {
+        let mut responses = self.responses.into_iter().map(|response| response.unwrap());
+        match self.kind {
+            PendingKind::Single => responses.next().unwrap(),
+            PendingKind::MultiKey => CommandResponse::Multi(responses.collect()),
+            PendingKind::Scan(limit) => {
+                let pages: Option<Vec<ScanPage>> = responses.map(CommandResponse::scan_response).collect();
+                CommandResponse::Scan(pages.map(|pages| ScanPage::merge(pages, limit)))
+            }
+            PendingKind::Stats => CommandResponse::Stats(Box::new(StatsReport::of(responses
+                .filter_map(CommandResponse::stats_response)
+                .flat_map(|report| report.shards)
+                .collect()))),
+            PendingKind::SlowLog(count) => CommandResponse::SlowLog(slow_log::merge_latest(responses
+                .filter_map(CommandResponse::slow_log_response)
+                .flatten()
+                .collect(), count)),
+            PendingKind::HotKeys(count) => CommandResponse::HotKeys(hot_keys::merge_top(responses
+                .filter_map(CommandResponse::hot_keys_response)
+                .flatten()
+                .collect(), count)),
+            PendingKind::Snapshot => CommandResponse::Snapshot(responses.all(|response| response.snapshot_response())),
+            PendingKind::ScanKeyspace { shard, number_of_shards } =>
+                continue_scan_keyspace(responses.next().unwrap(), shard, number_of_shards),
+        }
+    }
+}
+
+fn continue_scan_keyspace(response: CommandResponse, shard: usize, number_of_shards: usize) -> CommandResponse {
+    let Some((keys, position)) = response.scan_keyspace_response() else {
+        return CommandResponse::ScanKeyspace(Vec::new(), 0);
+    };
+    let cursor = match position {
+        0 if shard + 1 < number_of_shards => keyspace_cursor(shard + 1, 0),
+        0 => 0,
+        position => keyspace_cursor(shard, position),
+    };
+    CommandResponse::ScanKeyspace(keys, cursor)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::executor::command::{Command, CommandResponse, CommandType, TransactionError};
+    use crate::executor::command_executor::CommandExecutor;
+    use crate::memory::budget::{EvictionPolicy, MemoryBudget};
+    use crate::memory::log::{CompareAndSwapResult, Log};
+    use crate::memory::options::LogOptions;
+    use crate::memory::scan::ScanRequest;
+    use crate::memory::snapshot::Snapshot;
+    use crate::shard::message::RequestId;
+    use crate::shard::router::{slot_of, ShardRouter};
+    use crate::shard::worker::Shard;
+
+    fn shards(number_of_shards: usize, queue_capacity: usize) -> Vec<Shard> {
+        let executors = (0..number_of_shards)
+            .map(|_| CommandExecutor::new(Log::new(LogOptions::new(1024, 256).with_ordered_index())))
+            .collect();
+        Shard::all(executors, queue_capacity)
+    }
+
+    fn execute(shards: &mut [Shard], origin: usize, request_id: RequestId, command: Command) -> CommandResponse {
+        if let Some(response) = shards[origin].submit(request_id, command) {
+            return response;
+        }
+        loop {
+            for shard in shards.iter_mut() {
+                if let Some((completed_id, response)) = shard.poll().pop() {
+                    assert_eq!(origin, shard.id());
+                    assert_eq!(request_id, completed_id);
+                    return response;
+                }
+            }
+        }
+    }
+
+    fn keys() -> Vec<Vec<u8>> {
+        ["raft", "paxos", "zab", "viewstamped", "chain", "epaxos", "gossip", "bully"]
+            .iter()
+            .map(|key| key.as_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn keys_span_multiple_shards() {
+        let router = ShardRouter::new(3);
+        let mut owners: Vec<usize> = keys().iter().map(|key| router.shard_of(key)).collect();
+        owners.sort();
+        owners.dedup();
+        assert_eq!(3, owners.len());
+    }
+
+    #[test]
+    fn execute_a_single_key_command_on_the_owning_shard() {
+        let mut shards = shards(3, 4);
+        for (request_id, key) in keys().into_iter().enumerate() {
+            let response = execute(&mut shards, 0, request_id as u64, Command::put(key, Vec::from(b"consensus")));
+            assert_eq!(true, response.put_response());
+        }
+        for (request_id, key) in keys().into_iter().enumerate() {
+            let response = execute(&mut shards, 1, 100 + request_id as u64, Command::get(key));
+            assert_eq!(b"consensus", response.get_response().unwrap().unwrap().value());
+        }
+    }
+
+    #[test]
+    fn multi_put_and_multi_get_across_shards() {
+        let mut shards = shards(3, 4);
+        let key_values = keys().into_iter().map(|key| (key.clone(), key)).collect();
+        let responses = execute(&mut shards, 0, 1, Command::multi_put(key_values)).multi_response();
+        assert_eq!(8, responses.len());
+        assert_eq!(true, responses.iter().all(|response| response.put_response()));
+
+        let responses = execute(&mut shards, 2, 2, Command::multi_get(keys())).multi_response();
+        for (key, response) in keys().into_iter().zip(responses) {
+            assert_eq!(key, response.get_response().unwrap().unwrap().value());
+        }
+    }
+
+    #[test]
+    fn multi_get_reports_missing_keys_per_key() {
+        let mut shards = shards(3, 4);
+        execute(&mut shards, 0, 1, Command::put(Vec::from(b"raft"), Vec::from(b"consensus")));
+
+        let responses = execute(&mut shards, 1, 2, Command::multi_get(vec![Vec::from(b"paxos"), Vec::from(b"raft")])).multi_response();
+        let mut responses = responses.into_iter();
+        assert_eq!(true, responses.next().unwrap().get_response().is_none());
+        assert_eq!(b"consensus", responses.next().unwrap().get_response().unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn multi_delete_across_shards() {
+        let mut shards = shards(3, 4);
+        let key_values = keys().into_iter().map(|key| (key.clone(), key)).collect();
+        execute(&mut shards, 0, 1, Command::multi_put(key_values));
+
+        let mut to_delete = keys();
+        to_delete.push(Vec::from(b"missing"));
+        let responses = execute(&mut shards, 1, 2, Command::multi_delete(to_delete)).multi_response();
+        assert_eq!(true, responses[..8].iter().all(|response| response.delete_response()));
+        assert_eq!(false, responses[8].delete_response());
+
+        let responses = execute(&mut shards, 2, 3, Command::multi_get(keys())).multi_response();
+        assert_eq!(true, responses.into_iter().all(|response| response.get_response().is_none()));
+    }
+
+    #[test]
+    fn retries_messages_given_a_full_queue() {
+        let mut shards = shards(2, 1);
+        let router = ShardRouter::new(2);
+        let remote: Vec<Vec<u8>> = keys().into_iter().filter(|key| router.shard_of(key) == 1).collect();
+        assert_eq!(true, remote.len() > 1);
+
+        for (request_id, key) in remote.iter().enumerate() {
+            assert_eq!(true, shards[0].submit(request_id as u64, Command::put(key.clone(), Vec::from(b"consensus"))).is_none());
+        }
+        let mut completed = Vec::new();
+        while completed.len() < remote.len() {
+            shards[1].poll();
+            completed.extend(shards[0].poll());
+        }
+        assert_eq!(true, completed.into_iter().all(|(_, response)| response.put_response()));
+    }
+
+    #[test]
+    fn execute_a_transaction_on_the_owning_shard() {
+        let mut shards = shards(2, 4);
+        let router = ShardRouter::new(2);
+        let remote: Vec<Vec<u8>> = keys().into_iter().filter(|key| router.shard_of(key) == 1).collect();
+
+        let commands = remote.iter().map(|key| Command::put(key.clone(), Vec::from(b"consensus"))).collect();
+        let responses = execute(&mut shards, 0, 1, Command::transaction(commands)).transaction_response().unwrap();
+        assert_eq!(remote.len(), responses.len());
+
+        let response = execute(&mut shards, 0, 2, Command::get(remote[0].clone()));
+        assert_eq!(b"consensus", response.get_response().unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn reject_a_transaction_spanning_shards() {
+        let mut shards = shards(3, 4);
+        let commands = keys().into_iter().map(|key| Command::put(key, Vec::from(b"consensus"))).collect();
+
+        let response = shards[0].submit(1, Command::transaction(commands)).unwrap();
+        assert_eq!(Err(TransactionError::CrossShard), response.transaction_response().map(|responses| responses.len()));
+    }
+
+    #[test]
+    fn report_the_shard_owning_a_key() {
+        let mut shards = shards(3, 4);
+        let router = ShardRouter::new(3);
+        for key in keys() {
+            let response = shards[0].submit(1, Command::shard_of(key.clone())).unwrap();
+            assert_eq!(Some(router.shard_of(&key)), response.shard_of_response());
+        }
+    }
+
+    #[test]
+    fn execute_a_transaction_over_keys_sharing_a_hash_tag() {
+        let mut shards = shards(3, 4);
+        let commands = keys().into_iter()
+            .map(|key| Command::put([b"{user:42}:".as_slice(), &key].concat(), Vec::from(b"consensus")))
+            .collect();
+
+        let responses = execute(&mut shards, 0, 1, Command::transaction(commands)).transaction_response().unwrap();
+        assert_eq!(8, responses.len());
+    }
+
+    #[test]
+    fn scan_keys_across_shards_page_by_page() {
+        let mut shards = shards(3, 4);
+        let key_values = keys().into_iter().map(|key| ([b"node:".as_slice(), &key].concat(), key)).collect();
+        execute(&mut shards, 0, 1, Command::multi_put(key_values));
+        execute(&mut shards, 0, 2, Command::put(Vec::from(b"other"), Vec::from(b"consensus")));
+
+        let mut scanned = Vec::new();
+        let mut cursor = None;
+        let mut request_id = 3;
+        loop {
+            let request = ScanRequest::new(3).with_prefix(Vec::from(b"node:")).with_cursor(cursor);
+            let page = execute(&mut shards, 1, request_id, Command::scan(request)).scan_response().unwrap();
+            assert_eq!(true, page.keys.len() <= 3);
+            scanned.extend(page.keys);
+            cursor = page.cursor;
+            request_id += 1;
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        let mut expected: Vec<Vec<u8>> = keys().into_iter().map(|key| [b"node:".as_slice(), &key].concat()).collect();
+        expected.sort();
+        assert_eq!(expected, scanned);
+    }
+
+    #[test]
+    fn scan_keys_across_shards_within_a_range() {
+        let mut shards = shards(3, 4);
+        let key_values = keys().into_iter().map(|key| (key.clone(), key)).collect();
+        execute(&mut shards, 0, 1, Command::multi_put(key_values));
+
+        let request = ScanRequest::new(10).with_range(Some(Vec::from(b"c")), Some(Vec::from(b"p")));
+        let page = execute(&mut shards, 2, 2, Command::scan(request)).scan_response().unwrap();
+        assert_eq!(vec![Vec::from(b"chain"), Vec::from(b"epaxos"), Vec::from(b"gossip")], page.keys);
+        assert_eq!(None, page.cursor);
+    }
+
+    #[test]
+    fn scan_the_keyspace_across_shards() {
+        let mut shards = shards(3, 4);
+        let key_values = keys().into_iter().map(|key| (key.clone(), key)).collect();
+        execute(&mut shards, 0, 1, Command::multi_put(key_values));
+
+        let mut scanned = Vec::new();
+        let mut cursor = 0;
+        let mut request_id = 2;
+        loop {
+            let (keys, next) = execute(&mut shards, 1, request_id, Command::scan_keyspace(cursor, 2)).scan_keyspace_response().unwrap();
+            scanned.extend(keys);
+            cursor = next;
+            request_id += 1;
+            if cursor == 0 {
+                break;
+            }
+        }
+        scanned.sort();
+        let mut expected = keys();
+        expected.sort();
+        assert_eq!(expected, scanned);
+    }
+
+    #[test]
+    fn report_stats_per_shard_and_in_total() {
+        let mut shards = shards(3, 4);
+        let key_values = keys().into_iter().map(|key| (key.clone(), key)).collect();
+        execute(&mut shards, 0, 1, Command::multi_put(key_values));
+        execute(&mut shards, 0, 2, Command::multi_get(vec![Vec::from(b"raft"), Vec::from(b"missing")]));
+
+        let report = execute(&mut shards, 1, 3, Command::stats()).stats_response().unwrap();
+        assert_eq!(3, report.shards.len());
+        assert_eq!(8, report.total.puts);
+        assert_eq!(8, report.total.index_size);
+        assert_eq!(2, report.total.gets);
+        assert_eq!(1, report.total.hits);
+        assert_eq!(1, report.total.misses);
+        assert_eq!(report.total.bytes_used, report.shards.iter().map(|stats| stats.bytes_used).sum::<usize>());
+    }
+
+    #[test]
+    fn report_the_queue_depth_of_a_shard() {
+        let mut shards = shards(2, 4);
+        let router = ShardRouter::new(2);
+        let remote = keys().into_iter().find(|key| router.shard_of(key) == 1).unwrap();
+        shards[0].submit(1, Command::get(remote));
+
+        assert_eq!(1, shards[1].queue_depth());
+        assert_eq!(true, shards[1].submit(2, Command::stats()).is_none());
+
+        let report = loop {
+            shards[0].poll();
+            if let Some((_, response)) = shards[1].poll().pop() {
+                break response.stats_response().unwrap();
+            }
+        };
+        assert_eq!(1, report.shards[1].queue_depth);
+        assert_eq!(1, report.total.queue_depth);
+    }
+
+    #[test]
+    fn count_the_values_of_queued_messages_as_buffered_bytes() {
+        let mut shards = shards(2, 4);
+        let router = ShardRouter::new(2);
+        let remote = keys().into_iter().find(|key| router.shard_of(key) == 1).unwrap();
+        let empty = shards[1].buffer_bytes();
+        shards[0].submit(1, Command::put(remote, vec![1; 4096]));
+
+        assert_eq!(true, shards[1].buffer_bytes() >= empty + 4096);
+    }
+
+    #[test]
+    fn record_latencies_on_the_executing_shard() {
+        let mut shards = shards(2, 4);
+        let router = ShardRouter::new(2);
+        let remote = keys().into_iter().find(|key| router.shard_of(key) == 1).unwrap();
+        execute(&mut shards, 0, 1, Command::put(remote, Vec::from(b"consensus")));
+
+        assert_eq!(0, shards[0].metrics().latency_of(CommandType::Put).count());
+        assert_eq!(1, shards[1].metrics().latency_of(CommandType::Put).count());
+    }
+
+    #[test]
+    fn report_the_slow_log_of_every_shard() {
+        let executors = (0..2)
+            .map(|_| CommandExecutor::new(Log::new(LogOptions::new(1024, 256))).with_slow_log(Duration::ZERO, 8))
+            .collect();
+        let mut shards = Shard::all(executors, 4);
+        let router = ShardRouter::new(2);
+        let remote = keys().into_iter().find(|key| router.shard_of(key) == 1).unwrap();
+        execute(&mut shards, 0, 1, Command::put(remote.clone(), Vec::from(b"consensus")));
+
+        let entries = execute(&mut shards, 0, 2, Command::slow_log(1)).slow_log_response().unwrap();
+        assert_eq!(1, entries.len());
+
+        let entries = execute(&mut shards, 0, 3, Command::slow_log(10)).slow_log_response().unwrap();
+        let put = entries.iter().find(|entry| entry.command_type == CommandType::Put).unwrap();
+        assert_eq!(1, put.shard);
+        assert_eq!(remote, put.key_prefix);
+        assert_eq!(true, entries.windows(2).all(|pair| pair[0].timestamp >= pair[1].timestamp));
+    }
+
+    #[test]
+    fn report_the_hot_keys_of_every_shard() {
+        let mut shards = shards(2, 4);
+        let router = ShardRouter::new(2);
+        let remote = keys().into_iter().find(|key| router.shard_of(key) == 1).unwrap();
+        let local = keys().into_iter().find(|key| router.shard_of(key) == 0).unwrap();
+        execute(&mut shards, 0, 1, Command::put(remote.clone(), Vec::from(b"consensus")));
+        for request_id in 2..5 {
+            execute(&mut shards, 0, request_id, Command::get(remote.clone()));
+        }
+        execute(&mut shards, 0, 5, Command::get(local.clone()));
+
+        let hot_keys = execute(&mut shards, 0, 6, Command::hot_keys(2)).hot_keys_response().unwrap();
+        assert_eq!(2, hot_keys.len());
+        assert_eq!((1, remote, 4), (hot_keys[0].shard, hot_keys[0].key.clone(), hot_keys[0].count));
+        assert_eq!((0, local, 1), (hot_keys[1].shard, hot_keys[1].key.clone(), hot_keys[1].count));
+        assert_eq!(4, shards[1].metrics().hottest_key_accesses());
+    }
+
+    #[test]
+    fn complete_a_snapshot_on_an_idle_shard() {
+        let file_name = format!("memcore-worker-idle-snapshot-{}.snap", std::process::id());
+        let log = Log::new(LogOptions::new(512, 64));
+        let executor = CommandExecutor::new(log).with_snapshot_directory(std::env::temp_dir());
+        let mut shards = Shard::all(vec![executor], 4);
+        for (request_id, key) in keys().into_iter().enumerate() {
+            let response = execute(&mut shards, 0, request_id as u64, Command::put(key, Vec::from(b"consensus")));
+            assert_eq!(true, response.put_response());
+        }
+
+        assert_eq!(true, execute(&mut shards, 0, 8, Command::snapshot(file_name.clone().into_bytes())).snapshot_response());
+        assert_eq!(false, shards[0].executor.snapshot_progress().unwrap().is_complete());
+        while !shards[0].executor.snapshot_progress().unwrap().is_complete() {
+            assert_eq!(true, shards[0].poll().is_empty());
+        }
+        assert_eq!(261, shards[0].executor.snapshot_progress().unwrap().bytes_written);
+
+        std::fs::remove_file(std::env::temp_dir().join(format!("{}.shard-0", file_name))).unwrap();
+    }
+
+    #[test]
+    fn snapshot_every_shard_and_restore_the_node() {
+        let name = format!("memcore-worker-node-snapshot-{}", std::process::id());
+        let executors = (0..3)
+            .map(|_| CommandExecutor::new(Log::new(LogOptions::new(1024, 256))))
+            .map(|executor| executor.with_snapshot_directory(std::env::temp_dir()))
+            .collect();
+        let mut shards = Shard::all(executors, 4);
+        for (request_id, key) in keys().into_iter().enumerate() {
+            execute(&mut shards, 0, request_id as u64, Command::put(key, Vec::from(b"consensus")));
+        }
+
+        assert_eq!(true, execute(&mut shards, 1, 8, Command::snapshot(name.clone().into_bytes())).snapshot_response());
+        while shards.iter().any(|shard| !shard.executor.snapshot_progress().unwrap().is_complete()) {
+            shards.iter_mut().for_each(|shard| assert_eq!(true, shard.poll().is_empty()));
+        }
+        let report = execute(&mut shards, 0, 9, Command::stats()).stats_response().unwrap();
+        assert_eq!(261, report.total.snapshot_bytes_written);
+        assert_eq!(report.total.snapshot_bytes_total, report.total.snapshot_bytes_written);
+        assert_eq!(0, report.total.snapshot_failures);
+
+        let router = ShardRouter::new(3);
+        let options = (0..3).map(|_| LogOptions::new(1024, 256)).collect();
+        let shard_of = |key: &[u8]| router.shard_of(key);
+        let logs = Snapshot::restore_node(&std::env::temp_dir(), name.as_bytes(), options, shard_of).unwrap();
+        for key in keys() {
+            assert_eq!(b"consensus", logs[router.shard_of(&key)].try_get(&key).unwrap().unwrap().value());
+        }
+        assert_eq!(8, logs.iter().map(Log::index_size).sum::<usize>());
+        assert_eq!(true, logs.iter().all(|log| log.write_ahead_log_position().is_none()));
+
+        let router = ShardRouter::new(2);
+        let options = (0..2).map(|_| LogOptions::new(1024, 256)).collect();
+        let shard_of = |key: &[u8]| router.shard_of(key);
+        let logs = Snapshot::restore_node(&std::env::temp_dir(), name.as_bytes(), options, shard_of).unwrap();
+        for key in keys() {
+            assert_eq!(b"consensus", logs[router.shard_of(&key)].try_get(&key).unwrap().unwrap().value());
+        }
+
+        for shard in 0..3 {
+            std::fs::remove_file(std::env::temp_dir().join(format!("{}.shard-{}", name, shard))).unwrap();
+        }
+    }
+
+    #[test]
+    fn report_a_snapshot_that_failed_to_be_written() {
+        let directory = std::env::temp_dir().join(format!("memcore-worker-missing-{}", std::process::id()));
+        let executor = CommandExecutor::new(Log::new(LogOptions::new(1024, 256))).with_snapshot_directory(directory);
+        let mut shards = Shard::all(vec![executor], 4);
+
+        assert_eq!(false, execute(&mut shards, 0, 1, Command::snapshot(Vec::from(b"backup"))).snapshot_response());
+        let report = execute(&mut shards, 0, 2, Command::stats()).stats_response().unwrap();
+        assert_eq!(1, report.total.snapshot_failures);
+    }
+
+    fn replicating_shards(number_of_shards: usize) -> Vec<Shard> {
+        replicating_shards_with(number_of_shards, 8, Duration::from_secs(60))
+    }
+
+    fn replicating_shards_with(number_of_shards: usize, capacity: usize, resend_interval: Duration) -> Vec<Shard> {
+        shards(number_of_shards, 4)
+            .into_iter()
+            .map(|shard| shard.with_hot_key_replication(2, capacity, resend_interval))
+            .collect()
+    }
+
+    #[test]
+    fn serve_gets_of_a_hot_key_from_a_local_replica() {
+        let mut shards = replicating_shards(2);
+        let router = ShardRouter::new(2);
+        let remote = keys().into_iter().find(|key| router.shard_of(key) == 1).unwrap();
+        execute(&mut shards, 0, 1, Command::put(remote.clone(), Vec::from(b"consensus")));
+        execute(&mut shards, 0, 2, Command::get(remote.clone()));
+        shards[0].poll();
+
+        let response = shards[0].submit(3, Command::get(remote.clone())).unwrap();
+        assert_eq!(b"consensus", response.get_response().unwrap().unwrap().value());
+        assert_eq!(2, shards[1].executor.hot_keys().accesses_of(&remote));
+    }
+
+    #[test]
+    fn replicate_a_key_again_once_its_replica_was_dropped_for_another() {
+        let mut shards = replicating_shards_with(2, 1, Duration::ZERO);
+        let router = ShardRouter::new(2);
+        let remote: Vec<Vec<u8>> = keys().into_iter().filter(|key| router.shard_of(key) == 1).take(2).collect();
+        for (request_id, key) in remote.iter().enumerate() {
+            execute(&mut shards, 0, request_id as u64, Command::put(key.clone(), Vec::from(b"consensus")));
+            execute(&mut shards, 0, 10 + request_id as u64, Command::get(key.clone()));
+            shards[0].poll();
+        }
+        assert_eq!(true, shards[0].submit(21, Command::get(remote[1].clone())).is_some());
+        assert_eq!(true, shards[0].replication.as_ref().unwrap().peek(&remote[0]).is_none());
+
+        execute(&mut shards, 0, 22, Command::get(remote[0].clone()));
+        shards[0].poll();
+        assert_eq!(true, shards[0].submit(23, Command::get(remote[0].clone())).is_some());
+    }
+
+    #[test]
+    fn invalidate_replicas_given_the_owner_updates_the_key() {
+        let mut shards = replicating_shards(3);
+        let router = ShardRouter::new(3);
+        let remote = keys().into_iter().find(|key| router.shard_of(key) == 1).unwrap();
+        execute(&mut shards, 0, 1, Command::put(remote.clone(), Vec::from(b"consensus")));
+        execute(&mut shards, 0, 2, Command::get(remote.clone()));
+        shards[0].poll();
+        assert_eq!(true, shards[0].submit(3, Command::get(remote.clone())).is_some());
+
+        execute(&mut shards, 2, 4, Command::update(remote.clone(), Vec::from(b"leader")));
+        shards[0].poll();
+
+        let response = execute(&mut shards, 0, 5, Command::get(remote));
+        assert_eq!(b"leader", response.get_response().unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn drop_a_local_replica_given_a_write_through_the_same_shard() {
+        let mut shards = replicating_shards(2);
+        let router = ShardRouter::new(2);
+        let remote = keys().into_iter().find(|key| router.shard_of(key) == 1).unwrap();
+        execute(&mut shards, 0, 1, Command::put(remote.clone(), Vec::from(b"consensus")));
+        execute(&mut shards, 0, 2, Command::get(remote.clone()));
+        shards[0].poll();
+
+        assert_eq!(true, shards[0].submit(3, Command::multi_put(vec![(remote.clone(), Vec::from(b"leader"))])).is_none());
+        assert_eq!(true, shards[0].submit(4, Command::get(remote)).is_none());
+    }
+
+    #[test]
+    fn serve_a_get_from_the_segment_of_the_owning_shard() {
+        let mut shards = shards(2, 4);
+        let router = ShardRouter::new(2);
+        let local = keys().into_iter().find(|key| router.shard_of(key) == 0).unwrap();
+        execute(&mut shards, 0, 1, Command::put(local.clone(), Vec::from(b"consensus")));
+
+        let key_value_ref = shards[0].submit_get(2, local.clone()).unwrap().unwrap().unwrap();
+        assert_eq!(b"consensus", key_value_ref.value());
+        let value_at = key_value_ref.value().as_ptr();
+        assert_eq!(value_at, shards[0].submit_get(3, local.clone()).unwrap().unwrap().unwrap().value().as_ptr());
+        assert_eq!(value_at, shards[0].executor.get_ref(&local).unwrap().unwrap().value().as_ptr());
+        assert_eq!(2, shards[0].executor.stats().hits);
+        assert_eq!(2, shards[0].metrics().latency_of(CommandType::Get).count());
+    }
+
+    #[test]
+    fn serve_a_get_from_a_local_replica_and_forward_the_others() {
+        let mut shards = replicating_shards(2);
+        let router = ShardRouter::new(2);
+        let remote = keys().into_iter().find(|key| router.shard_of(key) == 1).unwrap();
+        execute(&mut shards, 0, 1, Command::put(remote.clone(), Vec::from(b"consensus")));
+
+        assert_eq!(true, shards[0].submit_get(2, remote.clone()).is_none());
+        let response = loop {
+            shards[1].poll();
+            if let Some((request_id, response)) = shards[0].poll().pop() {
+                assert_eq!(2, request_id);
+                break response;
+            }
+        };
+        assert_eq!(b"consensus", response.get_response().unwrap().unwrap().value());
+        execute(&mut shards, 0, 3, Command::get(remote.clone()));
+        shards[0].poll();
+
+        let key_value_ref = shards[0].submit_get(4, remote.clone()).unwrap().unwrap().unwrap();
+        assert_eq!(b"consensus", key_value_ref.value());
+        assert_eq!(2, shards[1].executor.hot_keys().accesses_of(&remote));
+    }
+
+    fn migrating_shards(number_of_shards: usize) -> Vec<Shard> {
+        let executors = (0..number_of_shards)
+            .map(|_| CommandExecutor::new(Log::new(LogOptions::new(1 << 16, 1 << 12))))
+            .collect();
+        Shard::all(executors, 16)
+    }
+
+    fn key_values(prefix: &str, count: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+        (0..count)
+            .map(|index| (format!("{}-{}", prefix, index).into_bytes(), format!("value-{}", index).into_bytes()))
+            .collect()
+    }
+
+    fn assert_readable(shards: &mut [Shard], origin: usize, request_id: RequestId, key_values: &[(Vec<u8>, Vec<u8>)]) {
+        let keys = key_values.iter().map(|(key, _)| key.clone()).collect();
+        let CommandResponse::Multi(responses) = execute(shards, origin, request_id, Command::multi_get(keys)) else {
+            panic!("expected a multi response");
+        };
+        for ((_, value), response) in key_values.iter().zip(responses) {
+            assert_eq!(value.as_slice(), response.get_response().unwrap().unwrap().value());
+        }
+    }
+
+    fn rebalance(shards: &mut [Shard], active: &[usize]) {
+        for (from, to, slots) in shards[0].router().rebalance(active) {
+            shards[from].migrate_slots(slots, to);
+        }
+        while shards.iter().any(Shard::is_migrating) {
+            for shard in shards.iter_mut() {
+                shard.poll();
+            }
+        }
+        for shard in shards.iter_mut() {
+            shard.poll();
+        }
+    }
+
+    #[test]
+    fn migrate_slots_to_remove_and_add_a_shard() {
+        let mut shards = migrating_shards(3);
+        let key_values = key_values("key", 200);
+        execute(&mut shards, 0, 1, Command::multi_put(key_values.clone()));
+
+        rebalance(&mut shards, &[0, 1]);
+        assert_eq!(0, shards[2].executor.stats().index_size);
+        assert_eq!(true, shards.iter().all(|shard| shard.router().slots_of(2).is_empty()));
+        assert_readable(&mut shards, 2, 2, &key_values);
+
+        rebalance(&mut shards, &[0, 1, 2]);
+        assert_eq!(true, shards[2].executor.stats().index_size > 0);
+        assert_eq!(200, shards.iter().map(|shard| shard.executor.stats().index_size).sum::<usize>());
+        assert_readable(&mut shards, 1, 3, &key_values);
+    }
+
+    #[test]
+    fn serve_keys_of_migrating_slots_while_they_move() {
+        let mut shards = migrating_shards(2);
+        let key_values = key_values("key", 200);
+        execute(&mut shards, 0, 1, Command::multi_put(key_values.clone()));
+
+        let slots = shards[1].router().slots_of(1);
+        shards[1].migrate_slots(slots, 0);
+        let mut written = Vec::new();
+        let mut request_id = 2;
+        while shards[1].is_migrating() {
+            let origin = request_id as usize % 2;
+            let (key, value) = key_values[request_id as usize % key_values.len()].clone();
+            let response = execute(&mut shards, origin, request_id, Command::get(key));
+            assert_eq!(value, response.get_response().unwrap().unwrap().value());
+
+            let (key, value) = (format!("new-{}", request_id).into_bytes(), Vec::from(b"written"));
+            assert_eq!(true, execute(&mut shards, origin, request_id + 1, Command::put(key.clone(), value.clone())).put_response());
+            written.push((key, value));
+            request_id += 2;
+        }
+        shards[0].poll();
+
+        assert_eq!(0, shards[1].executor.stats().index_size);
+        assert_readable(&mut shards, 1, request_id, &key_values);
+        assert_readable(&mut shards, 1, request_id + 1, &written);
+    }
+
+    #[test]
+    fn keep_the_client_flags_of_replicated_and_migrated_keys() {
+        let mut shards: Vec<Shard> = migrating_shards(2)
+            .into_iter()
+            .map(|shard| shard.with_hot_key_replication(2, 8, Duration::from_secs(60)))
+            .collect();
+        let remote = keys().into_iter().find(|key| shards[0].router().shard_of(key) == 1).unwrap();
+        execute(&mut shards, 0, 1, Command::put(remote.clone(), Vec::from(b"consensus")).with_flags(42));
+        execute(&mut shards, 0, 2, Command::get(remote.clone()));
+        shards[0].poll();
+
+        let replica = shards[0].submit(3, Command::get(remote.clone())).unwrap();
+        assert_eq!(42, replica.get_response().unwrap().unwrap().flags());
+
+        rebalance(&mut shards, &[0]);
+        let migrated = execute(&mut shards, 1, 4, Command::get(remote));
+        assert_eq!(42, migrated.get_response().unwrap().unwrap().flags());
+    }
+
+    #[test]
+    fn keep_the_expiry_of_migrated_keys_and_never_replicate_them() {
+        let mut shards: Vec<Shard> = migrating_shards(2)
+            .into_iter()
+            .map(|shard| shard.with_hot_key_replication(1, 8, Duration::from_secs(60)))
+            .collect();
+        let remote = keys().into_iter().find(|key| shards[0].router().shard_of(key) == 1).unwrap();
+        let put = Command::put(remote.clone(), Vec::from(b"alice")).with_ttl(Duration::from_secs(60));
+        let expires_at = put.expires_at;
+        execute(&mut shards, 0, 1, put);
+        execute(&mut shards, 0, 2, Command::get(remote.clone()));
+        shards[0].poll();
+        assert_eq!(0, shards[0].replication.as_ref().unwrap().len());
+
+        rebalance(&mut shards, &[0]);
+        assert_eq!(expires_at, shards[0].executor.expires_at(&remote));
+    }
+
+    #[test]
+    fn keep_the_version_of_migrated_keys() {
+        let mut shards = migrating_shards(2);
+        let remote = keys().into_iter().find(|key| shards[0].router().shard_of(key) == 1).unwrap();
+        execute(&mut shards, 0, 1, Command::put(remote.clone(), Vec::from(b"consensus")));
+        execute(&mut shards, 0, 2, Command::update(remote.clone(), Vec::from(b"leader")));
+        let response = execute(&mut shards, 0, 3, Command::get_with_version(remote.clone()));
+        let version = response.get_with_version_response().unwrap().unwrap().1;
+
+        rebalance(&mut shards, &[0]);
+        assert_eq!(0, shards[0].router().shard_of(&remote));
+        let swap = Command::compare_and_swap(remote.clone(), Vec::from(b"follower"), version);
+        let swapped = execute(&mut shards, 1, 4, swap).compare_and_swap_response().unwrap();
+        assert_eq!(CompareAndSwapResult::Swapped(version + 1), swapped);
+    }
+
+    #[test]
+    fn keep_a_write_that_reaches_the_target_before_the_migrated_entries() {
+        let mut shards = migrating_shards(3);
+        let key = keys().into_iter().find(|key| shards[0].router().shard_of(key) == 2).unwrap();
+        execute(&mut shards, 2, 1, Command::put(key.clone(), Vec::from(b"old")));
+
+        shards[2].migrate_slots(vec![slot_of(&key)], 1);
+        shards[1].poll();
+        shards[2].poll();
+        shards[0].poll();
+        assert_eq!(1, shards[0].router().shard_of(&key));
+
+        assert_eq!(true, execute(&mut shards, 0, 2, Command::put(key.clone(), Vec::from(b"new"))).put_response());
+        for shard in shards.iter_mut() {
+            shard.poll();
+        }
+        let response = execute(&mut shards, 0, 3, Command::get(key));
+        assert_eq!(b"new", response.get_response().unwrap().unwrap().value());
+    }
+
+    #[test]
+    fn add_a_shard_and_move_slots_to_it() {
+        let mut shards = migrating_shards(2);
+        let key_values = key_values("key", 200);
+        execute(&mut shards, 0, 1, Command::multi_put(key_values.clone()));
+
+        let executor = CommandExecutor::new(Log::new(LogOptions::new(1 << 16, 1 << 12)));
+        let added = shards[0].add_shard(executor, 16);
+        shards.push(added);
+        for shard in shards.iter_mut() {
+            shard.poll();
+        }
+        assert_eq!(true, shards.iter().all(|shard| shard.router().number_of_shards() == 3));
+
+        rebalance(&mut shards, &[0, 1, 2]);
+        assert_eq!(true, shards[2].executor.stats().index_size > 0);
+        assert_eq!(200, shards.iter().map(|shard| shard.executor.stats().index_size).sum::<usize>());
+        assert_readable(&mut shards, 2, 2, &key_values);
+        assert_readable(&mut shards, 1, 3, &key_values);
+    }
+
+    #[test]
+    fn reject_a_transaction_split_by_a_migrating_slot() {
+        let mut shards = migrating_shards(2);
+        let cart = Vec::from(b"{user:42}:cart");
+        let owner = shards[0].router().shard_of(&cart);
+        execute(&mut shards, owner, 1, Command::put(cart.clone(), Vec::from(b"book")));
+
+        shards[owner].migrate_slots(vec![slot_of(&cart)], 1 - owner);
+        let response = shards[owner].submit(2, Command::transaction(vec![
+            Command::put(cart, Vec::from(b"pen")),
+            Command::put(Vec::from(b"{user:42}:session"), Vec::from(b"active")),
+        ])).unwrap();
+        assert_eq!(Err(TransactionError::Migrating), response.transaction_response().map(|responses| responses.len()));
+    }
+
+    #[test]
+    fn reject_writes_forwarded_to_a_shard_over_its_memory_budget() {
+        let budgets = MemoryBudget::new(2 * (Log::new(LogOptions::new(1024, 256)).memory_usage() + 64), EvictionPolicy::Reject).split(2);
+        let executors = budgets
+            .into_iter()
+            .map(|budget| CommandExecutor::new(Log::new(LogOptions::new(1024, 256))).with_memory_budget(budget))
+            .collect();
+        let mut shards = Shard::all(executors, 4);
+        let router = ShardRouter::new(2);
+
+        let remote: Vec<Vec<u8>> = (0..).map(|index| format!("key-{}", index).into_bytes())
+            .filter(|key| router.shard_of(key) == 1)
+            .take(4)
+            .collect();
+        let responses: Vec<CommandResponse> = remote.into_iter().enumerate()
+            .map(|(request_id, key)| execute(&mut shards, 0, request_id as RequestId, Command::put(key, Vec::from(b"value"))))
+            .collect();
+        assert_eq!(true, responses[0].put_response());
+        assert_eq!(true, responses.last().unwrap().is_out_of_memory());
+
+        let report = execute(&mut shards, 0, 4, Command::stats()).stats_response().unwrap();
+        assert_eq!(0, report.shards[0].out_of_memory);
+        assert_eq!(true, report.shards[1].out_of_memory > 0);
+    }
+}
\ No newline at end of file